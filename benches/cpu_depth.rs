@@ -0,0 +1,82 @@
+//! Time the CPU depth processor on a single thread and on the global rayon pool.
+//!
+//! Run with `cargo bench --bench cpu_depth`.
+
+use std::{
+    error::Error,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use kinect_one::{
+    data::{IrParams, P0Tables},
+    processor::{
+        depth::{CpuDepthProcessor, DepthPacket},
+        ProcessorTrait,
+    },
+    DEPTH_PACKET_SIZE,
+};
+use rayon::ThreadPoolBuilder;
+use tokio::runtime::Builder;
+
+const ITERATIONS: u32 = 20;
+
+/// Packet of pseudo-random bytes, the same on every run.
+fn packet() -> DepthPacket {
+    let mut state = 0x2545F491u32;
+
+    DepthPacket {
+        sequence: 0,
+        timestamp: 0,
+        buffer: (0..DEPTH_PACKET_SIZE)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 24) as u8
+            })
+            .collect(),
+    }
+}
+
+/// Calibration without distortion, the principal point at the center of the sensor.
+fn ir_params() -> IrParams {
+    IrParams {
+        fx: 365.0,
+        fy: 365.0,
+        cx: 256.0,
+        cy: 212.0,
+        ..Default::default()
+    }
+}
+
+fn time(processor: &CpuDepthProcessor, packet: &DepthPacket) -> Result<Duration, Box<dyn Error>> {
+    let runtime = Builder::new_current_thread().build()?;
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        runtime.block_on(processor.process(packet.clone()))?;
+    }
+
+    Ok(start.elapsed() / ITERATIONS)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut processor = CpuDepthProcessor::from_calibration(&ir_params(), &P0Tables::default())?;
+    let packet = packet();
+
+    let parallel = time(&processor, &packet)?;
+
+    processor.set_thread_pool(Some(Arc::new(
+        ThreadPoolBuilder::new().num_threads(1).build()?,
+    )));
+
+    let serial = time(&processor, &packet)?;
+
+    println!("1 thread: {serial:?} per packet");
+    println!(
+        "{} threads: {parallel:?} per packet, {:.1}x",
+        rayon::current_num_threads(),
+        serial.as_secs_f64() / parallel.as_secs_f64()
+    );
+
+    Ok(())
+}
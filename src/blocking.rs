@@ -0,0 +1,401 @@
+//! Synchronous wrappers around the async device API.
+//!
+//! Each [`Device`] owns a small single threaded runtime, used to drive the async operations to completion.
+//! Mixing blocking and async calls on the same device is unsupported, use [`Device::block_on`]
+//! to run an async operation, or [`Device::into_inner`] to switch to the async API.
+
+use std::{
+    fmt::{self, Debug},
+    future::Future,
+    time::Duration,
+};
+
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    config::{ColorSettingCommandType, LedId, LedSettings, PacketParams, WhiteBalanceMode},
+    data::{ColorParams, FirwareVersion, HardwareInfo, IrParams, P0Tables},
+    device::{Closed, DeviceId, Opened},
+    packet::{ColorPacket, DepthPacket},
+    DeviceEnumerator, DeviceInfo, DeviceStats, Error, Streams, UsbErrorCounters, UsbSpeed,
+};
+
+#[cfg(feature = "cpu_depth")]
+use {
+    crate::record::{ExposureLock, RecordFormat},
+    std::path::Path,
+};
+
+/// Blocking counterpart of an opened [`crate::Device`].
+pub struct Device {
+    runtime: Runtime,
+    inner: crate::Device<Opened>,
+}
+
+impl Device {
+    /// Open the device.
+    pub fn open(device: crate::Device<Closed>, reset: bool) -> Result<Self, Error> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let inner = runtime.block_on(device.open(reset))?;
+
+        Ok(Self { runtime, inner })
+    }
+
+    /// Open the first device.
+    pub fn open_default(reset: bool) -> Result<Self, Error> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let inner = runtime.block_on(DeviceEnumerator::open_default(reset))?;
+
+        Ok(Self { runtime, inner })
+    }
+
+    /// Open the device with the given serial number.
+    pub fn open_by_serial(serial: &str, reset: bool) -> Result<Self, Error> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let inner = runtime.block_on(DeviceEnumerator::open_by_serial(serial, reset))?;
+
+        Ok(Self { runtime, inner })
+    }
+
+    /// Run any async operation of the wrapped device to completion.
+    pub fn block_on<'a, T, F: Future<Output = T> + 'a>(
+        &'a mut self,
+        operation: impl FnOnce(&'a mut crate::Device<Opened>) -> F,
+    ) -> T {
+        self.runtime.block_on(operation(&mut self.inner))
+    }
+
+    /// See [`crate::Device::connection_speed`].
+    pub fn connection_speed(&self) -> Option<UsbSpeed> {
+        self.inner.connection_speed()
+    }
+
+    pub fn running(&self) -> bool {
+        self.inner.running()
+    }
+
+    /// See [`crate::Device::last_color_sequence`].
+    pub fn last_color_sequence(&self) -> Option<u32> {
+        self.inner.last_color_sequence()
+    }
+
+    /// See [`crate::Device::last_depth_sequence`].
+    pub fn last_depth_sequence(&self) -> Option<u32> {
+        self.inner.last_depth_sequence()
+    }
+
+    /// See [`crate::Device::active_streams`].
+    pub fn active_streams(&self) -> Streams {
+        self.inner.active_streams()
+    }
+
+    /// See [`crate::Device::stats`].
+    pub fn stats(&self) -> DeviceStats {
+        self.inner.stats()
+    }
+
+    /// See [`crate::Device::reset_stats`].
+    pub fn reset_stats(&mut self) {
+        self.inner.reset_stats()
+    }
+
+    /// See [`crate::Device::usb_error_counters`].
+    pub fn usb_error_counters(&self) -> UsbErrorCounters {
+        self.inner.usb_error_counters()
+    }
+
+    /// See [`crate::Device::reset_usb_error_counters`].
+    pub fn reset_usb_error_counters(&mut self) {
+        self.inner.reset_usb_error_counters()
+    }
+
+    /// Start data processing with both color and depth streams.
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.start())
+    }
+
+    /// See [`crate::Device::start_depth_only`].
+    pub fn start_depth_only(&mut self) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.start_depth_only())
+    }
+
+    pub fn poll_color_packet(&mut self) -> Result<Option<ColorPacket>, Error> {
+        self.runtime.block_on(self.inner.poll_color_packet())
+    }
+
+    pub fn poll_depth_packet(&mut self) -> Result<Option<DepthPacket>, Error> {
+        self.runtime.block_on(self.inner.poll_depth_packet())
+    }
+
+    pub fn next_color_packet(&mut self) -> Result<ColorPacket, Error> {
+        self.runtime.block_on(self.inner.next_color_packet())
+    }
+
+    pub fn next_depth_packet(&mut self) -> Result<DepthPacket, Error> {
+        self.runtime.block_on(self.inner.next_depth_packet())
+    }
+
+    /// See [`crate::Device::set_color_timeout`].
+    pub fn set_color_timeout(&mut self, timeout: Option<Duration>) {
+        self.inner.set_color_timeout(timeout)
+    }
+
+    /// See [`crate::Device::set_depth_timeout`].
+    pub fn set_depth_timeout(&mut self, timeout: Option<Duration>) {
+        self.inner.set_depth_timeout(timeout)
+    }
+
+    /// See [`crate::Device::cancel_pending`].
+    pub fn cancel_pending(&mut self) {
+        self.runtime.block_on(self.inner.cancel_pending())
+    }
+
+    /// See [`crate::Device::record_to_dir`].
+    #[cfg(feature = "cpu_depth")]
+    pub fn record_to_dir(
+        &mut self,
+        dir: &Path,
+        frames: usize,
+        format: RecordFormat,
+    ) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.record_to_dir(dir, frames, format))
+    }
+
+    /// See [`crate::Device::record_to_dir_locked`].
+    #[cfg(feature = "cpu_depth")]
+    pub fn record_to_dir_locked(
+        &mut self,
+        dir: &Path,
+        frames: usize,
+        format: RecordFormat,
+        lock: ExposureLock,
+    ) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.record_to_dir_locked(dir, frames, format, lock))
+    }
+
+    pub fn get_hardware_info(&mut self) -> Result<HardwareInfo, Error> {
+        self.runtime.block_on(self.inner.get_hardware_info())
+    }
+
+    pub fn get_firware_versions(&mut self) -> Result<Vec<FirwareVersion>, Error> {
+        self.runtime.block_on(self.inner.get_firware_versions())
+    }
+
+    pub fn get_serial_number(&mut self) -> Result<String, Error> {
+        self.runtime.block_on(self.inner.get_serial_number())
+    }
+
+    /// Get color parameters.
+    pub fn get_color_params(&self) -> &ColorParams {
+        self.inner.get_color_params()
+    }
+
+    /// Get depth parameters.
+    pub fn get_ir_params(&self) -> &IrParams {
+        self.inner.get_ir_params()
+    }
+
+    /// See [`crate::Device::packet_params`].
+    pub fn packet_params(&self) -> PacketParams {
+        self.inner.packet_params()
+    }
+
+    /// See [`crate::Device::set_packet_params`].
+    pub fn set_packet_params(&mut self, packet_params: PacketParams) -> Result<(), Error> {
+        self.inner.set_packet_params(packet_params)
+    }
+
+    /// See [`crate::Device::max_iso_packet_size`].
+    pub fn max_iso_packet_size(&self) -> u16 {
+        self.inner.max_iso_packet_size()
+    }
+
+    /// Get p0 tables.
+    pub fn get_p0_tables(&self) -> &P0Tables {
+        self.inner.get_p0_tables()
+    }
+
+    /// See [`crate::Device::set_color_auto_exposure`].
+    pub fn set_color_auto_exposure(&mut self, exposure_compensation: f32) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.set_color_auto_exposure(exposure_compensation))
+    }
+
+    /// See [`crate::Device::set_color_semi_auto_exposure`].
+    pub fn set_color_semi_auto_exposure(
+        &mut self,
+        pseudo_exposure_time: Duration,
+    ) -> Result<(), Error> {
+        self.runtime.block_on(
+            self.inner
+                .set_color_semi_auto_exposure(pseudo_exposure_time),
+        )
+    }
+
+    /// See [`crate::Device::set_color_manual_exposure`].
+    pub fn set_color_manual_exposure(
+        &mut self,
+        integration_time: Duration,
+        analog_gain: f32,
+    ) -> Result<(), Error> {
+        self.runtime.block_on(
+            self.inner
+                .set_color_manual_exposure(integration_time, analog_gain),
+        )
+    }
+
+    /// See [`crate::Device::lock_color_exposure`].
+    pub fn lock_color_exposure(&mut self) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.lock_color_exposure())
+    }
+
+    /// See [`crate::Device::set_color_auto_white_balance`].
+    pub fn set_color_auto_white_balance(&mut self) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.set_color_auto_white_balance())
+    }
+
+    /// See [`crate::Device::set_color_manual_white_balance`].
+    pub fn set_color_manual_white_balance(
+        &mut self,
+        red_gain: f32,
+        green_gain: f32,
+        blue_gain: f32,
+    ) -> Result<(), Error> {
+        self.runtime.block_on(
+            self.inner
+                .set_color_manual_white_balance(red_gain, green_gain, blue_gain),
+        )
+    }
+
+    /// See [`crate::Device::get_color_white_balance_mode`].
+    pub fn get_color_white_balance_mode(&mut self) -> Result<WhiteBalanceMode, Error> {
+        self.runtime
+            .block_on(self.inner.get_color_white_balance_mode())
+    }
+
+    /// See [`crate::Device::set_color_frame_rate`].
+    pub fn set_color_frame_rate(&mut self, fps: u32) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.set_color_frame_rate(fps))
+    }
+
+    /// See [`crate::Device::get_color_frame_rate`].
+    pub fn get_color_frame_rate(&mut self) -> Result<u32, Error> {
+        self.runtime.block_on(self.inner.get_color_frame_rate())
+    }
+
+    /// See [`crate::Device::set_auto_color_settings`].
+    pub fn set_auto_color_settings(&mut self, enabled: bool) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.set_auto_color_settings(enabled))
+    }
+
+    /// See [`crate::Device::get_auto_color_settings`].
+    pub fn get_auto_color_settings(&mut self) -> Result<bool, Error> {
+        self.runtime.block_on(self.inner.get_auto_color_settings())
+    }
+
+    /// See [`crate::Device::get_color_exposure_time_ms`].
+    pub fn get_color_exposure_time_ms(&mut self) -> Result<f32, Error> {
+        self.runtime
+            .block_on(self.inner.get_color_exposure_time_ms())
+    }
+
+    /// See [`crate::Device::get_color_analog_gain`].
+    pub fn get_color_analog_gain(&mut self) -> Result<f32, Error> {
+        self.runtime.block_on(self.inner.get_color_analog_gain())
+    }
+
+    /// See [`crate::Device::get_color_integration_time`].
+    pub fn get_color_integration_time(&mut self) -> Result<Duration, Error> {
+        self.runtime
+            .block_on(self.inner.get_color_integration_time())
+    }
+
+    /// See [`crate::Device::get_color_float_setting`].
+    pub fn get_color_float_setting(
+        &mut self,
+        command: ColorSettingCommandType,
+    ) -> Result<f32, Error> {
+        self.runtime
+            .block_on(self.inner.get_color_float_setting(command))
+    }
+
+    /// Set an individual setting value of the color camera.
+    pub fn set_color_setting(
+        &mut self,
+        command: ColorSettingCommandType,
+        value: u32,
+    ) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.set_color_setting(command, value))
+    }
+
+    /// get an individual setting value of the color camera.
+    pub fn get_color_setting(&mut self, command: ColorSettingCommandType) -> Result<u32, Error> {
+        self.runtime.block_on(self.inner.get_color_setting(command))
+    }
+
+    /// See [`crate::Device::get_color_settings`].
+    pub fn get_color_settings(
+        &mut self,
+        commands: &[ColorSettingCommandType],
+    ) -> Result<Vec<u32>, Error> {
+        self.runtime
+            .block_on(self.inner.get_color_settings(commands))
+    }
+
+    /// See [`crate::Device::set_color_settings`].
+    pub fn set_color_settings(
+        &mut self,
+        settings: &[(ColorSettingCommandType, u32)],
+    ) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.set_color_settings(settings))
+    }
+
+    /// Set the settings of a Kinect LED.
+    pub fn set_led_status(&mut self, led_settings: LedSettings) -> Result<(), Error> {
+        self.runtime
+            .block_on(self.inner.set_led_status(led_settings))
+    }
+
+    /// See [`crate::Device::set_led_pulse`].
+    pub fn set_led_pulse(&mut self, id: LedId, period: Duration) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.set_led_pulse(id, period))
+    }
+
+    /// See [`crate::Device::set_led_off`].
+    pub fn set_led_off(&mut self, id: LedId) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.set_led_off(id))
+    }
+
+    /// Stop data processing.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        self.runtime.block_on(self.inner.stop())
+    }
+
+    /// Shut down the device.
+    pub fn close(self) -> Result<crate::Device<Closed>, Error> {
+        self.runtime.block_on(self.inner.close())
+    }
+
+    /// Get back the async device, dropping the internal runtime.
+    pub fn into_inner(self) -> crate::Device<Opened> {
+        self.inner
+    }
+}
+
+impl DeviceInfo for Device {
+    fn id(&self) -> DeviceId {
+        self.inner.id()
+    }
+}
+
+impl Debug for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
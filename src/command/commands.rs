@@ -1,7 +1,8 @@
 use crate::{config::LedSettings, settings::ColorSettingCommandType};
 
 use super::{
-    ColorParamsResponse, ColorSettingResponse, Command, DepthParamsResponse, P0TablesResponse,
+    ColorParamsResponse, ColorSettingResponse, ColorSettingStatus, ColorSettingsResponseHeader,
+    Command, DepthParamsResponse, P0TablesResponse,
 };
 
 // Kinect commands id
@@ -12,7 +13,8 @@ const KINECT_CMD_READ_STATUS: u32 = 0x16;
 const KINECT_CMD_READ_DATA_PAGE: u32 = 0x22;
 
 const KINECT_CMD_SET_STREAMING: u32 = 0x2b;
-const KINECT_CMD_SET_MODE: u32 = 0x4b;
+// Named "set mode" in libfreenect2, its parameters are actually a LED state
+const KINECT_CMD_SET_LED: u32 = 0x4b;
 
 const KINECT_CMD_COLOR_SETTING: u32 = 0x3e;
 
@@ -24,6 +26,13 @@ const P0_TABLES_RESPONSE_SIZE: u32 = size_of::<P0TablesResponse>() as u32;
 const DEPTH_PARAMS_RESPONSE_SIZE: u32 = size_of::<DepthParamsResponse>() as u32;
 const COLOR_PARAMS_RESPONSE_SIZE: u32 = size_of::<ColorParamsResponse>() as u32;
 const COLOR_SETTING_RESPONSE_SIZE: u32 = size_of::<ColorSettingResponse>() as u32;
+const COLOR_SETTINGS_RESPONSE_SIZE: u32 = (size_of::<ColorSettingsResponseHeader>()
+    + COLOR_SETTING_BATCH_SIZE * size_of::<ColorSettingStatus>())
+    as u32;
+
+// Maximum number of color settings sent in a single command
+pub const COLOR_SETTING_BATCH_SIZE: usize = 8;
+const COLOR_SETTINGS_NPARAM: usize = 2 + 2 * COLOR_SETTING_BATCH_SIZE;
 
 pub fn read_firware_versions_command() -> Command<KINECT_CMD_READ_FIRMWARE_VERSIONS, 0x200, 0x200, 0>
 {
@@ -106,10 +115,11 @@ pub fn shutdown_command() -> Command<KINECT_CMD_SHUTDOWN, 0, 0, 0> {
     }
 }
 
-pub fn set_mode_command(enabled: bool, mode: u32) -> Command<KINECT_CMD_SET_MODE, 0, 0, 4> {
+// Sequenced variant of the LED command, sent by the official driver while starting and stopping.
+pub fn led_state_command(led_settings: LedSettings) -> Command<KINECT_CMD_SET_LED, 0, 0, 4> {
     Command {
         has_sequence: true,
-        parameters: [enabled as u32, mode, 0, 0],
+        parameters: led_parameters(led_settings),
     }
 }
 
@@ -124,16 +134,85 @@ pub fn color_setting_command(
     }
 }
 
-pub fn led_setting_command(led_settings: LedSettings) -> Command<KINECT_CMD_SET_MODE, 0, 0, 4> {
+// Unused slots of the batch are left zeroed, only the first `settings.len()` are read by the device.
+pub fn color_settings_command(
+    settings: &[(ColorSettingCommandType, u32)],
+) -> Command<
+    KINECT_CMD_COLOR_SETTING,
+    COLOR_SETTINGS_RESPONSE_SIZE,
+    COLOR_SETTING_RESPONSE_SIZE,
+    COLOR_SETTINGS_NPARAM,
+> {
+    let settings = &settings[..settings.len().min(COLOR_SETTING_BATCH_SIZE)];
+    let mut parameters = [0; COLOR_SETTINGS_NPARAM];
+
+    parameters[0] = settings.len() as u32;
+
+    for (index, (command, value)) in settings.iter().enumerate() {
+        parameters[2 + index * 2] = *command as u32;
+        parameters[3 + index * 2] = *value;
+    }
+
     Command {
         has_sequence: false,
-        parameters: [
-            (led_settings.id() as u16 as u32)
-                + (led_settings.mode() as u16 as u32).overflowing_shl(16).0,
-            (led_settings.start_level() as u32)
-                + (led_settings.stop_level() as u32).overflowing_shl(16).0,
-            led_settings.interval().as_millis() as u32,
-            0,
-        ],
+        parameters,
+    }
+}
+
+pub fn led_setting_command(led_settings: LedSettings) -> Command<KINECT_CMD_SET_LED, 0, 0, 4> {
+    Command {
+        has_sequence: false,
+        parameters: led_parameters(led_settings),
+    }
+}
+
+fn led_parameters(led_settings: LedSettings) -> [u32; 4] {
+    [
+        (led_settings.id() as u16 as u32)
+            + (led_settings.mode() as u16 as u32).overflowing_shl(16).0,
+        (led_settings.start_level() as u32)
+            + (led_settings.stop_level() as u32).overflowing_shl(16).0,
+        led_settings.interval().as_millis() as u32,
+        0,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_settings_encoding() {
+        let settings = (0..COLOR_SETTING_BATCH_SIZE as u32 + 1)
+            .map(|value| (ColorSettingCommandType::SetAcs, value))
+            .collect::<Vec<_>>();
+        let command = color_settings_command(&settings[..3]);
+
+        assert!(!command.has_sequence);
+        // count, then the sequence slot left at zero like the single setting command
+        assert_eq!(command.parameters[..2], [3, 0]);
+        assert_eq!(
+            command.parameters[2..8],
+            [
+                ColorSettingCommandType::SetAcs as u32,
+                0,
+                ColorSettingCommandType::SetAcs as u32,
+                1,
+                ColorSettingCommandType::SetAcs as u32,
+                2
+            ]
+        );
+        assert!(command.parameters[8..]
+            .iter()
+            .all(|parameter| *parameter == 0));
+
+        // longer batches are truncated, the caller splits them
+        let command = color_settings_command(&settings);
+
+        assert_eq!(command.parameters[0], COLOR_SETTING_BATCH_SIZE as u32);
+        assert_eq!(
+            command.parameters[COLOR_SETTINGS_NPARAM - 1],
+            COLOR_SETTING_BATCH_SIZE as u32 - 1
+        );
     }
 }
@@ -1,9 +1,11 @@
 mod commands;
 mod response;
 
+use std::{io, ops::Add};
+
 pub use commands::*;
 use nusb::{
-    transfer::{Bulk, In, Out},
+    transfer::{Bulk, In, Out, TransferError},
     Interface,
 };
 pub use response::*;
@@ -14,12 +16,61 @@ use crate::{Error, FromBuffer, USB_TIMEOUT};
 const COMPLETE_RESPONSE_LENGTH: u32 = 16;
 const COMPLETE_RESPONSE_MAGIC: u32 = 0x0a6fe000;
 
+/// Cumulative USB error counts of the commands and streams, to notice a degrading cable or hub.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UsbErrorCounters {
+    pub stalls: u64,
+    pub timeouts: u64,
+    /// Command responses shorter than expected.
+    pub short_reads: u64,
+    /// Any other USB transfer error.
+    pub other: u64,
+}
+
+impl UsbErrorCounters {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn record_transfer_error(&mut self, error: &TransferError) {
+        match error {
+            TransferError::Stall => self.stalls += 1,
+            _ => self.other += 1,
+        }
+    }
+
+    /// Count the USB errors, protocol errors aren't counted.
+    pub(crate) fn record(&mut self, error: &Error) {
+        match error {
+            Error::Io(error) if error.kind() == io::ErrorKind::TimedOut => self.timeouts += 1,
+            Error::Io(_) | Error::Usb(_) => self.other += 1,
+            Error::UsbTransfer(error) => self.record_transfer_error(error),
+            Error::Receive(..) => self.short_reads += 1,
+            _ => {}
+        }
+    }
+}
+
+impl Add for UsbErrorCounters {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            stalls: self.stalls + other.stalls,
+            timeouts: self.timeouts + other.timeouts,
+            short_reads: self.short_reads + other.short_reads,
+            other: self.other + other.other,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct CommandTransaction {
     in_endpoint: u8,
     out_endpoint: u8,
     interface: Interface,
     sequence: u32,
+    error_counters: UsbErrorCounters,
 }
 
 impl CommandTransaction {
@@ -29,9 +80,18 @@ impl CommandTransaction {
             out_endpoint,
             interface,
             sequence: 0,
+            error_counters: UsbErrorCounters::default(),
         }
     }
 
+    pub fn error_counters(&self) -> &UsbErrorCounters {
+        &self.error_counters
+    }
+
+    pub fn reset_error_counters(&mut self) {
+        self.error_counters.reset();
+    }
+
     pub async fn execute<
         const COMMAND_ID: u32,
         const MAX_RESPONSE_LENGTH: u32,
@@ -40,6 +100,24 @@ impl CommandTransaction {
     >(
         &mut self,
         command: Command<COMMAND_ID, MAX_RESPONSE_LENGTH, MIN_RESPONSE_LENGTH, NPARAM>,
+    ) -> Result<Vec<u8>, Error> {
+        let result = self.transact(command).await;
+
+        if let Err(error) = &result {
+            self.error_counters.record(error);
+        }
+
+        result
+    }
+
+    async fn transact<
+        const COMMAND_ID: u32,
+        const MAX_RESPONSE_LENGTH: u32,
+        const MIN_RESPONSE_LENGTH: u32,
+        const NPARAM: usize,
+    >(
+        &mut self,
+        command: Command<COMMAND_ID, MAX_RESPONSE_LENGTH, MIN_RESPONSE_LENGTH, NPARAM>,
     ) -> Result<Vec<u8>, Error> {
         let sequence = self.send(&command).await?;
         let mut result = Vec::new();
@@ -130,16 +130,44 @@ pub struct FirmwareVersionResponse {
 
 impl ReadUnaligned for FirmwareVersionResponse {}
 
+// The layout of the hardware info reply isn't documented, libfreenect2 never parses it.
+#[repr(C, packed)]
+pub struct HardwareInfoResponse {
+    pub words: [u32; 23],
+}
+
+impl ReadUnaligned for HardwareInfoResponse {}
+
 // Color camera settings reply for a single setting change.
 // Equivalent of NUISENSOR_RGB_CHANGE_STREAM_SETTING_REPLY in NuiSensorLib.h
 #[repr(C, packed)]
 pub struct ColorSettingResponse {
     _num_status: u32,
     _command_list_status: u32,
-    // Result of the first command -- we only send one at a time for now.
+    // Result of the first command -- batches are parsed with ColorSettingsResponseHeader.
     // Equivalent of a fixed-length array of NUISENSOR_RGB_CHANGE_STREAM_SETTING_REPLY_STATUS in NuiSensorLib.h
     _status: u32,
     pub data: u32,
 }
 
 impl ReadUnaligned for ColorSettingResponse {}
+
+// Color camera settings reply header for a batch of setting changes,
+// followed by `num_status` ColorSettingStatus.
+#[repr(C, packed)]
+pub struct ColorSettingsResponseHeader {
+    pub num_status: u32,
+    _command_list_status: u32,
+}
+
+impl ReadUnaligned for ColorSettingsResponseHeader {}
+
+// Equivalent of NUISENSOR_RGB_CHANGE_STREAM_SETTING_REPLY_STATUS in NuiSensorLib.h
+#[repr(C, packed)]
+pub struct ColorSettingStatus {
+    // Zero on success
+    pub status: u32,
+    pub data: u32,
+}
+
+impl ReadUnaligned for ColorSettingStatus {}
@@ -2,15 +2,32 @@ use std::{fmt, ptr::read_unaligned};
 
 use crate::{
     command::{
-        ColorParamsResponse, DepthParamsResponse, FirmwareVersionResponse, P0TablesResponse,
+        ColorParamsResponse, DepthParamsResponse, FirmwareVersionResponse, HardwareInfoResponse,
+        P0TablesResponse,
     },
-    Error, ReadUnaligned, DEPTH_SIZE,
+    Error, ReadUnaligned, DEPTH_HEIGHT, DEPTH_SIZE, DEPTH_WIDTH,
 };
 
+/// Row major 3x3 matrix.
+pub type Matrix3 = [[f32; 3]; 3];
+/// Row major 3x4 matrix.
+pub type Matrix3x4 = [[f32; 4]; 3];
+
+/// Intrinsic camera matrix K.
+const fn camera_matrix(fx: f32, fy: f32, cx: f32, cy: f32) -> Matrix3 {
+    [[fx, 0.0, cx], [0.0, fy, cy], [0.0, 0.0, 1.0]]
+}
+
+/// Projection matrix P of a camera without rectification nor baseline, `[K | 0]`.
+const fn projection_matrix(fx: f32, fy: f32, cx: f32, cy: f32) -> Matrix3x4 {
+    [[fx, 0.0, cx, 0.0], [0.0, fy, cy, 0.0], [0.0, 0.0, 1.0, 0.0]]
+}
+
 /// Color camera calibration parameters.
 /// Kinect v2 includes factory preset values for these parameters.
 /// They are used in Registration.
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ColorParams {
     /*
         Intrinsic parameters
@@ -18,6 +35,11 @@ pub struct ColorParams {
     /// Focal length x (pixel)
     pub fx: f32,
     /// Focal length y (pixel)
+    ///
+    /// The factory calibration only provides a single focal length, so it equals `fx`
+    /// unless loaded from an external calibration.
+    /// The mapping polynomials are fitted against `fx`, Registration scales
+    /// its vertical mapping by `fy / fx` when they differ.
     pub fy: f32,
     /// Principal point x (pixel)
     pub cx: f32,
@@ -58,6 +80,20 @@ pub struct ColorParams {
     pub my_x0y0: f32,
 }
 
+impl ColorParams {
+    /// Intrinsic matrix K and distortion vector D in OpenCV's `[k1, k2, p1, p2, k3]` order.
+    ///
+    /// The factory calibration has no distortion coefficients for the color camera, D is all zero.
+    pub const fn opencv_params(&self) -> (Matrix3, [f32; 5]) {
+        (camera_matrix(self.fx, self.fy, self.cx, self.cy), [0.0; 5])
+    }
+
+    /// Projection matrix P.
+    pub const fn projection_matrix(&self) -> Matrix3x4 {
+        projection_matrix(self.fx, self.fy, self.cx, self.cy)
+    }
+}
+
 impl TryFrom<&[u8]> for ColorParams {
     type Error = Error;
 
@@ -65,6 +101,7 @@ impl TryFrom<&[u8]> for ColorParams {
         let raw = ColorParamsResponse::read_unaligned(buffer)?;
 
         Ok(Self {
+            // the response only holds a single focal length
             fx: raw.color_f,
             fy: raw.color_f,
             cx: raw.color_cx,
@@ -98,7 +135,8 @@ impl TryFrom<&[u8]> for ColorParams {
 /// IR camera intrinsic calibration parameters.
 /// Kinect v2 includes factory preset values for these parameters.
 /// They are used in depth image decoding, and Registration.
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IrParams {
     /// Focal length x (pixel)
     pub fx: f32,
@@ -120,6 +158,21 @@ pub struct IrParams {
     pub p2: f32,
 }
 
+impl IrParams {
+    /// Intrinsic matrix K and distortion vector D in OpenCV's `[k1, k2, p1, p2, k3]` order.
+    pub const fn opencv_params(&self) -> (Matrix3, [f32; 5]) {
+        (
+            camera_matrix(self.fx, self.fy, self.cx, self.cy),
+            [self.k1, self.k2, self.p1, self.p2, self.k3],
+        )
+    }
+
+    /// Projection matrix P.
+    pub const fn projection_matrix(&self) -> Matrix3x4 {
+        projection_matrix(self.fx, self.fy, self.cx, self.cy)
+    }
+}
+
 impl TryFrom<&[u8]> for IrParams {
     type Error = Error;
 
@@ -142,13 +195,71 @@ impl TryFrom<&[u8]> for IrParams {
 
 pub type P0Table = [u16; DEPTH_SIZE];
 
-#[derive(Debug, Clone)]
+/// Serialized as flat sequences of [`DEPTH_SIZE`] values with the `serde` feature.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct P0Tables {
+    #[cfg_attr(feature = "serde", serde(with = "p0_table_serde"))]
     pub p0_table0: Box<P0Table>,
+    #[cfg_attr(feature = "serde", serde(with = "p0_table_serde"))]
     pub p0_table1: Box<P0Table>,
+    #[cfg_attr(feature = "serde", serde(with = "p0_table_serde"))]
     pub p0_table2: Box<P0Table>,
 }
 
+/// Serde only implements arrays up to 32 elements, the tables go through a sequence visitor.
+#[cfg(feature = "serde")]
+mod p0_table_serde {
+    use std::fmt;
+
+    use serde::{
+        de::{Error, SeqAccess, Visitor},
+        Deserializer, Serializer,
+    };
+
+    use super::P0Table;
+    use crate::DEPTH_SIZE;
+
+    pub fn serialize<S: Serializer>(table: &P0Table, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(table.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<P0Table>, D::Error> {
+        struct TableVisitor;
+
+        impl<'de> Visitor<'de> for TableVisitor {
+            type Value = Box<P0Table>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a sequence of {DEPTH_SIZE} u16")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut table = Vec::with_capacity(DEPTH_SIZE);
+
+                while let Some(value) = seq.next_element()? {
+                    if table.len() == DEPTH_SIZE {
+                        return Err(A::Error::invalid_length(DEPTH_SIZE + 1, &self));
+                    }
+
+                    table.push(value);
+                }
+
+                let length = table.len();
+
+                table
+                    .into_boxed_slice()
+                    .try_into()
+                    .map_err(|_| A::Error::invalid_length(length, &self))
+            }
+        }
+
+        deserializer.deserialize_seq(TableVisitor)
+    }
+}
+
 impl TryFrom<&[u8]> for P0Tables {
     type Error = Error;
 
@@ -167,6 +278,56 @@ impl TryFrom<&[u8]> for P0Tables {
     }
 }
 
+impl P0Tables {
+    /// Get one of the three tables.
+    pub fn table(&self, table_index: usize) -> Option<&P0Table> {
+        match table_index {
+            0 => Some(&self.p0_table0),
+            1 => Some(&self.p0_table1),
+            2 => Some(&self.p0_table2),
+            _ => None,
+        }
+    }
+
+    /// Get a table value at pixel (x, y).
+    pub fn at(&self, table_index: usize, x: usize, y: usize) -> Option<u16> {
+        if x >= DEPTH_WIDTH || y >= DEPTH_HEIGHT {
+            return None;
+        }
+
+        Some(self.table(table_index)?[x + y * DEPTH_WIDTH])
+    }
+
+    /// Iterate over a table as (x, y, value).
+    pub fn iter(
+        &self,
+        table_index: usize,
+    ) -> Option<impl Iterator<Item = (usize, usize, u16)> + '_> {
+        Some(
+            self.table(table_index)?
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (index % DEPTH_WIDTH, index / DEPTH_WIDTH, *value)),
+        )
+    }
+
+    /// Export a table as a 16-bit grayscale binary PGM image, for calibration debugging.
+    pub fn to_pgm(&self, table_index: usize) -> Option<Vec<u8>> {
+        let table = self.table(table_index)?;
+        let header = format!("P5\n{} {}\n65535\n", DEPTH_WIDTH, DEPTH_HEIGHT);
+        let mut image = Vec::with_capacity(header.len() + DEPTH_SIZE * 2);
+
+        image.extend(header.as_bytes());
+
+        // PGM stores 16-bit samples as big endian
+        for value in table.iter() {
+            image.extend(value.to_be_bytes());
+        }
+
+        Some(image)
+    }
+}
+
 impl Default for P0Tables {
     fn default() -> Self {
         Self {
@@ -177,7 +338,28 @@ impl Default for P0Tables {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Hardware info reported by the device.
+///
+/// The meaning of the fields isn't documented, so they are kept as the raw little endian words of
+/// the reply. Devices of the same hardware revision report the same words, compare the whole
+/// struct to tell revisions apart, and the serial number to tell devices apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HardwareInfo {
+    pub words: [u32; 23],
+}
+
+impl TryFrom<&[u8]> for HardwareInfo {
+    type Error = Error;
+
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        let raw = HardwareInfoResponse::read_unaligned(buffer)?;
+
+        Ok(Self { words: raw.words })
+    }
+}
+
+/// Firmware version, ordered by maj, min, revision then build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FirwareVersion {
     pub maj: u16,
     pub min: u16,
@@ -185,6 +367,34 @@ pub struct FirwareVersion {
     pub build: u32,
 }
 
+/// Firmware ranges (inclusive) with a documented problem.
+/// Add an entry here when a firmware is confirmed to misbehave with this driver.
+const KNOWN_FIRMWARE_ISSUES: &[(FirwareVersion, FirwareVersion, &str)] = &[];
+
+impl FirwareVersion {
+    pub const fn new(maj: u16, min: u16, revision: u32, build: u32) -> Self {
+        Self {
+            maj,
+            min,
+            revision,
+            build,
+        }
+    }
+
+    pub const fn as_tuple(&self) -> (u16, u16, u32, u32) {
+        (self.maj, self.min, self.revision, self.build)
+    }
+
+    /// Documented problems affecting this firmware version.
+    pub fn known_issues(&self) -> Vec<&'static str> {
+        KNOWN_FIRMWARE_ISSUES
+            .iter()
+            .filter(|(first, last, _)| first <= self && self <= last)
+            .map(|(_, _, issue)| *issue)
+            .collect()
+    }
+}
+
 impl fmt::Display for FirwareVersion {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!(
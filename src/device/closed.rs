@@ -2,7 +2,7 @@ use std::fmt::{self, Debug};
 
 use crate::Error;
 
-use super::{Device, DeviceId, DeviceInfo, Opened};
+use super::{Device, DeviceId, DeviceInfo, Opened, UsbSpeed};
 
 #[derive(Clone)]
 pub struct Closed {
@@ -10,6 +10,18 @@ pub struct Closed {
 }
 
 impl Device<Closed> {
+    /// Get the USB connection speed, `None` if the OS doesn't report it.
+    /// Both streams need [`UsbSpeed::Super`] or faster.
+    pub fn connection_speed(&self) -> Option<UsbSpeed> {
+        UsbSpeed::of(&self.inner.device_info)
+    }
+
+    /// Get the serial number of the USB descriptor, opening checks it matches
+    /// the one reported by the device protocol.
+    pub fn serial_number(&self) -> Option<&str> {
+        self.inner.device_info.serial_number()
+    }
+
     /// Open the device.
     pub async fn open(self, reset: bool) -> Result<Device<Opened>, Error> {
         if reset {
@@ -20,6 +32,12 @@ impl Device<Closed> {
             inner: Opened::new(self.inner.device_info).await?,
         })
     }
+
+    /// Open the device without an async runtime, see [`crate::blocking`].
+    #[cfg(feature = "blocking")]
+    pub fn open_blocking(self, reset: bool) -> Result<crate::blocking::Device, Error> {
+        crate::blocking::Device::open(self, reset)
+    }
 }
 
 impl DeviceInfo for Device<Closed> {
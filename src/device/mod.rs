@@ -3,9 +3,12 @@ mod opened;
 
 use std::fmt::Debug;
 
+pub use crate::command::UsbErrorCounters;
 pub use closed::Closed;
 use nusb::list_devices;
-pub use opened::Opened;
+pub use opened::{
+    DeviceStats, Opened, RawIrPacket, RawIrPacketObserver, Streams, TransferErrorObserver,
+};
 
 use crate::Error;
 
@@ -19,12 +22,43 @@ pub struct DeviceId {
     pub address: u8,
 }
 
+/// USB connection speed of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UsbSpeed {
+    /// 1.5 Mbps, USB 1.0
+    Low,
+    /// 12 Mbps, USB 1.1
+    Full,
+    /// 480 Mbps, USB 2.0, too slow for both streams
+    High,
+    /// 5 Gbps, USB 3.0
+    Super,
+    /// 10 Gbps, USB 3.1
+    SuperPlus,
+}
+
+impl UsbSpeed {
+    fn of(device_info: &nusb::DeviceInfo) -> Option<Self> {
+        match device_info.speed()? {
+            nusb::Speed::Low => Some(Self::Low),
+            nusb::Speed::Full => Some(Self::Full),
+            nusb::Speed::High => Some(Self::High),
+            nusb::Speed::Super => Some(Self::Super),
+            nusb::Speed::SuperPlus => Some(Self::SuperPlus),
+            _ => None,
+        }
+    }
+}
+
 pub trait DeviceInfo: Debug {
     /// Get device id.
     fn id(&self) -> DeviceId;
 }
 
 /// Find, open, and control Kinect v2 devices.
+///
+/// Only the color camera exposes exposure and gain settings, the IR/depth sensor integration
+/// time is driven by the firmware and no command is known to change it.
 #[derive(Clone)]
 pub struct Device<T> {
     inner: T,
@@ -47,6 +81,20 @@ impl DeviceEnumerator {
             }))
     }
 
+    /// Enumerate all Kinect v2 devices in a stable order, by serial number then [`DeviceId`],
+    /// so indices map to the same physical units across reboots.
+    pub async fn enumerate_sorted() -> Result<Vec<Device<Closed>>, Error> {
+        let mut devices: Vec<_> = Self::enumerate().await?.collect();
+
+        devices.sort_by(|a, b| {
+            a.serial_number()
+                .cmp(&b.serial_number())
+                .then(a.id().cmp(&b.id()))
+        });
+
+        Ok(devices)
+    }
+
     /// Open the first device.
     pub async fn open_default(reset: bool) -> Result<Device<Opened>, Error> {
         Self::enumerate()
@@ -57,6 +105,31 @@ impl DeviceEnumerator {
             .await
     }
 
+    /// Open the device with the given serial number, [`Error::NoDevice`] if none matches.
+    pub async fn open_by_serial(serial: &str, reset: bool) -> Result<Device<Opened>, Error> {
+        Self::enumerate()
+            .await?
+            .find(|device| device.serial_number() == Some(serial))
+            .ok_or(Error::NoDevice)?
+            .open(reset)
+            .await
+    }
+
+    /// Open the first device without an async runtime, see [`crate::blocking`].
+    #[cfg(feature = "blocking")]
+    pub fn open_default_blocking(reset: bool) -> Result<crate::blocking::Device, Error> {
+        crate::blocking::Device::open_default(reset)
+    }
+
+    /// Open the device with the given serial number without an async runtime, see [`crate::blocking`].
+    #[cfg(feature = "blocking")]
+    pub fn open_by_serial_blocking(
+        serial: &str,
+        reset: bool,
+    ) -> Result<crate::blocking::Device, Error> {
+        crate::blocking::Device::open_by_serial(serial, reset)
+    }
+
     pub fn from(device_info: nusb::DeviceInfo) -> Device<Closed> {
         device_info.into()
     }
@@ -1,33 +1,43 @@
 use std::{
     fmt::{self, Debug},
-    thread::sleep,
+    future::Future,
+    io,
+    thread::{self, sleep},
     time::Duration,
 };
 
+#[cfg(feature = "stream")]
+use futures_util::{stream::unfold, Stream};
+use log::warn;
 use nusb::{
     descriptors::TransferType,
-    transfer::{Bulk, ControlOut, ControlType, In, Recipient},
+    transfer::{Bulk, ControlOut, ControlType, In, Recipient, TransferError},
     Endpoint, Interface, IsoEndpoint,
 };
 
 use crate::{
     command::{
-        color_setting_command, init_streams_command, led_setting_command,
-        read_color_params_command, read_depth_params_command, read_firware_versions_command,
-        read_p0_tables_command, read_serial_number_command, read_status_command, set_mode_command,
-        set_stream_state_command, shutdown_command, stop_command, ColorSettingResponse,
-        CommandTransaction,
+        color_setting_command, color_settings_command, init_streams_command, led_setting_command,
+        led_state_command, read_color_params_command, read_depth_params_command,
+        read_firware_versions_command, read_hardware_info_command, read_p0_tables_command,
+        read_serial_number_command, read_status_command, set_stream_state_command,
+        shutdown_command, stop_command, ColorSettingResponse, ColorSettingStatus,
+        ColorSettingsResponseHeader, CommandTransaction, UsbErrorCounters,
+        COLOR_SETTING_BATCH_SIZE,
     },
-    data::{ColorParams, FirwareVersion, IrParams, P0Tables},
+    data::{ColorParams, FirwareVersion, HardwareInfo, IrParams, P0Tables},
     packet::{
         parser::{ColorStreamParser, DepthStreamParser},
         ColorPacket, DepthPacket,
     },
-    settings::{ColorSettingCommandType, LedSettings, PacketParams},
-    Error, FromBuffer, ReadUnaligned, USB_TIMEOUT,
+    settings::{ColorSettingCommandType, LedId, LedSettings, PacketParams, WhiteBalanceMode},
+    Error, FromBuffer, ReadUnaligned, COLOR_FRAME_RATES, USB_TIMEOUT,
 };
 
-use super::{Closed, Device, DeviceId, DeviceInfo};
+#[cfg(feature = "stream")]
+use crate::PacketSync;
+
+use super::{Closed, Device, DeviceId, DeviceInfo, UsbSpeed};
 
 #[derive(Clone, Copy)]
 #[repr(u8)]
@@ -58,11 +68,50 @@ const CONTROL_OUT_ENDPOINT: u8 = 0x02;
 const COLOR_IN_ENDPOINT: u8 = 0x83;
 const IR_IN_ENDPOINT: u8 = 0x84;
 
+// don't shrink color transfers below a single USB 3 bulk packet
+const MIN_COLOR_TRANSFER_SIZE: usize = 1024;
+
 const SET_ISOCH_DELAY: u8 = 0x31;
 const REQUEST_SET_SEL: u8 = 0x30;
 const REQUEST_SET_FEATURE: u8 = 0x03;
 const DT_SS_ENDPOINT_COMPANION: u8 = 0x30;
 
+/// Streams currently delivering packets.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Streams {
+    pub color: bool,
+    pub depth: bool,
+}
+
+/// Packet counters and intervals between the last two packets of each stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceStats {
+    pub color_packets: u64,
+    pub depth_packets: u64,
+    pub last_color_interval: Duration,
+    pub last_depth_interval: Duration,
+}
+
+/// Observer of USB transfer errors, called with the endpoint address and the error.
+pub type TransferErrorObserver = Box<dyn FnMut(u8, &TransferError) + Send>;
+
+/// Isochronous packet of the depth stream as received, before parsing.
+#[derive(Debug, Clone, Copy)]
+pub struct RawIrPacket<'a> {
+    /// Number of the transfer the packet was received in, counted since the device was opened,
+    /// missing numbers are transfers that failed as a whole
+    pub transfer: u64,
+    /// Index of the packet in its transfer
+    pub index: usize,
+    /// Status of the packet, failed packets are skipped by the parser
+    pub status: Result<(), TransferError>,
+    /// Received bytes, shorter than the maximum iso packet size for partial packets
+    pub data: &'a [u8],
+}
+
+/// Observer of the raw isochronous packets of the depth stream, see [`Device::set_raw_ir_packet_observer`].
+pub type RawIrPacketObserver = Box<dyn FnMut(RawIrPacket) + Send>;
+
 pub struct Opened {
     command_transaction: CommandTransaction,
     device_info: nusb::DeviceInfo,
@@ -78,6 +127,17 @@ pub struct Opened {
     ir_endpoint: Option<IsoEndpoint<In>>,
     depth_stream_parser: DepthStreamParser,
     running: bool,
+    color_enabled: bool,
+    transfer_error_observer: Option<TransferErrorObserver>,
+    raw_ir_packet_observer: Option<RawIrPacketObserver>,
+    parse_raw_ir_packets: bool,
+    ir_transfers: u64,
+    color_timeout: Option<Duration>,
+    depth_timeout: Option<Duration>,
+    stats: DeviceStats,
+    usb_errors: UsbErrorCounters,
+    last_color_timestamp: Option<u32>,
+    last_depth_timestamp: Option<u32>,
 }
 
 impl Opened {
@@ -123,6 +183,17 @@ impl Opened {
             ir_endpoint: None,
             depth_stream_parser: DepthStreamParser::new(),
             running: false,
+            color_enabled: true,
+            transfer_error_observer: None,
+            raw_ir_packet_observer: None,
+            parse_raw_ir_packets: true,
+            ir_transfers: 0,
+            color_timeout: None,
+            depth_timeout: None,
+            stats: DeviceStats::default(),
+            usb_errors: UsbErrorCounters::default(),
+            last_color_timestamp: None,
+            last_depth_timestamp: None,
             packet_params,
             control_and_color_interface,
             ir_interface,
@@ -264,24 +335,508 @@ impl Opened {
         Ok(())
     }
 
+    /// Submit a single transfer on each endpoint, halving its size while the controller rejects it.
+    /// The probed data is dropped, the parsers resynchronize on the next frame.
+    /// A probe not completing within [`USB_TIMEOUT`] is cancelled and a timeout error returned.
+    ///
+    /// The depth stream is isochronous only, if the host rejects even the smallest transfer
+    /// [`Error::IsochronousUnsupported`] is returned, other errors are returned as is.
+    async fn probe_transfer_sizes(&mut self) -> Result<(), Error> {
+        while self.color_enabled {
+            self.color_endpoint.submit(
+                self.color_endpoint
+                    .allocate(self.packet_params.color_transfer_size),
+            );
+
+            let Some(completion) =
+                with_timeout(Some(USB_TIMEOUT), self.color_endpoint.next_complete()).await
+            else {
+                self.flush_color_transfers().await;
+
+                return Err(timed_out(
+                    &mut self.usb_errors,
+                    "Color transfer probe timed out",
+                ));
+            };
+
+            match completion.status {
+                Err(error)
+                    if is_transfer_size_error(&error)
+                        && self.packet_params.color_transfer_size > MIN_COLOR_TRANSFER_SIZE =>
+                {
+                    self.packet_params.color_transfer_size /= 2;
+
+                    warn!(
+                        "Color transfer failed ({error}), retrying with a transfer size of {} bytes",
+                        self.packet_params.color_transfer_size
+                    );
+                }
+                status => {
+                    status?;
+                    break;
+                }
+            }
+        }
+
+        while let Some(ir_endpoint) = self.ir_endpoint.as_mut() {
+            ir_endpoint.submit(
+                ir_endpoint.allocate(
+                    self.packet_params.max_iso_packet_size as usize
+                        * self.packet_params.ir_packets_per_transfer as usize,
+                ),
+                self.packet_params.max_iso_packet_size as usize,
+            );
+
+            let Some(completion) =
+                with_timeout(Some(USB_TIMEOUT), ir_endpoint.next_complete()).await
+            else {
+                self.flush_ir_transfers().await;
+
+                return Err(timed_out(
+                    &mut self.usb_errors,
+                    "IR transfer probe timed out",
+                ));
+            };
+
+            match completion.status {
+                Err(error)
+                    if is_transfer_size_error(&error)
+                        && self.packet_params.ir_packets_per_transfer > 1 =>
+                {
+                    self.packet_params.ir_packets_per_transfer /= 2;
+
+                    warn!(
+                        "IR transfer failed ({error}), retrying with {} packets per transfer",
+                        self.packet_params.ir_packets_per_transfer
+                    );
+
+                    // the endpoint has to be released before being claimed again
+                    self.ir_endpoint = None;
+                    self.ir_endpoint = Some(self.ir_interface.iso_endpoint(
+                        IR_IN_ENDPOINT,
+                        self.packet_params.ir_packets_per_transfer as usize,
+                    )?);
+                }
+                // even the smallest transfer is rejected, the host can't do isochronous transfers
+                Err(error) if is_isochronous_unsupported_error(&error) => {
+                    return Err(Error::IsochronousUnsupported(error));
+                }
+                Err(error) => return Err(error.into()),
+                Ok(()) => break,
+            }
+        }
+
+        Ok(())
+    }
+
     async fn set_video_transfer_function_state(&self, enabled: bool) -> Result<(), Error> {
         self.set_feature_function_suspend(!enabled, !enabled).await
     }
+
+    /// Stop sequence of [`Device::stop`], also run on drop.
+    async fn stop(&mut self) -> Result<(), Error> {
+        if !self.running {
+            return Ok(());
+        }
+
+        self.running = false;
+        self.disable_streams().await
+    }
+
+    /// Turn the emitter and the streams on, the end of the start sequence.
+    async fn enable_streams(&mut self) -> Result<(), Error> {
+        self.command_transaction
+            .execute(init_streams_command())
+            .await?;
+        self.set_ir_state(true).await?;
+        self.command_transaction
+            .execute(set_stream_state_command(true))
+            .await?;
+        self.probe_transfer_sizes().await
+    }
+
+    /// Commands of the stop sequence, also rolling back a failed start.
+    async fn disable_streams(&mut self) -> Result<(), Error> {
+        self.set_ir_state(false).await?;
+        self.command_transaction
+            .execute(led_state_command(LedSettings::SECONDARY_DIM))
+            .await?;
+        self.command_transaction
+            .execute(led_state_command(LedSettings::PRIMARY_OFF))
+            .await?;
+        self.command_transaction.execute(stop_command()).await?;
+        self.command_transaction
+            .execute(set_stream_state_command(false))
+            .await?;
+        self.command_transaction
+            .execute(led_state_command(LedSettings::SECONDARY_OFF))
+            .await?;
+        self.command_transaction
+            .execute(led_state_command(LedSettings::PRIMARY_OFF))
+            .await?;
+        self.command_transaction
+            .execute(led_state_command(LedSettings::SECONDARY_OFF))
+            .await?;
+        self.command_transaction
+            .execute(led_state_command(LedSettings::PRIMARY_OFF))
+            .await?;
+        self.set_video_transfer_function_state(false).await
+    }
+
+    /// Cancel the in-flight color transfers and wait for them to come back,
+    /// the data is dropped and the parser resynchronizes on the next frame.
+    async fn flush_color_transfers(&mut self) {
+        self.color_endpoint.cancel_all();
+
+        while self.color_endpoint.pending() > 0 {
+            self.color_endpoint.next_complete().await;
+        }
+    }
+
+    /// Same as [`Opened::flush_color_transfers`] for the depth stream.
+    async fn flush_ir_transfers(&mut self) {
+        if let Some(ir_endpoint) = self.ir_endpoint.as_mut() {
+            ir_endpoint.cancel_all();
+
+            while ir_endpoint.pending() > 0 {
+                ir_endpoint.next_complete().await;
+            }
+        }
+    }
+}
+
+/// Best effort stop of the streams when dropped while running, so the emitter is turned off
+/// and the device can be opened again without a reset.
+///
+/// Drop can't be async, the stop sequence runs on a separate thread with its own runtime,
+/// blocking the dropping thread until it's done, a few control transfers.
+/// Errors are only logged, call [`Device::stop`] or [`Device::close`] to handle them.
+impl Drop for Opened {
+    fn drop(&mut self) {
+        if !self.running {
+            return;
+        }
+
+        thread::scope(|scope| {
+            let stopped = scope.spawn(|| {
+                let result = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(Error::from)
+                    .and_then(|runtime| runtime.block_on(self.stop()));
+
+                if let Err(error) = result {
+                    warn!("Failed to stop the device on drop: {error}");
+                }
+            });
+
+            // a panic in drop would abort if already unwinding
+            if stopped.join().is_err() {
+                warn!("Failed to stop the device on drop");
+            }
+        });
+    }
+}
+
+/// Await `future`, `None` if it didn't complete within `timeout`.
+async fn with_timeout<F: Future>(timeout: Option<Duration>, future: F) -> Option<F::Output> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// Count a timeout of the transfers, once they're flushed, and return the error of the poll.
+fn timed_out(usb_errors: &mut UsbErrorCounters, message: &'static str) -> Error {
+    usb_errors.timeouts += 1;
+
+    io::Error::new(io::ErrorKind::TimedOut, message).into()
+}
+
+/// Errors a controller reports for a transfer larger than it can handle.
+fn is_transfer_size_error(error: &TransferError) -> bool {
+    matches!(
+        error,
+        TransferError::InvalidArgument | TransferError::Fault | TransferError::Unknown(_)
+    )
+}
+
+/// Errors a host without isochronous support reports for any isochronous transfer,
+/// a stall or a fault is an error of the device or the bus.
+fn is_isochronous_unsupported_error(error: &TransferError) -> bool {
+    matches!(
+        error,
+        TransferError::InvalidArgument | TransferError::Unknown(_)
+    )
+}
+
+/// Duration between two packet timestamps, which tick every 0.1 ms.
+fn timestamp_interval(previous: Option<u32>, timestamp: u32) -> Duration {
+    previous.map_or(Duration::ZERO, |previous| {
+        Duration::from_micros(timestamp.wrapping_sub(previous) as u64 * 100)
+    })
+}
+
+/// Raw packets of an isochronous transfer from their offset, actual length and status.
+fn raw_ir_packets<'a>(
+    transfer: u64,
+    buffer: &'a [u8],
+    packets: impl Iterator<Item = (usize, usize, Result<(), TransferError>)> + 'a,
+) -> impl Iterator<Item = RawIrPacket<'a>> {
+    packets.enumerate().map(
+        move |(index, (offset, actual_length, status))| RawIrPacket {
+            transfer,
+            index,
+            status,
+            data: buffer
+                .get(offset..offset + actual_length)
+                .unwrap_or_default(),
+        },
+    )
+}
+
+/// Count and forward a transfer error to the observer, the error is only returned if there is
+/// no observer or if the device is gone.
+fn report_transfer_error(
+    observer: &mut Option<TransferErrorObserver>,
+    usb_errors: &mut UsbErrorCounters,
+    endpoint: u8,
+    error: TransferError,
+) -> Result<(), Error> {
+    usb_errors.record_transfer_error(&error);
+
+    let Some(observer) = observer.as_mut() else {
+        return Err(error.into());
+    };
+
+    observer(endpoint, &error);
+
+    if matches!(error, TransferError::Disconnected) {
+        Err(error.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Outcome of draining the completed transfers of a stream.
+///
+/// A packet assembled from the successful transfers is returned even if another transfer
+/// of the drain failed, the error only reaches the observer and the counters then.
+struct Drain<T> {
+    packet: Option<T>,
+    error: Option<Error>,
+}
+
+impl<T> Drain<T> {
+    fn new() -> Self {
+        Self {
+            packet: None,
+            error: None,
+        }
+    }
+
+    /// Keep the first packet of the drain.
+    fn push_packet(&mut self, packet: Option<T>) {
+        if self.packet.is_none() {
+            self.packet = packet;
+        }
+    }
+
+    /// Keep the first error of the drain.
+    fn push_result(&mut self, result: Result<(), Error>) {
+        if let Err(error) = result {
+            self.error.get_or_insert(error);
+        }
+    }
+
+    fn finish(self) -> Result<Option<T>, Error> {
+        match (self.packet, self.error) {
+            (Some(packet), _) => Ok(Some(packet)),
+            (None, Some(error)) => Err(error),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// Parse a completed color transfer, a failed transfer drops the packet being assembled.
+fn drain_color_transfer(
+    drain: &mut Drain<ColorPacket>,
+    parser: &mut ColorStreamParser,
+    observer: &mut Option<TransferErrorObserver>,
+    usb_errors: &mut UsbErrorCounters,
+    status: Result<(), TransferError>,
+    data: &[u8],
+) {
+    if let Err(error) = status {
+        // the packet being assembled misses this transfer
+        parser.reset();
+        drain.push_result(report_transfer_error(
+            observer,
+            usb_errors,
+            COLOR_IN_ENDPOINT,
+            error,
+        ));
+    } else {
+        drain.push_packet(parser.parse(data.to_vec()));
+    }
 }
 
 impl Device<Opened> {
+    /// Get the USB connection speed, `None` if the OS doesn't report it.
+    /// Both streams need [`UsbSpeed::Super`] or faster.
+    pub fn connection_speed(&self) -> Option<UsbSpeed> {
+        UsbSpeed::of(&self.inner.device_info)
+    }
+
     pub fn running(&self) -> bool {
         self.inner.running
     }
 
+    /// Sequence of the last complete color packet received,
+    /// a cheap way to check the stream is making progress.
+    pub fn last_color_sequence(&self) -> Option<u32> {
+        self.inner.color_stream_parser.last_sequence()
+    }
+
+    /// Sequence of the last complete depth packet received.
+    pub fn last_depth_sequence(&self) -> Option<u32> {
+        self.inner.depth_stream_parser.last_sequence()
+    }
+
+    /// Get which streams are active, none of them until started.
+    pub fn active_streams(&self) -> Streams {
+        Streams {
+            color: self.inner.running && self.inner.color_enabled,
+            depth: self.inner.running && self.inner.ir_endpoint.is_some(),
+        }
+    }
+
+    /// Get the packet counters and the intervals between the last packets,
+    /// computed from the packet timestamps.
+    pub fn stats(&self) -> DeviceStats {
+        self.inner.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.inner.stats = DeviceStats::default();
+        self.inner.last_color_timestamp = None;
+        self.inner.last_depth_timestamp = None;
+    }
+
+    /// Get the USB errors counted since opened or since the last reset,
+    /// from both the commands and the streams.
+    ///
+    /// The counters also increase while a transfer error observer is set.
+    pub fn usb_error_counters(&self) -> UsbErrorCounters {
+        self.inner.usb_errors + *self.inner.command_transaction.error_counters()
+    }
+
+    pub fn reset_usb_error_counters(&mut self) {
+        self.inner.usb_errors.reset();
+        self.inner.command_transaction.reset_error_counters();
+    }
+
+    /// Observe USB transfer errors of the color and depth streams.
+    /// While set, recoverable errors are reported to the observer and the failed transfer is skipped
+    /// instead of failing the poll.
+    pub fn set_transfer_error_observer(
+        &mut self,
+        observer: impl FnMut(u8, &TransferError) + Send + 'static,
+    ) {
+        self.inner.transfer_error_observer = Some(Box::new(observer));
+    }
+
+    /// Remove the transfer error observer, errors fail the poll again
+    /// unless the other transfers of the poll completed a packet, which is returned.
+    pub fn clear_transfer_error_observer(&mut self) {
+        self.inner.transfer_error_observer = None;
+    }
+
+    /// Observe the isochronous packets of the depth stream as they are received,
+    /// e.g. to dump the wire format or study the lost sub images.
+    ///
+    /// Every packet is reported with its status, failed transfers go to the transfer error observer.
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - Called with each packet while polling depth packets
+    /// * `parse` - Whether the packets are still parsed, without parsing depth polls return `None`
+    ///   and the functions waiting for depth packets return [`Error::DepthParsingDisabled`]
+    pub fn set_raw_ir_packet_observer(
+        &mut self,
+        observer: impl FnMut(RawIrPacket) + Send + 'static,
+        parse: bool,
+    ) {
+        self.inner.raw_ir_packet_observer = Some(Box::new(observer));
+        self.inner.parse_raw_ir_packets = parse;
+    }
+
+    /// Remove the raw IR packet observer, packets are parsed again.
+    pub fn clear_raw_ir_packet_observer(&mut self) {
+        self.inner.raw_ir_packet_observer = None;
+        self.inner.parse_raw_ir_packets = true;
+    }
+
+    /// Error out of the functions waiting for depth packets, none would ever come.
+    pub(crate) fn check_depth_parsing(&self) -> Result<(), Error> {
+        if self.inner.parse_raw_ir_packets {
+            Ok(())
+        } else {
+            Err(Error::DepthParsingDisabled)
+        }
+    }
+
+    /// Maximum wait for each color transfer while polling, `None` (the default) waits forever.
+    ///
+    /// On timeout the in-flight color transfers are cancelled and the poll returns
+    /// an [`io::ErrorKind::TimedOut`] error, the next poll submits fresh transfers,
+    /// the depth stream isn't affected.
+    pub fn set_color_timeout(&mut self, timeout: Option<Duration>) {
+        self.inner.color_timeout = timeout;
+    }
+
+    /// Same as [`set_color_timeout`](Self::set_color_timeout) for the depth stream.
+    pub fn set_depth_timeout(&mut self, timeout: Option<Duration>) {
+        self.inner.depth_timeout = timeout;
+    }
+
+    /// Cancel the in-flight transfers of both streams, to recover from a wedged queue
+    /// without reopening the device, the next polls submit fresh transfers.
+    ///
+    /// Packets being assembled are lost.
+    pub async fn cancel_pending(&mut self) {
+        self.inner.flush_color_transfers().await;
+        self.inner.flush_ir_transfers().await;
+    }
+
     /// Start data processing with both color and depth streams.
     /// All above configuration must only be called before start() or after stop().
     pub async fn start(&mut self) -> Result<(), Error> {
+        self.start_streams(true).await
+    }
+
+    /// Start data processing with the depth stream only, no color transfer is ever submitted
+    /// so the USB bandwidth of the color stream is left to other devices.
+    ///
+    /// [`poll_color_packet`](Self::poll_color_packet) returns [`Error::ColorStreamDisabled`]
+    /// until restarted with [`start`](Self::start).
+    pub async fn start_depth_only(&mut self) -> Result<(), Error> {
+        self.start_streams(false).await
+    }
+
+    async fn start_streams(&mut self, color_enabled: bool) -> Result<(), Error> {
         if self.inner.running {
             return Ok(());
         }
 
-        self.inner.running = true;
+        self.inner.color_enabled = color_enabled;
+
+        if let Some(speed) = self
+            .connection_speed()
+            .filter(|speed| *speed <= UsbSpeed::High)
+        {
+            warn!("Connected at {speed:?} speed, the streams will likely drop frames, use a USB 3 port");
+        }
 
         self.inner.set_video_transfer_function_state(true).await?;
 
@@ -300,6 +855,7 @@ impl Device<Opened> {
             ));
         }
 
+        // each command is its own transaction, the protocol only batches color settings
         self.inner.ir_params = IrParams::try_from(
             self.inner
                 .command_transaction
@@ -322,15 +878,19 @@ impl Device<Opened> {
                 .as_slice(),
         )?;
 
+        // the official driver dims the secondary LED and turns off the primary one before
+        // waiting for the device to be ready, the LEDs aren't otherwise tied to the streams
         self.inner
             .command_transaction
-            .execute(set_mode_command(true, 0x00640064))
+            .execute(led_state_command(LedSettings::SECONDARY_DIM))
             .await?;
         self.inner
             .command_transaction
-            .execute(set_mode_command(false, 0))
+            .execute(led_state_command(LedSettings::PRIMARY_OFF))
             .await?;
 
+        let mut ready = false;
+
         for _ in 0..50 {
             if u32::from_buffer(
                 &self
@@ -341,20 +901,27 @@ impl Device<Opened> {
             ) & 1
                 != 0
             {
+                ready = true;
                 break;
             }
             sleep(Duration::from_millis(100));
         }
 
-        self.inner
-            .command_transaction
-            .execute(init_streams_command())
-            .await?;
-        self.inner.set_ir_state(true).await?;
-        self.inner
-            .command_transaction
-            .execute(set_stream_state_command(true))
-            .await?;
+        if !ready {
+            return Err(Error::DeviceNotReady);
+        }
+
+        if let Err(error) = self.inner.enable_streams().await {
+            // the emitter or the streams may be on, stop can't do it as the device isn't running
+            if let Err(stop_error) = self.inner.disable_streams().await {
+                warn!("Failed to stop the streams after a failed start: {stop_error}");
+            }
+
+            return Err(error);
+        }
+
+        // only once every command succeeded, a failed start can be retried
+        self.inner.running = true;
 
         Ok(())
     }
@@ -363,8 +930,12 @@ impl Device<Opened> {
         if !self.inner.running {
             return Err(Error::OnlyWhileRunning("Reading color frame"));
         }
+        if !self.inner.color_enabled {
+            return Err(Error::ColorStreamDisabled);
+        }
 
-        for _ in 0..self.inner.packet_params.color_num_transfers {
+        // top up the queue, transfers are still pending if the previous poll was dropped
+        for _ in self.inner.color_endpoint.pending()..self.inner.packet_params.color_num_transfers {
             self.inner.color_endpoint.submit(
                 self.inner
                     .color_endpoint
@@ -372,14 +943,42 @@ impl Device<Opened> {
             );
         }
 
-        let mut result = None;
+        // drain every completion even after a failed transfer, so the queue stays in sync
+        let mut drain = Drain::new();
 
         while self.inner.color_endpoint.pending() > 0 {
-            let packet = self.inner.color_endpoint.next_complete().await;
+            let Some(packet) = with_timeout(
+                self.inner.color_timeout,
+                self.inner.color_endpoint.next_complete(),
+            )
+            .await
+            else {
+                self.inner.flush_color_transfers().await;
 
-            packet.status?;
+                return Err(timed_out(
+                    &mut self.inner.usb_errors,
+                    "Color transfers timed out",
+                ));
+            };
+
+            drain_color_transfer(
+                &mut drain,
+                &mut self.inner.color_stream_parser,
+                &mut self.inner.transfer_error_observer,
+                &mut self.inner.usb_errors,
+                packet.status,
+                &packet.buffer,
+            );
+        }
 
-            result = result.or(self.inner.color_stream_parser.parse(packet.buffer.to_vec()));
+        let result = drain.finish()?;
+
+        if let Some(packet) = &result {
+            self.inner.stats.color_packets += 1;
+            self.inner.stats.last_color_interval = timestamp_interval(
+                self.inner.last_color_timestamp.replace(packet.timestamp),
+                packet.timestamp,
+            );
         }
 
         Ok(result)
@@ -394,7 +993,7 @@ impl Device<Opened> {
             return Ok(None);
         };
 
-        for _ in 0..self.inner.packet_params.ir_num_transfers {
+        for _ in ir_endpoint.pending()..self.inner.packet_params.ir_num_transfers {
             ir_endpoint.submit(
                 ir_endpoint.allocate(
                     self.inner.packet_params.max_iso_packet_size as usize
@@ -404,23 +1003,162 @@ impl Device<Opened> {
             );
         }
 
-        let mut result = None;
+        let mut drain = Drain::new();
 
         while ir_endpoint.pending() > 0 {
-            let iso_packet = ir_endpoint.next_complete().await;
+            let Some(iso_packet) =
+                with_timeout(self.inner.depth_timeout, ir_endpoint.next_complete()).await
+            else {
+                self.inner.flush_ir_transfers().await;
+
+                return Err(timed_out(
+                    &mut self.inner.usb_errors,
+                    "IR transfers timed out",
+                ));
+            };
+
+            let transfer = self.inner.ir_transfers;
 
-            iso_packet.status?;
+            self.inner.ir_transfers += 1;
 
-            for packet in iso_packet.successful_packets() {
-                result = result.or(self.inner.depth_stream_parser.parse(
-                    iso_packet.buffer[packet.offset..packet.offset + packet.actual_length].to_vec(),
+            if let Err(error) = iso_packet.status {
+                drain.push_result(report_transfer_error(
+                    &mut self.inner.transfer_error_observer,
+                    &mut self.inner.usb_errors,
+                    IR_IN_ENDPOINT,
+                    error,
                 ));
+                continue;
+            }
+
+            if let Some(observer) = self.inner.raw_ir_packet_observer.as_mut() {
+                let packets = iso_packet
+                    .packets
+                    .iter()
+                    .map(|packet| (packet.offset, packet.actual_length, packet.status));
+
+                raw_ir_packets(transfer, &iso_packet.buffer, packets).for_each(observer);
+            }
+
+            if self.inner.parse_raw_ir_packets {
+                for packet in iso_packet.successful_packets() {
+                    drain.push_packet(
+                        self.inner.depth_stream_parser.parse(
+                            iso_packet.buffer[packet.offset..packet.offset + packet.actual_length]
+                                .to_vec(),
+                        ),
+                    );
+                }
             }
         }
 
+        let result = drain.finish()?;
+
+        if let Some(packet) = &result {
+            self.inner.stats.depth_packets += 1;
+            self.inner.stats.last_depth_interval = timestamp_interval(
+                self.inner.last_depth_timestamp.replace(packet.timestamp),
+                packet.timestamp,
+            );
+        }
+
         Ok(result)
     }
 
+    /// Wait for the next complete color packet, instead of calling
+    /// [`poll_color_packet`](Self::poll_color_packet) in a loop.
+    ///
+    /// The future only wakes up on completed USB transfers, it doesn't spin while waiting.
+    /// Dropping it before completion can lose the packet being assembled.
+    pub async fn next_color_packet(&mut self) -> Result<ColorPacket, Error> {
+        loop {
+            if let Some(packet) = self.poll_color_packet().await? {
+                return Ok(packet);
+            }
+        }
+    }
+
+    /// Wait for the next complete depth packet, instead of calling
+    /// [`poll_depth_packet`](Self::poll_depth_packet) in a loop.
+    ///
+    /// The future only wakes up on completed USB transfers, it doesn't spin while waiting.
+    /// Dropping it before completion can lose the packet being assembled.
+    pub async fn next_depth_packet(&mut self) -> Result<DepthPacket, Error> {
+        self.check_depth_parsing()?;
+
+        loop {
+            // without the iso endpoint nothing would ever be awaited
+            if self.inner.ir_endpoint.is_none() {
+                return Err(Error::OnlyWhileRunning("Waiting for a depth frame"));
+            }
+
+            if let Some(packet) = self.poll_depth_packet().await? {
+                return Ok(packet);
+            }
+        }
+    }
+
+    /// Stream of synchronized color and depth packet pairs, polling both streams and
+    /// matching them with a [`PacketSync`].
+    ///
+    /// USB errors are yielded without ending the stream, it only ends after yielding the error
+    /// of a disconnected or stopped device.
+    #[cfg(feature = "stream")]
+    pub fn frame_stream(
+        &mut self,
+    ) -> impl Stream<Item = Result<(ColorPacket, DepthPacket), Error>> + '_ {
+        unfold(Some((self, PacketSync::new())), |state| async move {
+            let (device, mut packet_sync) = state?;
+
+            loop {
+                if let Some(packets) = packet_sync.poll_packets() {
+                    return Some((Ok(packets), Some((device, packet_sync))));
+                }
+
+                if let Err(error) = device.poll_packet_sync(&mut packet_sync).await {
+                    let ended = matches!(
+                        error,
+                        Error::OnlyWhileRunning(_)
+                            | Error::ColorStreamDisabled
+                            | Error::DepthParsingDisabled
+                            | Error::UsbTransfer(TransferError::Disconnected)
+                    );
+
+                    return Some((Err(error), (!ended).then_some((device, packet_sync))));
+                }
+            }
+        })
+    }
+
+    #[cfg(feature = "stream")]
+    async fn poll_packet_sync(&mut self, packet_sync: &mut PacketSync) -> Result<(), Error> {
+        // without the iso endpoint no pair would ever be complete
+        if self.inner.ir_endpoint.is_none() {
+            return Err(Error::OnlyWhileRunning("Streaming frames"));
+        }
+
+        self.check_depth_parsing()?;
+
+        if let Some(packet) = self.poll_color_packet().await? {
+            packet_sync.push_color_packet(packet);
+        }
+        if let Some(packet) = self.poll_depth_packet().await? {
+            packet_sync.push_depth_packet(packet);
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_hardware_info(&mut self) -> Result<HardwareInfo, Error> {
+        HardwareInfo::try_from(
+            self.inner
+                .command_transaction
+                .execute(read_hardware_info_command())
+                .await?
+                .as_slice(),
+        )
+    }
+
     pub async fn get_firware_versions(&mut self) -> Result<Vec<FirwareVersion>, Error> {
         let buffer = self
             .inner
@@ -461,6 +1199,33 @@ impl Device<Opened> {
         &self.inner.ir_params
     }
 
+    /// Get the USB transfer parameters used by the streams.
+    pub fn packet_params(&self) -> PacketParams {
+        self.inner.packet_params
+    }
+
+    /// Set the USB transfer parameters, e.g. fewer depth transfers when they saturate the bus.
+    /// The negotiated `max_iso_packet_size` is kept, and `start` may still shrink the transfer
+    /// sizes the controller rejects.
+    pub fn set_packet_params(&mut self, packet_params: PacketParams) -> Result<(), Error> {
+        if self.inner.running {
+            return Err(Error::OnlyWhileStopped("Setting packet parameters"));
+        }
+
+        self.inner.packet_params = PacketParams {
+            max_iso_packet_size: self.inner.packet_params.max_iso_packet_size,
+            ..packet_params
+        };
+
+        Ok(())
+    }
+
+    /// Get the max isochronous packet size negotiated for the depth stream,
+    /// a value close to the `0x8400` minimum leaves little bandwidth headroom.
+    pub fn max_iso_packet_size(&self) -> u16 {
+        self.inner.packet_params.max_iso_packet_size
+    }
+
     /// Get p0 tables.
     pub fn get_p0_tables(&self) -> &P0Tables {
         &self.inner.p0_tables
@@ -480,17 +1245,17 @@ impl Device<Opened> {
             return Err(Error::OnlyWhileRunning("Setting auto exposure"));
         }
 
-        self.set_color_setting(ColorSettingCommandType::SetAcs, 0)
-            .await?;
-        self.set_color_setting(ColorSettingCommandType::SetExposureMode, 0)
-            .await?;
-        self.set_color_setting(
-            ColorSettingCommandType::SetExposureCompensation,
-            exposure_compensation.clamp(-2.0, 2.0).to_bits(),
-        )
-        .await?;
-
-        Ok(())
+        self.set_color_settings(&[
+            (ColorSettingCommandType::SetAcs, 0),
+            (ColorSettingCommandType::SetExposureMode, 0),
+            (
+                ColorSettingCommandType::SetExposureCompensation,
+                ColorSettingCommandType::SetExposureCompensation
+                    .clamp(exposure_compensation)
+                    .to_bits(),
+            ),
+        ])
+        .await
     }
 
     /// Sets a flicker-free exposure time of the color camera in pseudo-ms, value in range [0.0, 640] ms.
@@ -519,19 +1284,17 @@ impl Device<Opened> {
             return Err(Error::OnlyWhileRunning("Setting semi-auto exposure"));
         }
 
-        self.set_color_setting(ColorSettingCommandType::SetAcs, 0)
-            .await?;
-        self.set_color_setting(ColorSettingCommandType::SetExposureMode, 3)
-            .await?;
-        self.set_color_setting(
-            ColorSettingCommandType::SetExposureTimeMs,
-            ((pseudo_exposure_time.as_secs_f64() / 1000.0) as f32)
-                .clamp(0.0, 66.0)
-                .to_bits(),
-        )
-        .await?;
-
-        Ok(())
+        self.set_color_settings(&[
+            (ColorSettingCommandType::SetAcs, 0),
+            (ColorSettingCommandType::SetExposureMode, 3),
+            (
+                ColorSettingCommandType::SetExposureTimeMs,
+                ColorSettingCommandType::SetExposureTimeMs
+                    .clamp((pseudo_exposure_time.as_secs_f64() / 1000.0) as f32)
+                    .to_bits(),
+            ),
+        ])
+        .await
     }
 
     /// Manually set true exposure time and analog gain of the color camera.
@@ -549,24 +1312,176 @@ impl Device<Opened> {
             return Err(Error::OnlyWhileRunning("Setting manual exposure"));
         }
 
-        self.set_color_setting(ColorSettingCommandType::SetAcs, 0)
+        self.set_color_settings(&[
+            (ColorSettingCommandType::SetAcs, 0),
+            (ColorSettingCommandType::SetExposureMode, 4),
+            (
+                ColorSettingCommandType::SetIntegrationTime,
+                ColorSettingCommandType::SetIntegrationTime
+                    .clamp((integration_time.as_secs_f64() / 1000.0) as f32)
+                    .to_bits(),
+            ),
+            (
+                ColorSettingCommandType::SetAnalogGain,
+                ColorSettingCommandType::SetAnalogGain
+                    .clamp(analog_gain)
+                    .to_bits(),
+            ),
+        ])
+        .await
+    }
+
+    /// Switch the color camera to manual exposure, keeping the integration time and analog gain
+    /// currently chosen by auto exposure, e.g. once converged so the brightness stops changing.
+    pub async fn lock_color_exposure(&mut self) -> Result<(), Error> {
+        if !self.inner.running {
+            return Err(Error::OnlyWhileRunning("Locking exposure"));
+        }
+
+        // the raw float values are written back as is
+        let values = self
+            .get_color_settings(&[
+                ColorSettingCommandType::GetIntegrationTime,
+                ColorSettingCommandType::GetAnalogGain,
+            ])
             .await?;
-        self.set_color_setting(ColorSettingCommandType::SetExposureMode, 4)
+
+        self.set_color_settings(&[
+            (ColorSettingCommandType::SetAcs, 0),
+            (ColorSettingCommandType::SetExposureMode, 4),
+            (ColorSettingCommandType::SetIntegrationTime, values[0]),
+            (ColorSettingCommandType::SetAnalogGain, values[1]),
+        ])
+        .await
+    }
+
+    /// Sets the color camera to automatic white balance.
+    pub async fn set_color_auto_white_balance(&mut self) -> Result<(), Error> {
+        if !self.inner.running {
+            return Err(Error::OnlyWhileRunning("Setting auto white balance"));
+        }
+
+        self.set_color_settings(&[
+            (ColorSettingCommandType::SetAcs, 0),
+            (
+                ColorSettingCommandType::SetWhiteBalanceMode,
+                WhiteBalanceMode::Auto.into(),
+            ),
+        ])
+        .await
+    }
+
+    /// Manually set the white balance gains of the color camera.
+    ///
+    /// # Arguments
+    ///
+    /// * `red_gain` - Red channel gain, range [1.0, 4.0]
+    /// * `green_gain` - Green channel gain, range [1.0, 4.0]
+    /// * `blue_gain` - Blue channel gain, range [1.0, 4.0]
+    pub async fn set_color_manual_white_balance(
+        &mut self,
+        red_gain: f32,
+        green_gain: f32,
+        blue_gain: f32,
+    ) -> Result<(), Error> {
+        if !self.inner.running {
+            return Err(Error::OnlyWhileRunning("Setting manual white balance"));
+        }
+
+        let gain =
+            |command: ColorSettingCommandType, gain: f32| (command, command.clamp(gain).to_bits());
+
+        self.set_color_settings(&[
+            (ColorSettingCommandType::SetAcs, 0),
+            (
+                ColorSettingCommandType::SetWhiteBalanceMode,
+                WhiteBalanceMode::Manual.into(),
+            ),
+            gain(ColorSettingCommandType::SetReChannelGain, red_gain),
+            gain(ColorSettingCommandType::SetGreenChannelGain, green_gain),
+            gain(ColorSettingCommandType::SetBlueChannelGain, blue_gain),
+        ])
+        .await
+    }
+
+    /// Get the white balance mode of the color camera.
+    pub async fn get_color_white_balance_mode(&mut self) -> Result<WhiteBalanceMode, Error> {
+        Ok(self
+            .get_color_setting(ColorSettingCommandType::GetWhiteBalanceMode)
+            .await?
+            .into())
+    }
+
+    /// Set the frame rate of the color camera, one of [`COLOR_FRAME_RATES`].
+    ///
+    /// In low light the camera otherwise lengthens the exposure and drops to a variable frame rate,
+    /// 15 fps keeps a steady rate with up to 66 ms of exposure.
+    pub async fn set_color_frame_rate(&mut self, fps: u32) -> Result<(), Error> {
+        if !self.inner.running {
+            return Err(Error::OnlyWhileRunning("Setting color frame rate"));
+        }
+        if !COLOR_FRAME_RATES.contains(&fps) {
+            return Err(Error::UnsupportedFrameRate(fps));
+        }
+
+        self.set_color_setting(ColorSettingCommandType::SetFrameRate, fps)
+            .await
+    }
+
+    /// Get the frame rate of the color camera.
+    pub async fn get_color_frame_rate(&mut self) -> Result<u32, Error> {
+        self.get_color_setting(ColorSettingCommandType::GetFrameRate)
+            .await
+    }
+
+    /// Enable or disable the automatic color settings (ACS) of the color camera.
+    ///
+    /// The exposure setters disable it, enabling it gives back the camera's full automatic color pipeline.
+    pub async fn set_auto_color_settings(&mut self, enabled: bool) -> Result<(), Error> {
+        if !self.inner.running {
+            return Err(Error::OnlyWhileRunning("Setting auto color settings"));
+        }
+
+        self.set_color_setting(ColorSettingCommandType::SetAcs, enabled as u32)
+            .await
+    }
+
+    /// Get whether the automatic color settings (ACS) of the color camera are enabled.
+    pub async fn get_auto_color_settings(&mut self) -> Result<bool, Error> {
+        Ok(self
+            .get_color_setting(ColorSettingCommandType::GetAcs)
+            .await?
+            != 0)
+    }
+
+    /// Get the pseudo-exposure time of the color camera in milliseconds.
+    pub async fn get_color_exposure_time_ms(&mut self) -> Result<f32, Error> {
+        self.get_color_float_setting(ColorSettingCommandType::GetExposureTimeMs)
+            .await
+    }
+
+    /// Get the analog gain of the color camera, range [1.0, 4.0].
+    pub async fn get_color_analog_gain(&mut self) -> Result<f32, Error> {
+        self.get_color_float_setting(ColorSettingCommandType::GetAnalogGain)
+            .await
+    }
+
+    /// Get the true shutter time of the color camera, zero if the camera reports an invalid value.
+    pub async fn get_color_integration_time(&mut self) -> Result<Duration, Error> {
+        let integration_time = self
+            .get_color_float_setting(ColorSettingCommandType::GetIntegrationTime)
             .await?;
-        self.set_color_setting(
-            ColorSettingCommandType::SetIntegrationTime,
-            ((integration_time.as_secs_f64() / 1000.0) as f32)
-                .clamp(0.0, 66.0)
-                .to_bits(),
-        )
-        .await?;
-        self.set_color_setting(
-            ColorSettingCommandType::SetAnalogGain,
-            analog_gain.clamp(1.0, 4.0).to_bits(),
-        )
-        .await?;
 
-        Ok(())
+        Ok(Duration::try_from_secs_f32(integration_time / 1000.0).unwrap_or_default())
+    }
+
+    /// Get a setting value of the color camera encoded as an `f32`,
+    /// see [`ColorSettingCommandType::is_float`].
+    pub async fn get_color_float_setting(
+        &mut self,
+        command: ColorSettingCommandType,
+    ) -> Result<f32, Error> {
+        Ok(f32::from_bits(self.get_color_setting(command).await?))
     }
 
     /// Set an individual setting value of the color camera.
@@ -597,6 +1512,51 @@ impl Device<Opened> {
         Ok(ColorSettingResponse::read_unaligned(&bytes)?.data)
     }
 
+    /// Get several setting values of the color camera, batching them to save USB round-trips.
+    pub async fn get_color_settings(
+        &mut self,
+        commands: &[ColorSettingCommandType],
+    ) -> Result<Vec<u32>, Error> {
+        let settings = commands
+            .iter()
+            .map(|command| (*command, 0))
+            .collect::<Vec<_>>();
+
+        self.execute_color_settings(&settings).await
+    }
+
+    /// Set several setting values of the color camera in order, batching them to save USB round-trips.
+    ///
+    /// Only color settings can be batched, every other command is its own transaction.
+    /// Fails with [`Error::ColorSetting`] for the first setting the device rejected.
+    pub async fn set_color_settings(
+        &mut self,
+        settings: &[(ColorSettingCommandType, u32)],
+    ) -> Result<(), Error> {
+        self.execute_color_settings(settings).await?;
+
+        Ok(())
+    }
+
+    async fn execute_color_settings(
+        &mut self,
+        settings: &[(ColorSettingCommandType, u32)],
+    ) -> Result<Vec<u32>, Error> {
+        let mut values = Vec::with_capacity(settings.len());
+
+        for settings in settings.chunks(COLOR_SETTING_BATCH_SIZE) {
+            let bytes = self
+                .inner
+                .command_transaction
+                .execute(color_settings_command(settings))
+                .await?;
+
+            values.extend(color_settings_values(settings, &bytes)?);
+        }
+
+        Ok(values)
+    }
+
     /// Set the settings of a Kinect LED.
     ///
     /// # Arguments
@@ -611,48 +1571,19 @@ impl Device<Opened> {
         Ok(())
     }
 
-    /// Stop data processing.
-    pub async fn stop(&mut self) -> Result<(), Error> {
-        if !self.inner.running {
-            return Ok(());
-        }
+    /// Pulse a LED, see [`LedSettings::pulse`].
+    pub async fn set_led_pulse(&mut self, id: LedId, period: Duration) -> Result<(), Error> {
+        self.set_led_status(LedSettings::pulse(id, period)).await
+    }
 
-        self.inner.running = false;
+    /// Turn a LED off.
+    pub async fn set_led_off(&mut self, id: LedId) -> Result<(), Error> {
+        self.set_led_status(LedSettings::off(id)).await
+    }
 
-        self.inner.set_ir_state(false).await?;
-        self.inner
-            .command_transaction
-            .execute(set_mode_command(true, 0x00640064))
-            .await?;
-        self.inner
-            .command_transaction
-            .execute(set_mode_command(false, 0))
-            .await?;
-        self.inner
-            .command_transaction
-            .execute(stop_command())
-            .await?;
-        self.inner
-            .command_transaction
-            .execute(set_stream_state_command(false))
-            .await?;
-        self.inner
-            .command_transaction
-            .execute(set_mode_command(true, 0))
-            .await?;
-        self.inner
-            .command_transaction
-            .execute(set_mode_command(false, 0))
-            .await?;
-        self.inner
-            .command_transaction
-            .execute(set_mode_command(true, 0))
-            .await?;
-        self.inner
-            .command_transaction
-            .execute(set_mode_command(false, 0))
-            .await?;
-        self.inner.set_video_transfer_function_state(false).await
+    /// Stop data processing.
+    pub async fn stop(&mut self) -> Result<(), Error> {
+        self.inner.stop().await
     }
 
     /// Shut down the device.
@@ -660,11 +1591,11 @@ impl Device<Opened> {
         self.stop().await?;
         self.inner
             .command_transaction
-            .execute(set_mode_command(true, 0x00640064))
+            .execute(led_state_command(LedSettings::SECONDARY_DIM))
             .await?;
         self.inner
             .command_transaction
-            .execute(set_mode_command(false, 0))
+            .execute(led_state_command(LedSettings::PRIMARY_OFF))
             .await?;
         self.inner
             .command_transaction
@@ -673,7 +1604,7 @@ impl Device<Opened> {
 
         Ok(Device {
             inner: Closed {
-                device_info: self.inner.device_info,
+                device_info: self.inner.device_info.clone(),
             },
         })
     }
@@ -693,3 +1624,227 @@ impl Debug for Device<Opened> {
         self.inner.device_info.fmt(f)
     }
 }
+
+/// Values of a batch of color settings, failing on the first setting the device rejected.
+fn color_settings_values(
+    settings: &[(ColorSettingCommandType, u32)],
+    bytes: &[u8],
+) -> Result<Vec<u32>, Error> {
+    let header = ColorSettingsResponseHeader::read_unaligned(bytes)?;
+
+    if (header.num_status as usize) < settings.len() {
+        return Err(Error::Receive(
+            header.num_status as usize,
+            settings.len() as u32,
+        ));
+    }
+
+    settings
+        .iter()
+        .enumerate()
+        .map(|(index, (command, _))| {
+            let offset = ColorSettingsResponseHeader::size() + index * ColorSettingStatus::size();
+            let status = ColorSettingStatus::read_unaligned(&bytes[offset.min(bytes.len())..])?;
+
+            match status.status {
+                0 => Ok(status.data),
+                code => Err(Error::ColorSetting(*command, code)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Batch response holding a status and value for each setting.
+    fn color_settings_response(statuses: &[(u32, u32)]) -> Vec<u8> {
+        [statuses.len() as u32, 0]
+            .into_iter()
+            .chain(statuses.iter().flat_map(|(status, data)| [*status, *data]))
+            .flat_map(u32::to_le_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn color_settings_statuses() {
+        let settings = [
+            (ColorSettingCommandType::GetIntegrationTime, 0),
+            (ColorSettingCommandType::GetAnalogGain, 0),
+        ];
+
+        assert_eq!(
+            color_settings_values(&settings, &color_settings_response(&[(0, 7), (0, 9)])).unwrap(),
+            [7, 9]
+        );
+        assert!(matches!(
+            color_settings_values(&settings, &color_settings_response(&[(0, 7), (3, 0)])),
+            Err(Error::ColorSetting(
+                ColorSettingCommandType::GetAnalogGain,
+                3
+            ))
+        ));
+        assert!(matches!(
+            color_settings_values(&settings, &color_settings_response(&[(0, 7)])),
+            Err(Error::Receive(1, 2))
+        ));
+
+        // reported statuses missing from the response
+        let mut truncated = color_settings_response(&[(0, 7), (0, 9)]);
+
+        truncated.truncate(truncated.len() - 4);
+
+        assert!(matches!(
+            color_settings_values(&settings, &truncated),
+            Err(Error::UnalignedRead(_))
+        ));
+    }
+
+    /// Transfer holding a whole color packet with a 4 bytes JPEG.
+    fn color_transfer(sequence: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        data.extend(sequence.to_le_bytes());
+        data.extend(0x42424242u32.to_le_bytes());
+        data.extend([0xff, 0xd8, 0xff, 0xd9]);
+        // header, JPEG and the 56 bytes of the footer
+        for word in [
+            0x39393939,
+            sequence,
+            0,
+            0,
+            0,
+            100,
+            0,
+            0,
+            0x42424242,
+            8 + 4 + 56,
+            0,
+            0,
+            0,
+            0,
+        ] {
+            data.extend(u32::to_le_bytes(word));
+        }
+
+        data
+    }
+
+    #[test]
+    fn color_drain_error() {
+        let mut parser = ColorStreamParser::new();
+        let mut usb_errors = UsbErrorCounters::default();
+        let mut drain = Drain::new();
+
+        // the packet assembled before the failed transfer is kept
+        for (status, data) in [
+            (Ok(()), color_transfer(1)),
+            (Err(TransferError::Stall), Vec::new()),
+            (Ok(()), Vec::new()),
+        ] {
+            drain_color_transfer(
+                &mut drain,
+                &mut parser,
+                &mut None,
+                &mut usb_errors,
+                status,
+                &data,
+            );
+        }
+
+        assert_eq!(drain.finish().unwrap().unwrap().sequence, 1);
+        assert_eq!(usb_errors.stalls, 1);
+
+        // a failed transfer in the middle of a packet drops it, the error is returned
+        let packet = color_transfer(2);
+        let mut drain = Drain::new();
+
+        for (status, data) in [
+            (Ok(()), &packet[..10]),
+            (Err(TransferError::Stall), &[][..]),
+            (Ok(()), &packet[10..]),
+        ] {
+            drain_color_transfer(
+                &mut drain,
+                &mut parser,
+                &mut None,
+                &mut usb_errors,
+                status,
+                data,
+            );
+        }
+
+        assert!(matches!(
+            drain.finish(),
+            Err(Error::UsbTransfer(TransferError::Stall))
+        ));
+        assert_eq!(usb_errors.stalls, 2);
+    }
+
+    #[tokio::test]
+    async fn transfer_timeout() {
+        let mut usb_errors = UsbErrorCounters::default();
+
+        assert_eq!(with_timeout(None, async { 1 }).await, Some(1));
+        assert_eq!(
+            with_timeout(Some(Duration::from_millis(1)), async { 1 }).await,
+            Some(1)
+        );
+
+        // a transfer never completing
+        let completion =
+            with_timeout(Some(Duration::from_millis(1)), std::future::pending::<()>()).await;
+
+        assert!(completion.is_none());
+
+        let error = timed_out(&mut usb_errors, "Color transfers timed out");
+
+        assert!(matches!(&error, Error::Io(error) if error.kind() == io::ErrorKind::TimedOut));
+        assert!(error.is_transient());
+        assert_eq!(usb_errors.timeouts, 1);
+    }
+
+    #[test]
+    fn isochronous_unsupported_errors() {
+        assert!(is_isochronous_unsupported_error(
+            &TransferError::InvalidArgument
+        ));
+        assert!(is_isochronous_unsupported_error(&TransferError::Unknown(
+            38
+        )));
+
+        for error in [
+            TransferError::Stall,
+            TransferError::Cancelled,
+            TransferError::Fault,
+            TransferError::Disconnected,
+        ] {
+            assert!(!is_isochronous_unsupported_error(&error));
+        }
+    }
+
+    #[test]
+    fn raw_ir_packets_numbering() {
+        let buffer: Vec<u8> = (0..12).collect();
+        let packets = [
+            (0, 4, Ok(())),
+            (4, 0, Err(TransferError::Fault)),
+            (8, 2, Ok(())),
+        ];
+        let raw_packets: Vec<_> = raw_ir_packets(7, &buffer, packets.into_iter()).collect();
+
+        assert!(raw_packets.iter().all(|packet| packet.transfer == 7));
+        assert_eq!(
+            raw_packets
+                .iter()
+                .map(|packet| (packet.index, packet.status, packet.data))
+                .collect::<Vec<_>>(),
+            [
+                (0, Ok(()), &[0, 1, 2, 3][..]),
+                (1, Err(TransferError::Fault), &[][..]),
+                (2, Ok(()), &[8, 9][..]),
+            ]
+        );
+    }
+}
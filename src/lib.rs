@@ -3,15 +3,25 @@ mod device;
 mod packet;
 mod settings;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod data;
 pub mod processor;
+#[cfg(feature = "cpu_depth")]
+pub mod record;
+pub mod replay;
+#[cfg(feature = "ros")]
+pub mod ros;
 
 use std::{any::type_name, collections::VecDeque, io, ptr::read_unaligned, time::Duration};
 
 use packet::{ColorPacket, DepthPacket};
 use thiserror::Error;
 
-pub use device::{Device, DeviceEnumerator, DeviceInfo};
+pub use device::{
+    Device, DeviceEnumerator, DeviceInfo, DeviceStats, RawIrPacket, RawIrPacketObserver, Streams,
+    TransferErrorObserver, UsbErrorCounters, UsbSpeed,
+};
 
 const USB_TIMEOUT: Duration = Duration::from_secs(2);
 
@@ -22,11 +32,26 @@ pub const DEPTH_SIZE: usize = DEPTH_WIDTH * DEPTH_HEIGHT;
 pub const COLOR_WIDTH: usize = 1920;
 pub const COLOR_HEIGHT: usize = 1080;
 pub const COLOR_SIZE: usize = COLOR_WIDTH * COLOR_HEIGHT;
+/// Frame rates the color camera can be set to, see [`Device::set_color_frame_rate`].
+pub const COLOR_FRAME_RATES: [u32; 2] = [15, 30];
 
 pub const LUT_SIZE: usize = 2048;
 
+/// Size in bytes of a depth sub image, pixels are packed on 11 bits.
+pub const DEPTH_SUBFRAME_SIZE: usize = DEPTH_SIZE * 11 / 8;
+/// Number of sub images in a depth packet, 3 phases for each of the 3 frequencies plus one more.
+/// The packet is only complete once all of them are received.
+pub const DEPTH_SUBFRAME_COUNT: usize = 10;
+/// Size in bytes of a depth packet buffer.
+/// The depth parser allocates this once per opened device to assemble the sub images,
+/// plus one [`DEPTH_SUBFRAME_SIZE`] working buffer, and each parsed packet owns a copy of it.
+pub const DEPTH_PACKET_SIZE: usize = DEPTH_SUBFRAME_SIZE * DEPTH_SUBFRAME_COUNT;
+
 pub mod config {
-    pub use crate::settings::{ColorSettingCommandType, LedId, LedMode, LedSettings};
+    pub use crate::settings::{
+        ColorSettingCommandType, DepthProcessorParams, LedId, LedMode, LedSettings, PacketParams,
+        WhiteBalanceMode,
+    };
 
     /// Configuration of depth processing.
     #[derive(Debug, Clone, Copy)]
@@ -40,6 +65,13 @@ pub mod config {
         pub enable_bilateral_filter: bool,
         // Remove pixels on edges because ToF cameras produce noisy edges
         pub enable_edge_aware_filter: bool,
+        // Keep edge pixels the edge-aware filter would remove, only clipping to the depth range
+        // (not used by the KDE pipeline)
+        pub preserve_edges: bool,
+        // Only process this region (x, y, width, height) of the depth frame, for speed.
+        // The frames stay full-size, pixels outside of it are 0 (only supported by the CPU pipeline,
+        // the others reject it)
+        pub roi: Option<(usize, usize, usize, usize)>,
     }
 
     impl Default for Config {
@@ -49,9 +81,26 @@ pub mod config {
                 max_depth: 4.5,
                 enable_bilateral_filter: true,
                 enable_edge_aware_filter: true,
+                preserve_edges: false,
+                roi: None,
             }
         }
     }
+
+    #[cfg(any(
+        feature = "opencl_depth",
+        feature = "opencl_kde_depth",
+        feature = "wgpu_depth"
+    ))]
+    impl Config {
+        /// Whether values compiled into the GPU programs differ,
+        /// the filters are toggled when processing without rebuilding them.
+        pub(crate) fn program_changed(&self, config: &Config) -> bool {
+            self.min_depth != config.min_depth
+                || self.max_depth != config.max_depth
+                || self.preserve_edges != config.preserve_edges
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -65,7 +114,7 @@ pub enum Error {
     #[error(transparent)]
     UsbTransfer(#[from] nusb::transfer::TransferError),
     #[error("Processing error: {0}")]
-    Processing(Box<dyn std::error::Error>),
+    Processing(#[from] processor::ProcessingError),
     #[error("No Kinect connected")]
     NoDevice,
     #[error("Wrong data length received, expected {1} got {0}")]
@@ -82,8 +131,62 @@ pub enum Error {
     UnalignedRead(&'static str),
     #[error("{0} can happen only while running")]
     OnlyWhileRunning(&'static str),
+    #[error("{0} can happen only while stopped")]
+    OnlyWhileStopped(&'static str),
+    #[error("Color stream is disabled, the device was started in depth only mode")]
+    ColorStreamDisabled,
+    #[error("{0} must be set before building the registration")]
+    MissingCalibration(&'static str),
     #[error("Can't set ir state, device handle is borrowed multiple times")]
     IrState,
+    #[error("Device never reported ready status")]
+    DeviceNotReady,
+    #[error("Isochronous transfers of the depth stream failed ({0}), they are often unsupported by virtualized USB (VMs, WSL) and the device has no bulk fallback")]
+    IsochronousUnsupported(nusb::transfer::TransferError),
+    #[error("Invalid depth range, minimum {0} must be lower than maximum {1}")]
+    DepthRange(f32, f32),
+    #[error("Invalid packet recording: {0}")]
+    InvalidRecording(&'static str),
+    #[error("Packet recording format version {0} isn't supported")]
+    RecordingVersion(u16),
+    #[error("Color frame rate {0} isn't supported, supported rates are {COLOR_FRAME_RATES:?}")]
+    UnsupportedFrameRate(u32),
+    #[error("Color setting {0:?} failed with status {1}")]
+    ColorSetting(config::ColorSettingCommandType, u32),
+    #[error("Invalid row alignment of {0} bytes")]
+    RowAlignment(usize),
+    #[error("Depth packets aren't parsed while the raw IR packet observer is set without parsing")]
+    DepthParsingDisabled,
+    #[error("Color space {0:?} can't be converted to an image")]
+    UnsupportedColorSpace(processor::color::ColorSpace),
+    #[cfg(any(feature = "opencl_depth", feature = "opencl_kde_depth"))]
+    #[error("No OpenCL platform or device found, install an OpenCL runtime or use the CPU depth processor")]
+    NoOpenCLPlatform,
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl Error {
+    /// Whether the error may go away by retrying, like a USB timeout,
+    /// as opposed to a disconnected device or a protocol error.
+    ///
+    /// Command timeouts are reported as [`io::ErrorKind::TimedOut`], timed out control
+    /// transfers as cancelled transfers. A stalled endpoint stays halted until cleared,
+    /// so stalls aren't transient.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Io(error) => matches!(
+                error.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+            ),
+            Self::UsbTransfer(error) => matches!(
+                error,
+                nusb::transfer::TransferError::Cancelled | nusb::transfer::TransferError::Fault
+            ),
+            _ => false,
+        }
+    }
 }
 
 trait ReadUnaligned: Sized {
@@ -131,47 +234,404 @@ impl FromBuffer for u16 {
     }
 }
 
+/// Default number of depth packets [`PacketSync`] waits with for a matching color packet,
+/// one second of the 30 fps depth stream.
+pub const PACKET_SYNC_DEPTH_CAPACITY: usize = 30;
+
+/// Buffering of the packets waiting to be paired by [`PacketSync`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Keep only the newest color and depth packets, overwriting the ones not consumed yet.
+    /// For interactive use, where stale frames are useless.
+    LatestOnly,
+    /// Queue both color and depth packets, up to the given capacity each.
+    /// For recording, nothing is dropped as long as the consumer keeps up on average.
+    Queue(usize),
+}
+
 pub struct PacketSync {
-    color_packet: Option<ColorPacket>,
+    color_packet: VecDeque<ColorPacket>,
     depth_packet: VecDeque<DepthPacket>,
+    color_capacity: usize,
+    depth_capacity: usize,
+    dropped_color_packets: u64,
+    dropped_depth_packets: u64,
+    timestamp_offset: i64,
+    deduplicate: bool,
+    last_color_sequence: Option<u32>,
+    last_depth_sequence: Option<u32>,
+    duplicate_packets: u64,
 }
 
 impl PacketSync {
     pub fn new() -> Self {
+        Self::with_capacity(1, PACKET_SYNC_DEPTH_CAPACITY)
+    }
+
+    pub fn with_mode(mode: SyncMode) -> Self {
+        match mode {
+            SyncMode::LatestOnly => Self::with_capacity(1, 1),
+            SyncMode::Queue(capacity) => Self::with_capacity(capacity, capacity),
+        }
+    }
+
+    /// Bound the packets of each stream waiting for a match, the oldest one is dropped
+    /// when full so a stalled stream doesn't grow the other queue without limit.
+    /// Capacities of 0 are raised to 1.
+    pub fn with_capacity(color_capacity: usize, depth_capacity: usize) -> Self {
         Self {
-            color_packet: None,
-            depth_packet: VecDeque::with_capacity(10),
+            color_packet: VecDeque::with_capacity(color_capacity),
+            depth_packet: VecDeque::with_capacity(depth_capacity),
+            color_capacity: color_capacity.max(1),
+            depth_capacity: depth_capacity.max(1),
+            dropped_color_packets: 0,
+            dropped_depth_packets: 0,
+            timestamp_offset: 0,
+            deduplicate: false,
+            last_color_sequence: None,
+            last_depth_sequence: None,
+            duplicate_packets: 0,
         }
     }
 
+    pub fn color_capacity(&self) -> usize {
+        self.color_capacity
+    }
+
+    pub fn depth_capacity(&self) -> usize {
+        self.depth_capacity
+    }
+
+    /// Number of depth packets dropped because the queue was full,
+    /// a growing count means the color stream or the consumer is starving.
+    pub fn dropped(&self) -> u64 {
+        self.dropped_depth_packets
+    }
+
+    /// Number of color packets overwritten before being paired.
+    pub fn dropped_color(&self) -> u64 {
+        self.dropped_color_packets
+    }
+
+    /// Drop packets with the same sequence as the previous packet of their stream,
+    /// USB stalls can deliver the same frame twice.
+    pub fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.deduplicate = deduplicate;
+    }
+
+    pub fn deduplicate(&self) -> bool {
+        self.deduplicate
+    }
+
+    /// Number of duplicate color and depth packets dropped.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicate_packets
+    }
+
+    /// Set the offset added to depth timestamps to bring them in the color clock domain,
+    /// for when a constant offset between the two streams has been measured.
+    pub fn set_timestamp_offset(&mut self, timestamp_offset: i64) {
+        self.timestamp_offset = timestamp_offset;
+    }
+
+    pub fn timestamp_offset(&self) -> i64 {
+        self.timestamp_offset
+    }
+
+    /// Convert a depth timestamp to the color clock domain.
+    pub fn depth_to_color_timestamp(&self, timestamp: u32) -> i64 {
+        timestamp as i64 + self.timestamp_offset
+    }
+
     pub fn push_color_packet(&mut self, color_packet: ColorPacket) {
-        self.color_packet = Some(color_packet);
+        if self.is_duplicate(color_packet.sequence, true) {
+            return;
+        }
+
+        if self.color_packet.len() >= self.color_capacity {
+            self.color_packet.pop_front();
+            self.dropped_color_packets += 1;
+        }
+
+        self.color_packet.push_back(color_packet);
     }
 
     pub fn push_depth_packet(&mut self, depth_packet: DepthPacket) {
+        if self.is_duplicate(depth_packet.sequence, false) {
+            return;
+        }
+
+        if self.depth_packet.len() >= self.depth_capacity {
+            self.depth_packet.pop_front();
+            self.dropped_depth_packets += 1;
+        }
+
         self.depth_packet.push_back(depth_packet);
     }
 
+    /// Check a packet against the previous sequence of its stream, counting duplicates.
+    fn is_duplicate(&mut self, sequence: u32, color: bool) -> bool {
+        let last_sequence = match color {
+            true => &mut self.last_color_sequence,
+            false => &mut self.last_depth_sequence,
+        };
+        let duplicate = self.deduplicate && *last_sequence == Some(sequence);
+
+        *last_sequence = Some(sequence);
+        self.duplicate_packets += duplicate as u64;
+
+        duplicate
+    }
+
     pub fn poll_packets(&mut self) -> Option<(ColorPacket, DepthPacket)> {
-        if let Some(color_packet) = self.color_packet.take() {
-            if let Some(depth_packet_position) = self
-                .depth_packet
-                .iter()
-                .position(|depth_packet| depth_packet.timestamp > color_packet.timestamp)
-            {
+        if let Some(color_packet) = self.color_packet.front() {
+            if let Some(depth_packet_position) = self.depth_packet.iter().position(|depth_packet| {
+                self.depth_to_color_timestamp(depth_packet.timestamp)
+                    > color_packet.timestamp as i64
+            }) {
                 self.depth_packet.drain(..depth_packet_position);
 
-                return Some((color_packet, self.depth_packet.pop_front().unwrap()));
+                return Some((
+                    self.color_packet.pop_front().unwrap(),
+                    self.depth_packet.pop_front().unwrap(),
+                ));
             }
-
-            self.color_packet = Some(color_packet);
         }
 
         return None;
     }
 
     pub fn clear(&mut self) {
-        self.color_packet = None;
+        self.color_packet.clear();
         self.depth_packet.clear();
+        self.last_color_sequence = None;
+        self.last_depth_sequence = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color_packet(timestamp: u32) -> ColorPacket {
+        ColorPacket {
+            sequence: 0,
+            timestamp,
+            exposure: 0.0,
+            gain: 0.0,
+            gamma: 0.0,
+            jpeg_buffer: Vec::new(),
+        }
+    }
+
+    fn depth_packet(timestamp: u32) -> DepthPacket {
+        DepthPacket {
+            sequence: 0,
+            timestamp,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn poll_timestamps(packet_sync: &mut PacketSync) -> Option<(u32, u32)> {
+        packet_sync
+            .poll_packets()
+            .map(|(color_packet, depth_packet)| (color_packet.timestamp, depth_packet.timestamp))
+    }
+
+    #[test]
+    fn depth_before_color() {
+        let mut packet_sync = PacketSync::new();
+
+        packet_sync.push_depth_packet(depth_packet(110));
+        packet_sync.push_color_packet(color_packet(100));
+
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((100, 110)));
+        assert_eq!(poll_timestamps(&mut packet_sync), None);
+    }
+
+    #[test]
+    fn depth_after_color() {
+        let mut packet_sync = PacketSync::new();
+
+        packet_sync.push_color_packet(color_packet(100));
+
+        assert_eq!(poll_timestamps(&mut packet_sync), None);
+
+        packet_sync.push_depth_packet(depth_packet(105));
+
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((100, 105)));
+    }
+
+    #[test]
+    fn depth_straddling_color() {
+        let mut packet_sync = PacketSync::new();
+
+        for timestamp in [90, 95, 105, 110] {
+            packet_sync.push_depth_packet(depth_packet(timestamp));
+        }
+
+        packet_sync.push_color_packet(color_packet(100));
+
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((100, 105)));
+
+        // the older depth packets were drained, only the last one is left
+        packet_sync.push_color_packet(color_packet(90));
+
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((90, 110)));
+        assert_eq!(poll_timestamps(&mut packet_sync), None);
+    }
+
+    #[test]
+    fn no_matching_depth() {
+        let mut packet_sync = PacketSync::new();
+
+        packet_sync.push_depth_packet(depth_packet(80));
+        packet_sync.push_depth_packet(depth_packet(100));
+        packet_sync.push_color_packet(color_packet(100));
+
+        assert_eq!(poll_timestamps(&mut packet_sync), None);
+
+        // the color packet is kept until a later depth packet arrives
+        packet_sync.push_depth_packet(depth_packet(101));
+
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((100, 101)));
+    }
+
+    #[test]
+    fn timestamp_offset() {
+        let mut packet_sync = PacketSync::new();
+
+        packet_sync.set_timestamp_offset(-10);
+        packet_sync.push_depth_packet(depth_packet(105));
+        packet_sync.push_depth_packet(depth_packet(115));
+        packet_sync.push_color_packet(color_packet(100));
+
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((100, 115)));
+    }
+
+    #[test]
+    fn latest_only() {
+        let mut packet_sync = PacketSync::with_mode(SyncMode::LatestOnly);
+
+        packet_sync.push_color_packet(color_packet(100));
+        packet_sync.push_color_packet(color_packet(130));
+
+        for timestamp in [110, 120, 140] {
+            packet_sync.push_depth_packet(depth_packet(timestamp));
+        }
+
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((130, 140)));
+        assert_eq!(packet_sync.dropped_color(), 1);
+        assert_eq!(packet_sync.dropped(), 2);
+    }
+
+    #[test]
+    fn transient_errors() {
+        use nusb::transfer::TransferError;
+
+        assert!(Error::from(io::Error::from(io::ErrorKind::TimedOut)).is_transient());
+        assert!(!Error::from(TransferError::Stall).is_transient());
+        assert!(Error::from(TransferError::Cancelled).is_transient());
+        assert!(!Error::from(TransferError::Disconnected).is_transient());
+        assert!(!Error::from(io::Error::from(io::ErrorKind::NotFound)).is_transient());
+        assert!(!Error::NoDevice.is_transient());
+    }
+
+    #[test]
+    fn usb_error_counters() {
+        use nusb::transfer::TransferError;
+
+        let mut counters = UsbErrorCounters::default();
+
+        counters.record(&io::Error::from(io::ErrorKind::TimedOut).into());
+        counters.record(&TransferError::Stall.into());
+        counters.record(&TransferError::Fault.into());
+        counters.record(&Error::Receive(4, 16));
+        counters.record(&Error::PrematureComplete);
+
+        assert_eq!(
+            counters,
+            UsbErrorCounters {
+                stalls: 1,
+                timeouts: 1,
+                short_reads: 1,
+                other: 1,
+            }
+        );
+
+        counters.reset();
+
+        assert_eq!(counters, UsbErrorCounters::default());
+    }
+
+    #[test]
+    fn deduplicate() {
+        let mut packet_sync = PacketSync::with_mode(SyncMode::Queue(4));
+
+        packet_sync.set_deduplicate(true);
+
+        for (sequence, timestamp) in [(1, 100), (1, 100), (2, 130)] {
+            packet_sync.push_color_packet(ColorPacket {
+                sequence,
+                ..color_packet(timestamp)
+            });
+        }
+        for (sequence, timestamp) in [(1, 110), (1, 110), (2, 140)] {
+            packet_sync.push_depth_packet(DepthPacket {
+                sequence,
+                ..depth_packet(timestamp)
+            });
+        }
+
+        assert_eq!(packet_sync.duplicates(), 2);
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((100, 110)));
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((130, 140)));
+        assert_eq!(poll_timestamps(&mut packet_sync), None);
+    }
+
+    #[test]
+    fn queue() {
+        let mut packet_sync = PacketSync::with_mode(SyncMode::Queue(4));
+
+        packet_sync.push_color_packet(color_packet(100));
+        packet_sync.push_color_packet(color_packet(130));
+
+        for timestamp in [110, 120, 140] {
+            packet_sync.push_depth_packet(depth_packet(timestamp));
+        }
+
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((100, 110)));
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((130, 140)));
+        assert_eq!(poll_timestamps(&mut packet_sync), None);
+    }
+
+    #[test]
+    fn depth_capacity() {
+        let mut packet_sync = PacketSync::with_capacity(1, 2);
+
+        for timestamp in [100, 110, 120] {
+            packet_sync.push_depth_packet(depth_packet(timestamp));
+        }
+
+        packet_sync.push_color_packet(color_packet(0));
+
+        assert_eq!(packet_sync.dropped(), 1);
+        assert_eq!(poll_timestamps(&mut packet_sync), Some((0, 110)));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a connected Kinect"]
+    async fn reopen_after_drop() {
+        let mut device = DeviceEnumerator::open_default(true).await.unwrap();
+
+        device.start().await.unwrap();
+        drop(device);
+
+        // no reset, the drop must have stopped the streams
+        let mut device = DeviceEnumerator::open_default(false).await.unwrap();
+
+        device.start().await.unwrap();
+        device.next_depth_packet().await.unwrap();
+        device.close().await.unwrap();
     }
 }
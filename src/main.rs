@@ -3,13 +3,12 @@ use std::{error::Error, fs::write};
 use kinect_one::{
     processor::{
         color::{ColorSpace, MozColorProcessor},
-        depth::{DepthProcessorTrait, OpenCLDepthProcessor},
-        ProcessTrait, Registration,
+        depth::{first_opencl_device, DepthProcessorTrait, OpenCLDepthProcessor},
+        process_rgbd, Registration,
     },
-    DeviceEnumerator, PacketSync, DEPTH_HEIGHT, DEPTH_SIZE, DEPTH_WIDTH,
+    DeviceEnumerator, PacketSync,
 };
 use mozjpeg::Compress;
-use ocl::{Device, Platform};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -21,13 +20,13 @@ async fn main() -> Result<(), Box<dyn Error>> {
     device.start().await?;
     println!("Started");
 
-    let mut registration = Registration::new();
-
-    registration.set_ir_params(device.get_ir_params());
-    registration.set_color_params(device.get_color_params());
+    let registration = Registration::builder()
+        .ir_params(device.get_ir_params())
+        .color_params(device.get_color_params())
+        .build()?;
 
     let color_processor = MozColorProcessor::new(ColorSpace::RGB, false, false);
-    let mut depth_processor = OpenCLDepthProcessor::new(Device::first(Platform::first()?)?)?;
+    let mut depth_processor = OpenCLDepthProcessor::new(first_opencl_device()?)?;
 
     depth_processor.set_p0_tables(device.get_p0_tables())?;
     depth_processor.set_ir_params(device.get_ir_params())?;
@@ -45,30 +44,23 @@ async fn main() -> Result<(), Box<dyn Error>> {
         if let Some((color_packet, depth_packet)) = packet_sync.poll_packets() {
             println!("{} {}", color_packet.timestamp, depth_packet.timestamp);
 
-            let color_frame = color_packet.process(&color_processor).await?;
-            let depth_frame = depth_packet.process(&depth_processor).await?.1;
+            let (color_frame, _, depth_frame) = process_rgbd(
+                color_packet,
+                depth_packet,
+                &color_processor,
+                &depth_processor,
+            )
+            .await?;
 
-            let (registered_frame, undistorted_frame) =
+            let (registered_frame, _) =
                 registration.undistort_depth_and_color(&color_frame, &depth_frame, true);
 
             let mut comp = Compress::new(mozjpeg::ColorSpace::JCS_RGB);
 
-            comp.set_size(depth_frame.width, depth_frame.height);
+            comp.set_size(registered_frame.width, registered_frame.height);
 
             let mut comp = comp.start_compress(Vec::new())?;
 
-            let mut buffer = Vec::with_capacity(DEPTH_SIZE * 3);
-
-            for y in 0..DEPTH_HEIGHT {
-                for x in 0..DEPTH_WIDTH {
-                    buffer.extend(
-                        registration
-                            .point_to_xyz_pixel(&undistorted_frame, &registered_frame, x, y)
-                            .3,
-                    );
-                }
-            }
-
             comp.write_scanlines(&registered_frame.buffer)?;
 
             write("t.jpeg", comp.finish()?)?;
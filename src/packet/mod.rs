@@ -1,6 +1,8 @@
 use std::fmt::{self, Debug};
 
-use crate::processor::ProcessTrait;
+use crate::{
+    processor::ProcessTrait, DEPTH_HEIGHT, DEPTH_SUBFRAME_COUNT, DEPTH_SUBFRAME_SIZE, DEPTH_WIDTH,
+};
 
 pub mod parser;
 
@@ -10,9 +12,70 @@ pub struct DepthPacket {
     pub sequence: u32,
     pub timestamp: u32,
     /// Depth data.
+    ///
+    /// [`DEPTH_SUBFRAME_COUNT`] sub images of [`DEPTH_SUBFRAME_SIZE`] (298496) bytes each, one after the other.
+    /// Sub images 0 to 8 are the 3 phases of the 3 modulation frequencies, in that order,
+    /// the last one isn't used by the processors.
+    ///
+    /// A sub image holds 424 rows of 352 little endian 16 bits words, with 512 pixels of 11 bits packed in each row.
+    /// The pixels of a row are split in 4 groups of 128, pixel `x` starting at bit `((x / 4) + (x % 4) * 128) * 11`.
+    /// The rows are ordered from the middle of the sensor outwards, see [`DepthPacket::raw_measurement`].
+    ///
+    /// The 11 bits values index the lookup table computed by
+    /// [`DepthProcessorTrait::set_ir_params`](crate::processor::depth::DepthProcessorTrait::set_ir_params)
+    /// to get the signed measurements.
     pub buffer: Vec<u8>,
 }
 
+impl DepthPacket {
+    // 16 bits words per row of a sub image
+    const ROW_WORDS: usize = 352;
+
+    /// Number of complete sub images in the buffer, [`DEPTH_SUBFRAME_COUNT`] for parsed packets.
+    pub fn num_subframes(&self) -> usize {
+        (self.buffer.len() / DEPTH_SUBFRAME_SIZE).min(DEPTH_SUBFRAME_COUNT)
+    }
+
+    /// Packed data of a sub image, `None` if `index` is past [`DepthPacket::num_subframes`].
+    pub fn subframe(&self, index: usize) -> Option<&[u8]> {
+        if index >= DEPTH_SUBFRAME_COUNT {
+            return None;
+        }
+
+        self.buffer
+            .get(index * DEPTH_SUBFRAME_SIZE..(index + 1) * DEPTH_SUBFRAME_SIZE)
+    }
+
+    /// Get the 11 bits raw value of a sub image pixel,
+    /// in the orientation of the processed frames.
+    pub fn raw_measurement(&self, subframe: usize, x: usize, y: usize) -> Option<u16> {
+        if x >= DEPTH_WIDTH || y >= DEPTH_HEIGHT {
+            return None;
+        }
+
+        let data = self.subframe(subframe)?;
+
+        // the sensor is read upside down, from the middle rows outwards
+        let y = DEPTH_HEIGHT - 1 - y;
+        let row = if y < DEPTH_HEIGHT / 2 {
+            y + DEPTH_HEIGHT / 2
+        } else {
+            DEPTH_HEIGHT - 1 - y
+        };
+        let bit = ((x >> 2) + ((x & 0x3) << 7)) * 11;
+        let word_index = row * Self::ROW_WORDS + bit / 16;
+        let shift = bit % 16;
+        let word = |index: usize| {
+            data.get(index * 2..index * 2 + 2)
+                .map_or(0, |bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as u32)
+        };
+
+        Some(
+            (((word(word_index) >> shift) | (word(word_index + 1) << (16 - shift))) & 0x7ff) as u16,
+        )
+    }
+}
+
 impl ProcessTrait for DepthPacket {}
 
 impl Debug for DepthPacket {
@@ -42,17 +42,30 @@ impl ReadUnaligned for RawColorPacketFooter {}
 
 pub struct ColorStreamParser {
     memory: Vec<u8>,
+    last_sequence: Option<u32>,
 }
 
 impl ColorStreamParser {
-    const CAPACITY: usize = 2 * 1024 * 1024;
+    /// Largest packet, bigger ones are dropped.
+    pub(crate) const CAPACITY: usize = 2 * 1024 * 1024;
 
     pub fn new() -> Self {
         Self {
             memory: Vec::with_capacity(Self::CAPACITY),
+            last_sequence: None,
         }
     }
 
+    /// Sequence of the last emitted packet.
+    pub fn last_sequence(&self) -> Option<u32> {
+        self.last_sequence
+    }
+
+    /// Drop the packet being assembled, e.g. when one of its transfers failed.
+    pub fn reset(&mut self) {
+        self.memory.clear();
+    }
+
     pub fn parse(&mut self, buffer: Vec<u8>) -> Option<ColorPacket> {
         if self.memory.len() + buffer.len() > Self::CAPACITY {
             self.memory.clear();
@@ -119,6 +132,7 @@ impl ColorStreamParser {
         };
 
         self.memory.clear();
+        self.last_sequence = Some(packet.sequence);
 
         Some(packet)
     }
@@ -1,6 +1,9 @@
 use std::u32;
 
-use crate::{packet::DepthPacket, ReadUnaligned, DEPTH_SIZE};
+use crate::{
+    packet::DepthPacket, ReadUnaligned, DEPTH_PACKET_SIZE, DEPTH_SUBFRAME_COUNT,
+    DEPTH_SUBFRAME_SIZE,
+};
 
 /** Footer of a depth packet. */
 #[derive(Debug)]
@@ -23,11 +26,16 @@ pub struct DepthStreamParser {
     processed_packets: Option<u32>,
     current_sequence: u32,
     current_subsequence: u32,
+    last_sequence: Option<u32>,
 }
 
 impl DepthStreamParser {
-    const WORKER_CAPACITY: usize = DEPTH_SIZE * 11 / 8;
-    const MEMORY_CAPACITY: usize = Self::WORKER_CAPACITY * 10;
+    // a single sub image is assembled from several iso packets
+    const WORKER_CAPACITY: usize = DEPTH_SUBFRAME_SIZE;
+    // every sub image is needed to decode depth, so the whole packet is kept
+    const MEMORY_CAPACITY: usize = DEPTH_PACKET_SIZE;
+    // one bit per received sub image
+    const COMPLETE_SUBSEQUENCES: u32 = (1 << DEPTH_SUBFRAME_COUNT) - 1;
 
     pub fn new() -> Self {
         Self {
@@ -36,9 +44,15 @@ impl DepthStreamParser {
             processed_packets: None,
             current_sequence: 0,
             current_subsequence: 0,
+            last_sequence: None,
         }
     }
 
+    /// Sequence of the last emitted packet.
+    pub fn last_sequence(&self) -> Option<u32> {
+        self.last_sequence
+    }
+
     pub fn parse(&mut self, mut buffer: Vec<u8>) -> Option<DepthPacket> {
         if buffer.len() == 0 {
             self.worker.clear();
@@ -68,7 +82,18 @@ impl DepthStreamParser {
         let Some(footer) = footer else {
             return None;
         };
-        if footer.length as usize != self.worker.len() {
+        if footer.length as usize != self.worker.len()
+            || footer.subsequence as usize >= DEPTH_SUBFRAME_COUNT
+        {
+            self.worker.clear();
+            return None;
+        }
+
+        // a corrupt footer must never make the copy below panic
+        let memory_start = footer.subsequence as usize * self.worker.len();
+        let memory_range = memory_start..memory_start + self.worker.len();
+
+        if memory_range.end > Self::MEMORY_CAPACITY {
             self.worker.clear();
             return None;
         }
@@ -76,12 +101,13 @@ impl DepthStreamParser {
         let mut result = None;
 
         if self.current_sequence != footer.sequence {
-            if self.current_subsequence == 0x3ff {
+            if self.current_subsequence == Self::COMPLETE_SUBSEQUENCES {
                 result = Some(DepthPacket {
                     sequence: self.current_sequence,
                     timestamp: footer.timestamp,
                     buffer: self.memory.clone(),
                 });
+                self.last_sequence = Some(self.current_sequence);
 
                 if let Some(processed_packets) = self.processed_packets.as_mut() {
                     *processed_packets += 1;
@@ -104,12 +130,7 @@ impl DepthStreamParser {
 
         self.current_subsequence |= 1 << footer.subsequence;
 
-        if (footer.subsequence * footer.length) as usize <= Self::MEMORY_CAPACITY {
-            let memory_start = (footer.subsequence * footer.length) as usize;
-
-            self.memory[memory_start..memory_start + footer.length as usize]
-                .copy_from_slice(&self.worker);
-        }
+        self.memory[memory_range].copy_from_slice(&self.worker);
 
         self.worker.clear();
 
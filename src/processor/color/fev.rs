@@ -8,7 +8,10 @@ use fev::{
 };
 use winit::event_loop::EventLoop;
 
-use crate::{processor::ProcessorTrait, COLOR_HEIGHT, COLOR_WIDTH};
+use crate::{
+    processor::{ProcessingError, ProcessorTrait},
+    COLOR_HEIGHT, COLOR_WIDTH,
+};
 
 use super::{ColorFrame, ColorPacket, ColorSpace};
 
@@ -60,21 +63,25 @@ impl FeVColorProcessor {
 }
 
 impl ProcessorTrait<ColorPacket, ColorFrame> for FeVColorProcessor {
-    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, Box<dyn Error>> {
+    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, ProcessingError> {
         let mut jpeg_decode_session =
-            JpegDecodeSession::new(&self.display, COLOR_WIDTH as u16, COLOR_HEIGHT as u16)?;
+            JpegDecodeSession::new(&self.display, COLOR_WIDTH as u16, COLOR_HEIGHT as u16)
+                .map_err(ProcessingError::jpeg_decode)?;
         let mut image = Image::new(
             &self.display,
             ImageFormat::new(self.color_space),
             COLOR_WIDTH as u32,
             COLOR_HEIGHT as u32,
-        )?;
+        )
+        .map_err(ProcessingError::jpeg_decode)?;
 
         jpeg_decode_session
-            .decode(&input.jpeg_buffer)?
-            .copy_to_image(&mut image)?;
+            .decode(&input.jpeg_buffer)
+            .map_err(ProcessingError::jpeg_decode)?
+            .copy_to_image(&mut image)
+            .map_err(ProcessingError::jpeg_decode)?;
 
-        let mapping = image.map()?;
+        let mapping = image.map().map_err(ProcessingError::jpeg_decode)?;
 
         Ok(ColorFrame::from_packet(
             self.color_space.into(),
@@ -1,7 +1,13 @@
+//! JPEG decoders of the color stream.
+//!
+//! None of the backends decode a single image on multiple threads, throughput comes from
+//! decoding several packets concurrently, each processor can be shared between tasks.
+
 #[cfg(feature = "fev_color")]
 mod fev;
 #[cfg(feature = "moz_color")]
 mod moz;
+mod raw;
 #[cfg(feature = "turbo_color")]
 mod turbo;
 #[cfg(feature = "zen_color")]
@@ -9,12 +15,13 @@ mod zen;
 #[cfg(feature = "zune_color")]
 mod zune;
 
-use std::fmt;
+use std::{fmt, time::Duration};
 
 #[cfg(feature = "fev_color")]
 pub use fev::*;
 #[cfg(feature = "moz_color")]
 pub use moz::*;
+pub use raw::*;
 #[cfg(feature = "turbo_color")]
 pub use turbo::*;
 #[cfg(feature = "zen_color")]
@@ -23,7 +30,7 @@ pub use zen::*;
 pub use zune::*;
 
 pub use crate::packet::ColorPacket;
-use crate::{COLOR_HEIGHT, COLOR_WIDTH};
+use crate::{processor::ProcessingError, Error, COLOR_HEIGHT, COLOR_WIDTH};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ColorSpace {
@@ -64,21 +71,56 @@ pub struct ColorFrame {
     pub color_space: ColorSpace,
     pub width: usize,
     pub height: usize,
+    /// Bytes per row, `width * bytes per pixel` unless aligned with [`Self::with_row_alignment`].
+    pub stride: usize,
     pub buffer: Vec<u8>,
 
     pub sequence: u32,
     pub timestamp: u32,
+    /// Raw exposure of the packet footer, see [`Self::exposure_ms`].
     pub exposure: f32,
+    /// Raw gain of the packet footer, see [`Self::analog_gain`].
     pub gain: f32,
+    /// Raw gamma of the packet footer, from 1.0 with a clear view to about 6.4 when covered.
+    ///
+    /// Its meaning is unknown, it keeps rising once the exposure and the gain are at their maximum
+    /// so it's likely the digital gain or tone curve of the image processor.
     pub gamma: f32,
 }
 
 impl ColorFrame {
+    /// Exposure time of the frame in milliseconds.
+    ///
+    /// Assumed to be the pseudo-exposure time of
+    /// [`Device::get_color_exposure_time_ms`](crate::Device::get_color_exposure_time_ms):
+    /// it ranges from 0.5 with powerful light to about 60-66 when covered,
+    /// where the integration time is capped by the frame period at 15 fps.
+    pub fn exposure_ms(&self) -> f32 {
+        self.exposure
+    }
+
+    /// Same as [`Self::exposure_ms`] as a [`Duration`], zero if the exposure is negative,
+    /// infinite or NaN.
+    pub fn exposure_time(&self) -> Duration {
+        Duration::try_from_secs_f32(self.exposure / 1000.0).unwrap_or_default()
+    }
+
+    /// Analog gain multiplier of the frame, assumed to be the linear gain of
+    /// [`Device::get_color_analog_gain`](crate::Device::get_color_analog_gain).
+    ///
+    /// It goes from 1.0 with a clear view to about 1.5 when covered,
+    /// the exposure is raised first so it rarely gets higher.
+    pub fn analog_gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// 1920x1080 frame of contiguous rows, the buffer length isn't checked, see [`Self::from_decoded`].
     pub fn from_packet(color_space: ColorSpace, buffer: Vec<u8>, packet: &ColorPacket) -> Self {
         Self {
             color_space,
             width: COLOR_WIDTH,
             height: COLOR_HEIGHT,
+            stride: COLOR_WIDTH * color_space.bytes_per_pixel(),
             buffer,
             sequence: packet.sequence,
             timestamp: packet.timestamp,
@@ -87,6 +129,177 @@ impl ColorFrame {
             gamma: packet.gamma,
         }
     }
+
+    /// Same as [`Self::from_packet`] checking the decoded dimensions and buffer length first,
+    /// registration relies on 1920x1080 frames.
+    ///
+    /// [`ProcessingError::DimensionMismatch`] for other dimensions,
+    /// [`ProcessingError::BufferLength`] if the buffer is shorter than the rows.
+    pub fn from_decoded(
+        color_space: ColorSpace,
+        width: usize,
+        height: usize,
+        buffer: Vec<u8>,
+        packet: &ColorPacket,
+    ) -> Result<Self, ProcessingError> {
+        let stride = width * color_space.bytes_per_pixel();
+
+        Self::from_decoded_rows(color_space, width, height, stride, buffer, packet)
+    }
+
+    /// Same as [`Self::from_decoded`] for rows `stride` bytes apart.
+    fn from_decoded_rows(
+        color_space: ColorSpace,
+        width: usize,
+        height: usize,
+        stride: usize,
+        buffer: Vec<u8>,
+        packet: &ColorPacket,
+    ) -> Result<Self, ProcessingError> {
+        if width != COLOR_WIDTH || height != COLOR_HEIGHT {
+            return Err(ProcessingError::DimensionMismatch {
+                expected: (COLOR_WIDTH, COLOR_HEIGHT),
+                actual: (width, height),
+            });
+        }
+
+        Self::with_rows(color_space, width, height, stride, buffer, packet)
+    }
+
+    /// Same as [`Self::from_decoded`] for images of other dimensions, they can't be registered.
+    ///
+    /// [`ProcessingError::BufferLength`] if the buffer is shorter than the rows.
+    pub fn with_dimensions(
+        color_space: ColorSpace,
+        width: usize,
+        height: usize,
+        buffer: Vec<u8>,
+        packet: &ColorPacket,
+    ) -> Result<Self, ProcessingError> {
+        let stride = width * color_space.bytes_per_pixel();
+
+        Self::with_rows(color_space, width, height, stride, buffer, packet)
+    }
+
+    fn with_rows(
+        color_space: ColorSpace,
+        width: usize,
+        height: usize,
+        stride: usize,
+        buffer: Vec<u8>,
+        packet: &ColorPacket,
+    ) -> Result<Self, ProcessingError> {
+        if buffer.len() < stride * height {
+            return Err(ProcessingError::BufferLength {
+                expected: stride * height,
+                actual: buffer.len(),
+            });
+        }
+
+        Ok(Self {
+            width,
+            height,
+            stride,
+            ..Self::from_packet(color_space, buffer, packet)
+        })
+    }
+
+    /// Pads every row so the stride is a multiple of `alignment` bytes, as needed to upload
+    /// the buffer to a GPU texture, padding is filled with 0.
+    ///
+    /// Registration and conversions read the rows at the stride, aligned frames can be processed.
+    /// Frames of [`ColorSpace::Unknown`] are returned as is.
+    ///
+    /// [`Error::RowAlignment`] if `alignment` is 0.
+    pub fn with_row_alignment(mut self, alignment: usize) -> Result<Self, Error> {
+        if alignment == 0 {
+            return Err(Error::RowAlignment(alignment));
+        }
+
+        let row_length = self.width * self.color_space.bytes_per_pixel();
+        let stride = row_length.next_multiple_of(alignment);
+
+        if row_length != 0 && stride != self.stride {
+            let mut buffer = Vec::with_capacity(stride * self.height);
+
+            for row in self.buffer.chunks_exact(self.stride).take(self.height) {
+                buffer.extend_from_slice(&row[..row_length]);
+                buffer.resize(buffer.len() + (stride - row_length), 0);
+            }
+
+            self.buffer = buffer;
+            self.stride = stride;
+        }
+
+        Ok(self)
+    }
+
+    /// Bytes of the pixel at `x`, `y`, rows are `stride` bytes apart.
+    ///
+    /// # Panics
+    ///
+    /// If the pixel is outside of the frame.
+    pub fn pixel(&self, x: usize, y: usize) -> &[u8] {
+        let bytes_per_pixel = self.color_space.bytes_per_pixel();
+
+        &self.buffer[y * self.stride + x * bytes_per_pixel..][..bytes_per_pixel]
+    }
+
+    /// Pixels in row order, without the padding of aligned rows.
+    ///
+    /// Frames of [`ColorSpace::Unknown`] have no pixels.
+    pub fn pixels(&self) -> impl Iterator<Item = &[u8]> {
+        let bytes_per_pixel = self.color_space.bytes_per_pixel();
+        let row_length = self.width * bytes_per_pixel;
+
+        self.buffer
+            .chunks(self.stride.max(1))
+            .take(if bytes_per_pixel == 0 { 0 } else { self.height })
+            .flat_map(move |row| row[..row_length].chunks_exact(bytes_per_pixel))
+    }
+
+    /// Copies the frame into an [`image::DynamicImage`], BGR frames are swizzled to RGB
+    /// and padding bytes are dropped, YCbCr and unknown frames are an error.
+    #[cfg(feature = "image")]
+    pub fn to_image(&self) -> Result<image::DynamicImage, Error> {
+        use image::{DynamicImage, RgbImage, RgbaImage};
+
+        let (width, height) = (self.width as u32, self.height as u32);
+        let pixels = self.pixels();
+        let image = match self.color_space {
+            ColorSpace::RGB => {
+                RgbImage::from_raw(width, height, pixels.flatten().copied().collect())
+                    .map(DynamicImage::ImageRgb8)
+            }
+            ColorSpace::RGBA => {
+                RgbaImage::from_raw(width, height, pixels.flatten().copied().collect())
+                    .map(DynamicImage::ImageRgba8)
+            }
+            ColorSpace::RGBX => {
+                let buffer = pixels.flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]);
+
+                RgbImage::from_raw(width, height, buffer.collect()).map(DynamicImage::ImageRgb8)
+            }
+            ColorSpace::BGR | ColorSpace::BGRX => {
+                let buffer = pixels.flat_map(|pixel| [pixel[2], pixel[1], pixel[0]]);
+
+                RgbImage::from_raw(width, height, buffer.collect()).map(DynamicImage::ImageRgb8)
+            }
+            ColorSpace::BGRA => {
+                let buffer = pixels.flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]]);
+
+                RgbaImage::from_raw(width, height, buffer.collect()).map(DynamicImage::ImageRgba8)
+            }
+            ColorSpace::YCbCr | ColorSpace::Unknown => {
+                return Err(Error::UnsupportedColorSpace(self.color_space))
+            }
+        };
+
+        image.ok_or(Error::Processing(ProcessingError::BufferLength {
+            expected: self.stride * self.height,
+            actual: self.buffer.len(),
+        }))
+    }
 }
 
 impl fmt::Debug for ColorFrame {
@@ -95,6 +308,7 @@ impl fmt::Debug for ColorFrame {
             .field("color_space", &self.color_space)
             .field("width", &self.width)
             .field("height", &self.height)
+            .field("stride", &self.stride)
             .field("buffer_length", &self.buffer.len())
             .field("sequence", &self.sequence)
             .field("timestamp", &self.timestamp)
@@ -104,3 +318,95 @@ impl fmt::Debug for ColorFrame {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposure_time() {
+        let mut packet = ColorPacket {
+            sequence: 0,
+            timestamp: 0,
+            exposure: 62.5,
+            gain: 1.0,
+            gamma: 1.0,
+            jpeg_buffer: Vec::new(),
+        };
+
+        assert_eq!(
+            ColorFrame::from_packet(ColorSpace::RGB, Vec::new(), &packet).exposure_time(),
+            Duration::from_micros(62500)
+        );
+
+        for exposure in [-1.0, f32::INFINITY, f32::NAN] {
+            packet.exposure = exposure;
+
+            assert_eq!(
+                ColorFrame::from_packet(ColorSpace::RGB, Vec::new(), &packet).exposure_time(),
+                Duration::ZERO
+            );
+        }
+    }
+
+    #[test]
+    fn from_decoded_checks_buffer() {
+        let packet = ColorPacket {
+            sequence: 0,
+            timestamp: 0,
+            exposure: 0.0,
+            gain: 0.0,
+            gamma: 0.0,
+            jpeg_buffer: Vec::new(),
+        };
+        let size = COLOR_WIDTH * COLOR_HEIGHT * 3;
+
+        assert_eq!(
+            ColorFrame::from_decoded(
+                ColorSpace::RGB,
+                COLOR_WIDTH,
+                COLOR_HEIGHT,
+                vec![0; size],
+                &packet
+            )
+            .unwrap()
+            .stride,
+            COLOR_WIDTH * 3
+        );
+        assert!(matches!(
+            ColorFrame::from_decoded(ColorSpace::RGB, 16, 8, vec![0; 16 * 8 * 3], &packet),
+            Err(ProcessingError::DimensionMismatch {
+                actual: (16, 8),
+                ..
+            })
+        ));
+        assert!(matches!(
+            ColorFrame::from_decoded(ColorSpace::RGBA, COLOR_WIDTH, COLOR_HEIGHT, vec![0; size], &packet),
+            Err(ProcessingError::BufferLength { expected, actual })
+                if (expected, actual) == (COLOR_WIDTH * COLOR_HEIGHT * 4, size)
+        ));
+    }
+
+    #[test]
+    fn with_dimensions_checks_buffer() {
+        let packet = ColorPacket {
+            sequence: 0,
+            timestamp: 0,
+            exposure: 0.0,
+            gain: 0.0,
+            gamma: 0.0,
+            jpeg_buffer: Vec::new(),
+        };
+        let frame =
+            ColorFrame::with_dimensions(ColorSpace::BGRA, 4, 2, vec![0; 32], &packet).unwrap();
+
+        assert_eq!((frame.width, frame.height, frame.stride), (4, 2, 16));
+        assert!(matches!(
+            ColorFrame::with_dimensions(ColorSpace::BGRA, 4, 2, vec![0; 31], &packet),
+            Err(ProcessingError::BufferLength {
+                expected: 32,
+                actual: 31
+            })
+        ));
+    }
+}
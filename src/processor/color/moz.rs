@@ -1,8 +1,6 @@
-use std::error::Error;
-
 use mozjpeg::{DctMethod, Decompress};
 
-use crate::processor::ProcessorTrait;
+use crate::processor::{ProcessingError, ProcessorTrait};
 
 use super::{ColorFrame, ColorPacket, ColorSpace};
 
@@ -62,20 +60,27 @@ impl MozColorProcessor {
 }
 
 impl ProcessorTrait<ColorPacket, ColorFrame> for MozColorProcessor {
-    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, Box<dyn Error>> {
-        let mut decoder = Decompress::new_mem(&input.jpeg_buffer)?;
+    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, ProcessingError> {
+        let mut decoder =
+            Decompress::new_mem(&input.jpeg_buffer).map_err(ProcessingError::jpeg_decode)?;
 
         decoder.do_fancy_upsampling(self.fancy_upsampling);
         decoder.do_block_smoothing(self.block_smoothing);
         decoder.dct_method(self.dct_method);
 
-        let mut decoder = decoder.to_colorspace(self.color_space)?;
-        let buffer = decoder.read_scanlines()?;
+        let mut decoder = decoder
+            .to_colorspace(self.color_space)
+            .map_err(ProcessingError::jpeg_decode)?;
+        let buffer = decoder
+            .read_scanlines()
+            .map_err(ProcessingError::jpeg_decode)?;
 
-        Ok(ColorFrame::from_packet(
+        ColorFrame::from_decoded(
             decoder.color_space().into(),
+            decoder.width(),
+            decoder.height(),
             buffer,
             &input,
-        ))
+        )
     }
 }
@@ -0,0 +1,90 @@
+use std::error::Error;
+
+use crate::{
+    processor::{ProcessingError, ProcessorTrait},
+    COLOR_SIZE,
+};
+
+use super::{ColorFrame, ColorPacket, ColorSpace};
+
+/// Bring your own decoder color processor
+///
+/// Wraps a user supplied JPEG decoder (e.g. a hardware one) so its output can go through
+/// the same pipeline as the built-in decoders, Registration included.
+/// The decoder must output a 1920x1080 contiguous image in the returned color space,
+/// other buffer lengths are a [`ProcessingError::BufferLength`], unchecked for [`ColorSpace::Unknown`].
+pub struct RawColorProcessor<F>
+where
+    F: Fn(&[u8]) -> Result<(ColorSpace, Vec<u8>), Box<dyn Error>>,
+{
+    decoder: F,
+}
+
+impl<F> RawColorProcessor<F>
+where
+    F: Fn(&[u8]) -> Result<(ColorSpace, Vec<u8>), Box<dyn Error>>,
+{
+    /// # Arguments
+    ///
+    /// * `decoder` - Decode the JPEG data of a packet, returning the color space and pixels
+    pub fn new(decoder: F) -> Self {
+        Self { decoder }
+    }
+}
+
+impl<F> ProcessorTrait<ColorPacket, ColorFrame> for RawColorProcessor<F>
+where
+    F: Fn(&[u8]) -> Result<(ColorSpace, Vec<u8>), Box<dyn Error>>,
+{
+    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, ProcessingError> {
+        let (color_space, buffer) =
+            (self.decoder)(&input.jpeg_buffer).map_err(ProcessingError::JpegDecode)?;
+        let bytes_per_pixel = color_space.bytes_per_pixel();
+
+        if bytes_per_pixel != 0 && buffer.len() != COLOR_SIZE * bytes_per_pixel {
+            return Err(ProcessingError::BufferLength {
+                expected: COLOR_SIZE * bytes_per_pixel,
+                actual: buffer.len(),
+            });
+        }
+
+        Ok(ColorFrame::from_packet(color_space, buffer, &input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::COLOR_WIDTH;
+
+    fn packet() -> ColorPacket {
+        ColorPacket {
+            sequence: 0,
+            timestamp: 0,
+            exposure: 1.0,
+            gain: 1.0,
+            gamma: 1.0,
+            jpeg_buffer: vec![0xff, 0xd8, 0xff, 0xd9],
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_buffer_length() {
+        let processor = RawColorProcessor::new(|_| Ok((ColorSpace::RGB, vec![0; COLOR_SIZE * 3])));
+
+        assert_eq!(
+            processor.process(packet()).await.unwrap().stride,
+            COLOR_WIDTH * 3
+        );
+
+        let processor = RawColorProcessor::new(|_| Ok((ColorSpace::RGBA, vec![0; COLOR_SIZE * 3])));
+
+        assert!(matches!(
+            processor.process(packet()).await,
+            Err(ProcessingError::BufferLength {
+                expected,
+                actual,
+            }) if (expected, actual) == (COLOR_SIZE * 4, COLOR_SIZE * 3)
+        ));
+    }
+}
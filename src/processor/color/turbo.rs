@@ -2,7 +2,7 @@ use std::error::Error;
 
 use turbojpeg::{yuv_pixels_len, Decompressor, Image, PixelFormat, YuvImage};
 
-use crate::processor::ProcessorTrait;
+use crate::processor::{ProcessingError, ProcessorTrait};
 
 use super::{ColorFrame, ColorPacket, ColorSpace};
 
@@ -41,23 +41,41 @@ impl TryInto<Option<PixelFormat>> for ColorSpace {
 /// TurboJpeg color processor
 pub struct TurboColorProcessor {
     color_space: Option<PixelFormat>,
+    row_alignment: usize,
 }
 
 impl TurboColorProcessor {
     pub fn new(colorspace: ColorSpace) -> Result<Self, Box<dyn Error>> {
         Ok(Self {
             color_space: colorspace.try_into()?,
+            row_alignment: 1,
         })
     }
+
+    /// Decode rows padded to a multiple of `alignment` bytes, like
+    /// [`ColorFrame::with_row_alignment`] without the extra copy, ignored for YCbCr.
+    ///
+    /// [`crate::Error::RowAlignment`] if `alignment` is 0.
+    pub fn with_row_alignment(mut self, alignment: usize) -> Result<Self, Box<dyn Error>> {
+        if alignment == 0 {
+            return Err(crate::Error::RowAlignment(alignment).into());
+        }
+
+        self.row_alignment = alignment;
+
+        Ok(self)
+    }
 }
 
 impl ProcessorTrait<ColorPacket, ColorFrame> for TurboColorProcessor {
-    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, Box<dyn Error>> {
-        let mut decompressor = Decompressor::new()?;
-        let header = decompressor.read_header(&input.jpeg_buffer)?;
+    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, ProcessingError> {
+        let mut decompressor = Decompressor::new().map_err(ProcessingError::jpeg_decode)?;
+        let header = decompressor
+            .read_header(&input.jpeg_buffer)
+            .map_err(ProcessingError::jpeg_decode)?;
 
-        let pixels = if let Some(color_space) = self.color_space {
-            let pitch = header.width * color_space.size();
+        let (pixels, stride) = if let Some(color_space) = self.color_space {
+            let pitch = (header.width * color_space.size()).next_multiple_of(self.row_alignment);
             let mut image = Image {
                 pixels: vec![0; header.height * pitch],
                 width: header.width,
@@ -66,13 +84,15 @@ impl ProcessorTrait<ColorPacket, ColorFrame> for TurboColorProcessor {
                 format: color_space,
             };
 
-            decompressor.decompress(&input.jpeg_buffer, image.as_deref_mut())?;
+            decompressor
+                .decompress(&input.jpeg_buffer, image.as_deref_mut())
+                .map_err(ProcessingError::jpeg_decode)?;
 
-            image.pixels
+            (image.pixels, pitch)
         } else {
             let align = 4;
-            let yuv_pixels_len =
-                yuv_pixels_len(header.width, align, header.height, header.subsamp)?;
+            let yuv_pixels_len = yuv_pixels_len(header.width, align, header.height, header.subsamp)
+                .map_err(ProcessingError::jpeg_decode)?;
             let mut yuv_image = YuvImage {
                 pixels: vec![0; yuv_pixels_len],
                 width: header.width,
@@ -81,15 +101,21 @@ impl ProcessorTrait<ColorPacket, ColorFrame> for TurboColorProcessor {
                 subsamp: header.subsamp,
             };
 
-            decompressor.decompress_to_yuv(&input.jpeg_buffer, yuv_image.as_deref_mut())?;
+            decompressor
+                .decompress_to_yuv(&input.jpeg_buffer, yuv_image.as_deref_mut())
+                .map_err(ProcessingError::jpeg_decode)?;
 
-            yuv_image.pixels
+            // planar, the stride is the one of the luma plane
+            (yuv_image.pixels, header.width.next_multiple_of(align))
         };
 
-        Ok(ColorFrame::from_packet(
+        ColorFrame::from_decoded_rows(
             self.color_space.into(),
+            header.width,
+            header.height,
+            stride,
             pixels,
             &input,
-        ))
+        )
     }
 }
@@ -3,7 +3,10 @@ use std::error::Error;
 use enough::Unstoppable;
 use zenjpeg::decoder::{ChromaUpsampling, Decoder, PixelFormat};
 
-use crate::{processor::ProcessorTrait, COLOR_HEIGHT, COLOR_WIDTH};
+use crate::{
+    processor::{ProcessingError, ProcessorTrait},
+    COLOR_HEIGHT, COLOR_WIDTH,
+};
 
 use super::{ColorFrame, ColorPacket, ColorSpace};
 
@@ -60,13 +63,18 @@ impl ZenColorProcessor {
 }
 
 impl ProcessorTrait<ColorPacket, ColorFrame> for ZenColorProcessor {
-    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, Box<dyn Error>> {
-        let decoder_result = self.decoder.decode(&input.jpeg_buffer, Unstoppable)?;
+    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, ProcessingError> {
+        let decoder_result = self
+            .decoder
+            .decode(&input.jpeg_buffer, Unstoppable)
+            .map_err(ProcessingError::jpeg_decode)?;
 
-        Ok(ColorFrame::from_packet(
+        ColorFrame::from_decoded(
             decoder_result.format().into(),
+            decoder_result.width() as usize,
+            decoder_result.height() as usize,
             decoder_result.into_pixels_u8().unwrap_or_default(),
             &input,
-        ))
+        )
     }
 }
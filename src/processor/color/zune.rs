@@ -5,7 +5,10 @@ use zune_jpeg::{
     JpegDecoder,
 };
 
-use crate::{processor::ProcessorTrait, COLOR_HEIGHT, COLOR_WIDTH};
+use crate::{
+    processor::{ProcessingError, ProcessorTrait},
+    COLOR_HEIGHT, COLOR_WIDTH,
+};
 
 use super::{ColorFrame, ColorPacket, ColorSpace};
 
@@ -39,36 +42,163 @@ impl TryInto<colorspace::ColorSpace> for ColorSpace {
     }
 }
 
+/// Decoding options of [`ZuneColorProcessor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZuneOptions {
+    /// Larger images are rejected.
+    pub max_width: usize,
+    /// Larger images are rejected.
+    pub max_height: usize,
+    /// Use the platform specific code paths, see [`DecoderOptions::new_fast`].
+    pub fast: bool,
+    /// Reject non conforming images instead of decoding what can be decoded.
+    pub strict: bool,
+    /// Reject images that aren't 1920x1080, as registration expects, see [`ColorFrame::from_decoded`].
+    pub full_resolution: bool,
+}
+
+impl Default for ZuneOptions {
+    fn default() -> Self {
+        Self {
+            max_width: COLOR_WIDTH,
+            max_height: COLOR_HEIGHT,
+            fast: true,
+            strict: false,
+            full_resolution: true,
+        }
+    }
+}
+
 /// ZuneJpeg color processor
-pub struct ZuneColorProcessor(colorspace::ColorSpace);
+pub struct ZuneColorProcessor {
+    color_space: colorspace::ColorSpace,
+    options: ZuneOptions,
+}
 
 impl ZuneColorProcessor {
     pub fn new(color_space: ColorSpace) -> Result<Self, Box<dyn Error>> {
-        Ok(Self(color_space.try_into()?))
+        Self::with_options(color_space, ZuneOptions::default())
+    }
+
+    pub fn with_options(
+        color_space: ColorSpace,
+        options: ZuneOptions,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            color_space: color_space.try_into()?,
+            options,
+        })
+    }
+
+    pub fn options(&self) -> &ZuneOptions {
+        &self.options
     }
 }
 
 impl ProcessorTrait<ColorPacket, ColorFrame> for ZuneColorProcessor {
-    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, Box<dyn Error>> {
+    async fn process(&self, input: ColorPacket) -> Result<ColorFrame, ProcessingError> {
         let reader = Cursor::new(&input.jpeg_buffer);
         let mut decoder = JpegDecoder::new(reader);
 
-        decoder.set_options(
+        let options = if self.options.fast {
             DecoderOptions::new_fast()
-                .set_max_height(COLOR_HEIGHT)
-                .set_max_width(COLOR_WIDTH)
-                .jpeg_set_out_colorspace(self.0),
+        } else {
+            DecoderOptions::new_safe()
+        };
+
+        decoder.set_options(
+            options
+                .set_max_height(self.options.max_height)
+                .set_max_width(self.options.max_width)
+                .set_strict_mode(self.options.strict)
+                .jpeg_set_out_colorspace(self.color_space),
         );
 
-        let buffer = decoder.decode()?;
+        let buffer = decoder.decode().map_err(ProcessingError::jpeg_decode)?;
+        let (width, height) = decoder.dimensions().expect("Expected dimensions");
+        let color_space = decoder
+            .output_colorspace()
+            .expect("Expected colorspace")
+            .into();
+
+        if self.options.full_resolution {
+            ColorFrame::from_decoded(color_space, width, height, buffer, &input)
+        } else {
+            ColorFrame::with_dimensions(color_space, width, height, buffer, &input)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 16x8 mid-gray baseline JPEG, 3 components of DC only blocks
+    const JPEG_16X8: &[u8] = &[
+        0xff, 0xd8, // SOI
+        0xff, 0xdb, 0x00, 0x43, 0x00, // DQT, table 0 of ones
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, //
+        0xff, 0xc0, 0x00, 0x11, 0x08, 0x00, 0x08, 0x00, 0x10, 0x03, // SOF0, 16x8
+        0x01, 0x11, 0x00, 0x02, 0x11, 0x00, 0x03, 0x11, 0x00, //
+        0xff, 0xc4, 0x00, 0x14, 0x00, // DHT, DC table 0, single code for category 0
+        0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, //
+        0xff, 0xc4, 0x00, 0x14, 0x10, // DHT, AC table 0, single code for EOB
+        0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, //
+        0xff, 0xda, 0x00, 0x0c, 0x03, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x00, 0x3f,
+        0x00, // SOS
+        0x00, 0x0f, // 2 MCUs of 3 blocks, DC 0 and EOB
+        0xff, 0xd9, // EOI
+    ];
+
+    fn packet() -> ColorPacket {
+        ColorPacket {
+            sequence: 0,
+            timestamp: 0,
+            exposure: 0.0,
+            gain: 0.0,
+            gamma: 0.0,
+            jpeg_buffer: JPEG_16X8.to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn decodes_other_dimensions() {
+        let processor = ZuneColorProcessor::with_options(
+            ColorSpace::RGB,
+            ZuneOptions {
+                full_resolution: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let frame = processor.process(packet()).await.unwrap();
+
+        assert_eq!((frame.width, frame.height), (16, 8));
+        assert_eq!(frame.buffer.len(), 16 * 8 * 3);
+
+        let processor = ZuneColorProcessor::new(ColorSpace::RGB).unwrap();
+
+        assert!(matches!(
+            processor.process(packet()).await,
+            Err(ProcessingError::DimensionMismatch {
+                actual: (16, 8),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn corrupted_jpeg_is_a_decode_error() {
+        let processor = ZuneColorProcessor::new(ColorSpace::RGB).unwrap();
+        let mut packet = packet();
+
+        packet.jpeg_buffer.truncate(20);
 
-        Ok(ColorFrame::from_packet(
-            decoder
-                .output_colorspace()
-                .expect("Expected colorspace")
-                .into(),
-            buffer,
-            &input,
-        ))
+        assert!(matches!(
+            processor.process(packet).await,
+            Err(ProcessingError::JpegDecode(_))
+        ));
     }
 }
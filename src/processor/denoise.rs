@@ -0,0 +1,245 @@
+//! Depth guided denoising of registered color frames.
+//!
+//! Neighboring color pixels are averaged with a joint bilateral filter, weighted by their
+//! distance, their color difference and their depth difference, so noise is smoothed
+//! within a surface without blurring across depth discontinuities.
+
+use super::{color::ColorFrame, depth::DepthFrame, ProcessingError, ProcessorTrait};
+
+/// Registered color frame and the undistorted depth frame it's aligned to,
+/// as output by [`Registration::undistort_depth_and_color`](super::Registration::undistort_depth_and_color).
+#[derive(Clone)]
+pub struct RgbdFrame {
+    pub color: ColorFrame,
+    pub depth: DepthFrame,
+}
+
+/// Joint bilateral color denoising guided by depth.
+///
+/// Only the first 3 channels are filtered, alpha or padding is copied as is.
+/// Pixels without valid depth are left untouched and never contribute to their neighbors.
+pub struct DepthGuidedDenoiser {
+    radius: usize,
+    color_sigma: f32,
+    depth_sigma: f32,
+    spatial_weights: Vec<f32>,
+}
+
+impl DepthGuidedDenoiser {
+    /// # Arguments
+    ///
+    /// * `radius` - Half size of the filter window (pixel)
+    /// * `spatial_sigma` - Standard deviation of the distance weight (pixel)
+    /// * `color_sigma` - Standard deviation of the color difference weight (0 - 255)
+    /// * `depth_sigma` - Standard deviation of the depth difference weight (millimeter)
+    ///
+    /// # Panics
+    ///
+    /// If a standard deviation isn't strictly positive, the weights would be NaN.
+    pub fn new(radius: usize, spatial_sigma: f32, color_sigma: f32, depth_sigma: f32) -> Self {
+        assert!(
+            spatial_sigma > 0.0 && color_sigma > 0.0 && depth_sigma > 0.0,
+            "standard deviations must be strictly positive"
+        );
+
+        let size = radius * 2 + 1;
+        let spatial_weights = (0..size * size)
+            .map(|index| {
+                let dx = (index % size) as f32 - radius as f32;
+                let dy = (index / size) as f32 - radius as f32;
+
+                gaussian(dx * dx + dy * dy, spatial_sigma)
+            })
+            .collect();
+
+        Self {
+            radius,
+            color_sigma,
+            depth_sigma,
+            spatial_weights,
+        }
+    }
+
+    fn denoise(&self, input: &RgbdFrame) -> Result<ColorFrame, ProcessingError> {
+        let RgbdFrame { color, depth } = input;
+        let bytes_per_pixel = color.color_space.bytes_per_pixel();
+
+        if bytes_per_pixel < 3 {
+            return Err(ProcessingError::UnsupportedColorSpace(color.color_space));
+        }
+        if color.width != depth.width || color.height != depth.height {
+            // the color frame must be registered first
+            return Err(ProcessingError::DimensionMismatch {
+                expected: (depth.width, depth.height),
+                actual: (color.width, color.height),
+            });
+        }
+
+        let (width, height) = (depth.width, depth.height);
+        let size = self.radius * 2 + 1;
+        let mut output = color.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let index = x + y * width;
+                let z = depth.buffer[index];
+
+                if !is_valid(z) {
+                    continue;
+                }
+
+                let pixel = &color.pixel(x, y)[..3];
+                let mut sum = [0.0; 3];
+                let mut weight_sum = 0.0;
+
+                for ny in y.saturating_sub(self.radius)..(y + self.radius + 1).min(height) {
+                    for nx in x.saturating_sub(self.radius)..(x + self.radius + 1).min(width) {
+                        let neighbor_index = nx + ny * width;
+                        let neighbor_z = depth.buffer[neighbor_index];
+
+                        if !is_valid(neighbor_z) {
+                            continue;
+                        }
+
+                        let neighbor = &color.pixel(nx, ny)[..3];
+                        let color_distance: f32 = pixel
+                            .iter()
+                            .zip(neighbor)
+                            .map(|(a, b)| (*a as f32 - *b as f32).powi(2))
+                            .sum();
+                        let weight = self.spatial_weights
+                            [(nx + self.radius - x) + (ny + self.radius - y) * size]
+                            * gaussian(color_distance, self.color_sigma)
+                            * gaussian((neighbor_z - z).powi(2), self.depth_sigma);
+
+                        for (sum, value) in sum.iter_mut().zip(neighbor) {
+                            *sum += weight * *value as f32;
+                        }
+                        weight_sum += weight;
+                    }
+                }
+
+                // the pixel itself always has a weight of 1
+                for (value, sum) in output.buffer[y * output.stride + x * bytes_per_pixel..][..3]
+                    .iter_mut()
+                    .zip(sum)
+                {
+                    *value = (sum / weight_sum).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+impl Default for DepthGuidedDenoiser {
+    fn default() -> Self {
+        Self::new(2, 1.5, 12.0, 30.0)
+    }
+}
+
+impl ProcessorTrait<RgbdFrame, ColorFrame> for DepthGuidedDenoiser {
+    async fn process(&self, input: RgbdFrame) -> Result<ColorFrame, ProcessingError> {
+        self.denoise(&input)
+    }
+}
+
+fn is_valid(depth: f32) -> bool {
+    depth.is_finite() && depth > 0.0
+}
+
+/// Unnormalized gaussian of a squared distance.
+fn gaussian(distance_sqr: f32, sigma: f32) -> f32 {
+    (-distance_sqr / (2.0 * sigma * sigma)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::color::ColorSpace;
+
+    const SIZE: usize = 8;
+
+    /// RGB frame and its depth, both given per pixel.
+    fn frame(color: impl Fn(usize, usize) -> u8, depth: impl Fn(usize, usize) -> f32) -> RgbdFrame {
+        let pixels = (0..SIZE * SIZE).map(|index| (index % SIZE, index / SIZE));
+
+        RgbdFrame {
+            color: ColorFrame {
+                color_space: ColorSpace::RGB,
+                width: SIZE,
+                height: SIZE,
+                stride: SIZE * 3,
+                buffer: pixels.clone().flat_map(|(x, y)| [color(x, y); 3]).collect(),
+                sequence: 0,
+                timestamp: 0,
+                exposure: 0.0,
+                gain: 0.0,
+                gamma: 0.0,
+            },
+            depth: DepthFrame {
+                width: SIZE,
+                height: SIZE,
+                buffer: pixels.map(|(x, y)| depth(x, y)).collect(),
+                sequence: 0,
+                timestamp: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn preserves_depth_edges() {
+        let denoiser = DepthGuidedDenoiser::default();
+        let color = |x, _| if x < SIZE / 2 { 100 } else { 106 };
+        let edge = denoiser
+            .denoise(&frame(
+                color,
+                |x, _| if x < SIZE / 2 { 1000.0 } else { 2000.0 },
+            ))
+            .unwrap();
+        let flat = denoiser.denoise(&frame(color, |_, _| 1000.0)).unwrap();
+
+        for y in 0..SIZE {
+            assert_eq!(edge.pixel(SIZE / 2 - 1, y), [100; 3]);
+            assert_eq!(edge.pixel(SIZE / 2, y), [106; 3]);
+            // the same color step is blurred on a single surface
+            assert!(flat.pixel(SIZE / 2 - 1, y)[0] > 100);
+            assert!(flat.pixel(SIZE / 2, y)[0] < 106);
+        }
+    }
+
+    #[test]
+    fn smooths_flat_surfaces() {
+        let input = frame(
+            |x, y| if (x + y) % 2 == 0 { 100 } else { 110 },
+            |_, _| 1000.0,
+        );
+        let output = DepthGuidedDenoiser::default().denoise(&input).unwrap();
+
+        for y in 2..SIZE - 2 {
+            for x in 2..SIZE - 2 {
+                let value = output.pixel(x, y)[0];
+
+                assert!((101..=109).contains(&value), "{value}");
+            }
+        }
+    }
+
+    #[test]
+    fn skips_invalid_depth() {
+        let input = frame(
+            |x, _| x as u8 * 10,
+            |x, _| if x == 3 { f32::NAN } else { 0.0 },
+        );
+        let output = DepthGuidedDenoiser::default().denoise(&input).unwrap();
+
+        assert_eq!(output.buffer, input.color.buffer);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_zero_sigma() {
+        DepthGuidedDenoiser::new(2, 1.5, 0.0, 30.0);
+    }
+}
@@ -0,0 +1,188 @@
+//! Depth processor selected at runtime among the compiled backends.
+
+use std::error::Error;
+
+#[cfg(any(feature = "opencl_depth", feature = "opencl_kde_depth"))]
+use ocl::Device;
+
+use crate::{
+    config::Config,
+    data::P0Tables,
+    processor::{ProcessingError, ProcessorTrait},
+    DEPTH_SIZE, LUT_SIZE,
+};
+
+#[cfg(any(feature = "opencl_depth", feature = "opencl_kde_depth"))]
+use super::first_opencl_device;
+#[cfg(feature = "cpu_depth")]
+use super::CpuDepthProcessor;
+#[cfg(feature = "opencl_depth")]
+use super::OpenCLDepthProcessor;
+#[cfg(feature = "opencl_kde_depth")]
+use super::OpenCLKdeDepthProcessor;
+use super::{DepthFrame, DepthPacket, DepthProcessorTrait, IrFrame};
+
+/// Depth processing backend, only the backends of the enabled features exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthBackend {
+    #[cfg(feature = "cpu_depth")]
+    Cpu,
+    #[cfg(feature = "opencl_depth")]
+    OpenCl,
+    #[cfg(feature = "opencl_kde_depth")]
+    OpenClKde,
+}
+
+impl DepthBackend {
+    /// Every compiled backend, e.g. to list them in a menu.
+    pub const ALL: &'static [Self] = &[
+        #[cfg(feature = "cpu_depth")]
+        Self::Cpu,
+        #[cfg(feature = "opencl_depth")]
+        Self::OpenCl,
+        #[cfg(feature = "opencl_kde_depth")]
+        Self::OpenClKde,
+    ];
+}
+
+/// Depth processor of any backend, see [`create_depth_processor`].
+///
+/// The processor traits return `impl Future` so they can't be boxed,
+/// this enum dispatches to the selected backend instead.
+pub enum AnyDepthProcessor {
+    #[cfg(feature = "cpu_depth")]
+    Cpu(CpuDepthProcessor),
+    #[cfg(feature = "opencl_depth")]
+    OpenCl(OpenCLDepthProcessor),
+    #[cfg(feature = "opencl_kde_depth")]
+    OpenClKde(OpenCLKdeDepthProcessor),
+}
+
+macro_rules! dispatch {
+    ($value:expr, $processor:ident => $expr:expr) => {
+        match $value {
+            #[cfg(feature = "cpu_depth")]
+            AnyDepthProcessor::Cpu($processor) => $expr,
+            #[cfg(feature = "opencl_depth")]
+            AnyDepthProcessor::OpenCl($processor) => $expr,
+            #[cfg(feature = "opencl_kde_depth")]
+            AnyDepthProcessor::OpenClKde($processor) => $expr,
+        }
+    };
+}
+
+/// Options of [`create_depth_processor`], the same type whatever the enabled features
+/// so crates built with different features can share the call.
+#[derive(Debug, Clone, Default)]
+pub struct DepthProcessorOptions {
+    #[cfg(any(feature = "opencl_depth", feature = "opencl_kde_depth"))]
+    opencl_device: Option<Device>,
+}
+
+impl DepthProcessorOptions {
+    /// Device of the OpenCL backends, [`first_opencl_device`] if not set.
+    #[cfg(any(feature = "opencl_depth", feature = "opencl_kde_depth"))]
+    pub fn opencl_device(mut self, device: Device) -> Self {
+        self.opencl_device = Some(device);
+        self
+    }
+}
+
+/// Create a depth processor of the given backend, set the calibration before processing.
+///
+/// # Arguments
+///
+/// * `backend` - Depth processing backend
+/// * `options` - Options of the backends, ignored by the others
+pub fn create_depth_processor(
+    backend: DepthBackend,
+    #[cfg_attr(
+        not(any(feature = "opencl_depth", feature = "opencl_kde_depth")),
+        allow(unused_variables)
+    )]
+    options: DepthProcessorOptions,
+) -> Result<AnyDepthProcessor, Box<dyn Error>> {
+    #[cfg(any(feature = "opencl_depth", feature = "opencl_kde_depth"))]
+    let opencl_device = || -> Result<Device, Box<dyn Error>> {
+        match options.opencl_device {
+            Some(device) => Ok(device),
+            None => Ok(first_opencl_device()?),
+        }
+    };
+
+    Ok(match backend {
+        #[cfg(feature = "cpu_depth")]
+        DepthBackend::Cpu => AnyDepthProcessor::Cpu(CpuDepthProcessor::new()?),
+        #[cfg(feature = "opencl_depth")]
+        DepthBackend::OpenCl => {
+            AnyDepthProcessor::OpenCl(OpenCLDepthProcessor::new(opencl_device()?)?)
+        }
+        #[cfg(feature = "opencl_kde_depth")]
+        DepthBackend::OpenClKde => {
+            AnyDepthProcessor::OpenClKde(OpenCLKdeDepthProcessor::new(opencl_device()?)?)
+        }
+    })
+}
+
+impl AnyDepthProcessor {
+    pub fn backend(&self) -> DepthBackend {
+        match self {
+            #[cfg(feature = "cpu_depth")]
+            Self::Cpu(_) => DepthBackend::Cpu,
+            #[cfg(feature = "opencl_depth")]
+            Self::OpenCl(_) => DepthBackend::OpenCl,
+            #[cfg(feature = "opencl_kde_depth")]
+            Self::OpenClKde(_) => DepthBackend::OpenClKde,
+        }
+    }
+}
+
+impl DepthProcessorTrait for AnyDepthProcessor {
+    fn set_config(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        dispatch!(self, processor => processor.set_config(config))
+    }
+
+    fn config(&self) -> &Config {
+        dispatch!(self, processor => processor.config())
+    }
+
+    fn set_p0_tables(&mut self, p0_tables: &P0Tables) -> Result<(), Box<dyn Error>> {
+        dispatch!(self, processor => processor.set_p0_tables(p0_tables))
+    }
+
+    fn set_x_z_tables(
+        &mut self,
+        x_table: &[f32; DEPTH_SIZE],
+        z_table: &[f32; DEPTH_SIZE],
+    ) -> Result<(), Box<dyn Error>> {
+        dispatch!(self, processor => processor.set_x_z_tables(x_table, z_table))
+    }
+
+    fn set_lookup_table(&mut self, lut: &[i16; LUT_SIZE]) -> Result<(), Box<dyn Error>> {
+        dispatch!(self, processor => processor.set_lookup_table(lut))
+    }
+}
+
+impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for AnyDepthProcessor {
+    async fn process(&self, input: DepthPacket) -> Result<(IrFrame, DepthFrame), ProcessingError> {
+        dispatch!(self, processor => processor.process(input).await)
+    }
+}
+
+#[cfg(all(test, feature = "cpu_depth"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_round_trips() {
+        assert!(DepthBackend::ALL.contains(&DepthBackend::Cpu));
+
+        for backend in DepthBackend::ALL {
+            match create_depth_processor(*backend, DepthProcessorOptions::default()) {
+                Ok(processor) => assert_eq!(processor.backend(), *backend),
+                // the OpenCL backends need a device
+                Err(_) => assert_ne!(*backend, DepthBackend::Cpu),
+            }
+        }
+    }
+}
@@ -2,14 +2,24 @@ use std::{
     error::Error,
     f32::consts::{LOG10_2, LOG2_10, PI},
     iter::repeat,
+    ops::Range,
 };
 
+#[cfg(feature = "parallel")]
+use std::sync::Arc;
+
+#[cfg(feature = "parallel")]
+use rayon::ThreadPool;
+
 use crate::{
-    config::Config, data::P0Tables, processor::ProcessorTrait, settings::DepthProcessorParams,
+    config::Config,
+    data::{IrParams, P0Tables},
+    processor::{ProcessingError, ProcessorTrait},
+    settings::DepthProcessorParams,
     DEPTH_HEIGHT, DEPTH_SIZE, DEPTH_WIDTH, LUT_SIZE,
 };
 
-use super::{DepthFrame, DepthPacket, DepthProcessorTrait, IrFrame};
+use super::{DepthFrame, DepthInvalidReason, DepthPacket, DepthProcessorTrait, IrFrame};
 
 const INV_THREE: f32 = 1.0 / 3.0;
 const INV_NINE: f32 = 1.0 / 9.0;
@@ -84,14 +94,21 @@ pub struct CpuDepthProcessor {
     trig_table1: [Vec<f32>; 6],
     trig_table2: [Vec<f32>; 6],
 
-    enable_bilateral_filter: bool,
-    enable_edge_filter: bool,
+    config: Config,
 
     flip_ptables: bool,
+
+    #[cfg(feature = "parallel")]
+    thread_pool: Option<Arc<ThreadPool>>,
 }
 
 impl CpuDepthProcessor {
     pub fn new() -> Result<Self, Box<dyn Error>> {
+        Self::with_config(&Config::default())
+    }
+
+    /// Create a processor with the filters and depth range of `config` applied.
+    pub fn with_config(config: &Config) -> Result<Self, Box<dyn Error>> {
         let mut processor = Self {
             params: DepthProcessorParams::default(),
             x_table: Mat::<f32>::new(DEPTH_WIDTH, DEPTH_HEIGHT),
@@ -121,16 +138,51 @@ impl CpuDepthProcessor {
                 vec![0.0; DEPTH_SIZE],
                 vec![0.0; DEPTH_SIZE],
             ],
-            enable_bilateral_filter: true,
-            enable_edge_filter: true,
+            config: *config,
             flip_ptables: true,
+            #[cfg(feature = "parallel")]
+            thread_pool: None,
         };
 
-        processor.set_config(&Config::default())?;
+        processor.set_config(config)?;
 
         Ok(processor)
     }
 
+    /// Create a processor from a previously saved calibration, to process recorded packets without a device.
+    pub fn from_calibration(
+        ir_params: &IrParams,
+        p0_tables: &P0Tables,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut processor = Self::new()?;
+
+        processor.set_calibration(ir_params, p0_tables)?;
+
+        Ok(processor)
+    }
+
+    /// Whether the p0 tables are flipped horizontally when set, as the tables read from the device are.
+    /// Disable it for recorded tables that are already flipped, before setting them.
+    pub fn set_flip_ptables(&mut self, flip_ptables: bool) {
+        self.flip_ptables = flip_ptables;
+    }
+
+    pub fn flip_ptables(&self) -> bool {
+        self.flip_ptables
+    }
+
+    /// Run the processing on `thread_pool` instead of the global rayon pool,
+    /// so it doesn't contend with the rest of the application.
+    #[cfg(feature = "parallel")]
+    pub fn set_thread_pool(&mut self, thread_pool: Option<Arc<ThreadPool>>) {
+        self.thread_pool = thread_pool;
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn thread_pool(&self) -> Option<&Arc<ThreadPool>> {
+        self.thread_pool.as_ref()
+    }
+
     fn decode_pixel_measurement(&self, data: &[u8], sub: usize, x: usize, y: usize) -> i16 {
         if x < 1 || 510 < x || 423 < y {
             return self.lut11_to_16[0];
@@ -371,7 +423,14 @@ impl CpuDepthProcessor {
         m[1] = (m[0] * m[0] + m[1] * m[1]).sqrt() * self.params.ab_multiplier;
     }
 
-    fn process_pixel_stage2(&self, x: usize, y: usize, m: &mut [f32; 9]) -> (f32, f32, f32) {
+    fn process_pixel_stage2(
+        &self,
+        x: usize,
+        y: usize,
+        m: &mut [f32; 9],
+    ) -> (f32, f32, f32, DepthInvalidReason) {
+        let saturated = m[2] >= 65535.0 || m[5] >= 65535.0 || m[8] >= 65535.0;
+
         self.transform_measurements(&mut m[0..3]);
         self.transform_measurements(&mut m[3..6]);
         self.transform_measurements(&mut m[6..9]);
@@ -380,8 +439,15 @@ impl CpuDepthProcessor {
         let ir_sum = m01 + m11 + m21;
         let ir_min = m01.min(*m11).min(*m21);
 
+        let mut reason = DepthInvalidReason::Valid;
         let mut phase =
             if ir_min < self.params.individual_ab_threshold || ir_sum < self.params.ab_threshold {
+                reason = if ir_sum < self.params.ab_threshold {
+                    DepthInvalidReason::NoReturn
+                } else {
+                    DepthInvalidReason::LowAmplitude
+                };
+
                 0.0
             } else {
                 let t0 = m00 * PHASE_SCALE_0;
@@ -439,10 +505,17 @@ impl CpuDepthProcessor {
                 if t9 >= 0.0 && ir_x >= norm {
                     t9 * INV_THREE
                 } else {
+                    reason = DepthInvalidReason::LowAmplitude;
+
                     0.0
                 }
             };
 
+        // saturated measurements are zeroed in stage 1, so they fail the thresholds above
+        if saturated {
+            reason = DepthInvalidReason::Saturated;
+        }
+
         if phase > 0.0 {
             phase += self.params.phase_offset;
         }
@@ -464,6 +537,7 @@ impl CpuDepthProcessor {
             ((m02 + m12 + m22) * INV_THREE * self.params.ab_output_multiplier).min(65535.0),
             depth,
             ir_sum,
+            reason,
         )
     }
 
@@ -480,7 +554,7 @@ impl CpuDepthProcessor {
 
         let depth_out = if raw_depth >= self.params.min_depth && raw_depth <= self.params.max_depth
         {
-            if x < 1 || y < 1 || x > 510 || y > 422 {
+            if self.config.preserve_edges || x < 1 || y < 1 || x > 510 || y > 422 {
                 raw_depth
             } else {
                 let mut ir_sum_acc = ir_sum;
@@ -541,14 +615,27 @@ impl CpuDepthProcessor {
 
 impl DepthProcessorTrait for CpuDepthProcessor {
     fn set_config(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        if let Some((x, y, width, height)) = config.roi {
+            let inside = |start: usize, length: usize, size| {
+                length > 0 && start.checked_add(length).is_some_and(|end| end <= size)
+            };
+
+            if !inside(x, width, DEPTH_WIDTH) || !inside(y, height, DEPTH_HEIGHT) {
+                return Err("Region of interest outside of the depth frame".into());
+            }
+        }
+
         self.params.min_depth = config.min_depth * 1000.0;
         self.params.max_depth = config.max_depth * 1000.0;
-        self.enable_bilateral_filter = config.enable_bilateral_filter;
-        self.enable_edge_filter = config.enable_edge_aware_filter;
+        self.config = *config;
 
         Ok(())
     }
 
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
     fn set_p0_tables(&mut self, p0_tables: &P0Tables) -> Result<(), Box<dyn Error>> {
         let mut p0_table0 = Mat::from(DEPTH_WIDTH, p0_tables.p0_table0.to_vec());
         let mut p0_table1 = Mat::from(DEPTH_WIDTH, p0_tables.p0_table1.to_vec());
@@ -585,47 +672,132 @@ impl DepthProcessorTrait for CpuDepthProcessor {
     }
 }
 
-impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for CpuDepthProcessor {
-    async fn process(&self, input: DepthPacket) -> Result<(IrFrame, DepthFrame), Box<dyn Error>> {
+impl CpuDepthProcessor {
+    /// Process a packet, also returning the IR amplitude of each of the three modulation frequencies.
+    ///
+    /// These are the amplitudes the IR sum used for thresholding and the edge-aware filter is made of,
+    /// useful to study multipath and frequency dependent artifacts.
+    /// Like the IR frame, they are vertically flipped to match the depth frame.
+    pub async fn process_with_amplitudes(
+        &self,
+        input: DepthPacket,
+    ) -> Result<(IrFrame, DepthFrame, [IrFrame; 3]), Box<dyn Error>> {
+        let (ir_frame, depth_frame, m, _) = self.process_packet(&input);
+        let mut amplitudes = [
+            vec![0.0; DEPTH_SIZE],
+            vec![0.0; DEPTH_SIZE],
+            vec![0.0; DEPTH_SIZE],
+        ];
+
+        for (x, y) in (0..DEPTH_HEIGHT).flat_map(|y| (0..DEPTH_WIDTH).zip(repeat(y))) {
+            let m = m.get(x, y);
+
+            for (frequency, amplitude) in amplitudes.iter_mut().enumerate() {
+                amplitude[x + (423 - y) * DEPTH_WIDTH] = m[frequency * 3 + 1];
+            }
+        }
+
+        Ok((
+            ir_frame,
+            depth_frame,
+            amplitudes.map(|amplitude| IrFrame::from_packet(amplitude, &input)),
+        ))
+    }
+
+    /// Process a packet, also returning why each depth pixel is invalid.
+    ///
+    /// With the edge-aware filter disabled, depth isn't clipped so only the stage 2 reasons are reported.
+    pub async fn process_with_invalid_reasons(
+        &self,
+        input: DepthPacket,
+    ) -> Result<(IrFrame, DepthFrame, Vec<DepthInvalidReason>), Box<dyn Error>> {
+        let (ir_frame, depth_frame, _, reasons) = self.process_packet(&input);
+
+        Ok((ir_frame, depth_frame, reasons))
+    }
+
+    /// Packet rows and columns to process for the region of interest, grown by `margin` pixels.
+    ///
+    /// The packet is vertically flipped compared to the frames the region is given in.
+    fn roi_ranges(&self, margin: usize) -> (Range<usize>, Range<usize>) {
+        match self.config.roi {
+            Some((x, y, width, height)) => (
+                (DEPTH_HEIGHT - y - height).saturating_sub(margin)
+                    ..(DEPTH_HEIGHT - y + margin).min(DEPTH_HEIGHT),
+                x.saturating_sub(margin)..(x + width + margin).min(DEPTH_WIDTH),
+            ),
+            None => (0..DEPTH_HEIGHT, 0..DEPTH_WIDTH),
+        }
+    }
+
+    /// Returns the IR and depth frames, the stage 2 measurements (phase, amplitude, IR for each frequency)
+    /// and why each depth pixel is invalid.
+    fn process_packet(
+        &self,
+        input: &DepthPacket,
+    ) -> (IrFrame, DepthFrame, Mat<[f32; 9]>, Vec<DepthInvalidReason>) {
+        #[cfg(feature = "parallel")]
+        if let Some(thread_pool) = &self.thread_pool {
+            return thread_pool.install(|| self.process_packet_pixels(input, true));
+        }
+
+        self.process_packet_pixels(input, cfg!(feature = "parallel"))
+    }
+
+    /// Runs the pixel loops on the rayon thread pool if `parallel`, the serial loops are kept
+    /// with the `parallel` feature so the tests can check both give the same output.
+    fn process_packet_pixels(
+        &self,
+        input: &DepthPacket,
+        parallel: bool,
+    ) -> (IrFrame, DepthFrame, Mat<[f32; 9]>, Vec<DepthInvalidReason>) {
         let mut m: Mat<[f32; 9]> = Mat::<[f32; 9]>::new(DEPTH_WIDTH, DEPTH_HEIGHT);
         let mut m_filtered: Mat<[f32; 9]> = Mat::<[f32; 9]>::new(DEPTH_WIDTH, DEPTH_HEIGHT);
         let mut m_max_edge_test: Mat<bool> = Mat::<bool>::new(DEPTH_WIDTH, DEPTH_HEIGHT);
 
-        let indexes = (0..DEPTH_HEIGHT).flat_map(|y| (0..DEPTH_WIDTH).zip(repeat(y)));
+        // the filters read the neighbors of each pixel, 2 pixels around the region keep it exact
+        let (rows, columns) = self.roi_ranges(2);
+        let indexes = rows.clone().flat_map(|y| columns.clone().zip(repeat(y)));
 
-        #[cfg(not(feature = "parallel"))]
-        for (x, y) in indexes.clone() {
-            self.process_pixel_stage1(x, y, &input.buffer, m.get_mut(x, y));
+        if !parallel {
+            for (x, y) in indexes.clone() {
+                self.process_pixel_stage1(x, y, &input.buffer, m.get_mut(x, y));
+            }
         }
 
         #[cfg(feature = "parallel")]
-        depth_mat_iter(|x, y| {
-            let mut m_out = m.get(x, y);
+        if parallel {
+            depth_mat_iter(&rows, &columns, |x, y| {
+                let mut m_out = m.get(x, y);
 
-            self.process_pixel_stage1(x, y, &input.buffer, &mut m_out);
+                self.process_pixel_stage1(x, y, &input.buffer, &mut m_out);
 
-            m_out
-        })
-        .for_each(|(x, y, m_out)| *m.get_mut(x, y) = m_out);
+                m_out
+            })
+            .for_each(|(x, y, m_out)| *m.get_mut(x, y) = m_out);
+        }
 
         // bilateral filtering
-        let mut m_ptr = if self.enable_bilateral_filter {
-            #[cfg(not(feature = "parallel"))]
-            for (x, y) in indexes.clone() {
-                *m_max_edge_test.get_mut(x, y) =
-                    self.filter_pixel_stage1(x, y, &m, m_filtered.get_mut(x, y))
+        let mut m_ptr = if self.config.enable_bilateral_filter {
+            if !parallel {
+                for (x, y) in indexes.clone() {
+                    *m_max_edge_test.get_mut(x, y) =
+                        self.filter_pixel_stage1(x, y, &m, m_filtered.get_mut(x, y))
+                }
             }
 
             #[cfg(feature = "parallel")]
-            depth_mat_iter(|x, y| {
-                let mut m_out = m_filtered.get(x, y);
-
-                (self.filter_pixel_stage1(x, y, &m, &mut m_out), m_out)
-            })
-            .for_each(|(x, y, (max_edge_test, m_out))| {
-                *m_max_edge_test.get_mut(x, y) = max_edge_test;
-                *m_filtered.get_mut(x, y) = m_out;
-            });
+            if parallel {
+                depth_mat_iter(&rows, &columns, |x, y| {
+                    let mut m_out = m_filtered.get(x, y);
+
+                    (self.filter_pixel_stage1(x, y, &m, &mut m_out), m_out)
+                })
+                .for_each(|(x, y, (max_edge_test, m_out))| {
+                    *m_max_edge_test.get_mut(x, y) = max_edge_test;
+                    *m_filtered.get_mut(x, y) = m_out;
+                });
+            }
 
             m_filtered
         } else {
@@ -634,77 +806,140 @@ impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for CpuDepthProcessor {
 
         let mut out_ir: Mat<f32> = Mat::<f32>::new(DEPTH_WIDTH, DEPTH_HEIGHT);
         let mut out_depth: Mat<f32> = Mat::<f32>::new(DEPTH_WIDTH, DEPTH_HEIGHT);
+        let mut out_reasons: Mat<DepthInvalidReason> =
+            Mat::<DepthInvalidReason>::new(DEPTH_WIDTH, DEPTH_HEIGHT);
 
-        if self.enable_edge_filter {
+        if self.config.enable_edge_aware_filter {
             let mut depth_ir_sum: Mat<[f32; 3]> = Mat::<[f32; 3]>::new(DEPTH_WIDTH, DEPTH_HEIGHT);
 
-            #[cfg(not(feature = "parallel"))]
-            for (x, y) in indexes.clone() {
-                let (out_ir_value, raw_depth, ir_sum) =
-                    self.process_pixel_stage2(x, y, m_ptr.get_mut(x, y));
+            if !parallel {
+                for (x, y) in indexes.clone() {
+                    let (out_ir_value, raw_depth, ir_sum, reason) =
+                        self.process_pixel_stage2(x, y, m_ptr.get_mut(x, y));
 
-                *out_ir.get_mut(x, 423 - y) = out_ir_value;
+                    *out_ir.get_mut(x, 423 - y) = out_ir_value;
+                    *out_reasons.get_mut(x, 423 - y) = reason;
 
-                let depth_ir_sum_ptr = depth_ir_sum.get_mut(x, y);
+                    let depth_ir_sum_ptr = depth_ir_sum.get_mut(x, y);
 
-                depth_ir_sum_ptr[0] = raw_depth;
-                depth_ir_sum_ptr[1] = if m_max_edge_test.get(x, y) {
-                    raw_depth
-                } else {
-                    0.0
-                };
-                depth_ir_sum_ptr[2] = ir_sum;
+                    depth_ir_sum_ptr[0] = raw_depth;
+                    depth_ir_sum_ptr[1] = if m_max_edge_test.get(x, y) {
+                        raw_depth
+                    } else {
+                        0.0
+                    };
+                    depth_ir_sum_ptr[2] = ir_sum;
+                }
             }
 
             #[cfg(feature = "parallel")]
-            depth_mat_iter(|x, y| {
-                let mut m_out = m_ptr.get(x, y);
-
-                (self.process_pixel_stage2(x, y, &mut m_out), m_out)
-            })
-            .for_each(|(x, y, ((out_ir_value, raw_depth, ir_sum), m_out))| {
-                *m_ptr.get_mut(x, y) = m_out;
-
-                *out_ir.get_mut(x, 423 - y) = out_ir_value;
-
-                let depth_ir_sum_ptr = depth_ir_sum.get_mut(x, y);
-
-                depth_ir_sum_ptr[0] = raw_depth;
-                depth_ir_sum_ptr[1] = if m_max_edge_test.get(x, y) {
-                    raw_depth
-                } else {
-                    0.0
-                };
-                depth_ir_sum_ptr[2] = ir_sum;
-            });
+            if parallel {
+                depth_mat_iter(&rows, &columns, |x, y| {
+                    let mut m_out = m_ptr.get(x, y);
+
+                    (self.process_pixel_stage2(x, y, &mut m_out), m_out)
+                })
+                .for_each(
+                    |(x, y, ((out_ir_value, raw_depth, ir_sum, reason), m_out))| {
+                        *m_ptr.get_mut(x, y) = m_out;
+
+                        *out_ir.get_mut(x, 423 - y) = out_ir_value;
+                        *out_reasons.get_mut(x, 423 - y) = reason;
+
+                        let depth_ir_sum_ptr = depth_ir_sum.get_mut(x, y);
+
+                        depth_ir_sum_ptr[0] = raw_depth;
+                        depth_ir_sum_ptr[1] = if m_max_edge_test.get(x, y) {
+                            raw_depth
+                        } else {
+                            0.0
+                        };
+                        depth_ir_sum_ptr[2] = ir_sum;
+                    },
+                );
+            }
 
             for (x, y) in indexes {
-                *out_depth.get_mut(x, 423 - y) =
+                let raw_depth = depth_ir_sum.get(x, y)[0];
+                let depth =
                     self.filter_pixel_stage2(x, y, &mut depth_ir_sum, m_max_edge_test.get(x, y));
+                let reason = out_reasons.get_mut(x, 423 - y);
+
+                if *reason == DepthInvalidReason::Valid && depth <= 0.0 {
+                    *reason = if raw_depth < self.params.min_depth {
+                        DepthInvalidReason::BelowMin
+                    } else if raw_depth > self.params.max_depth {
+                        DepthInvalidReason::AboveMax
+                    } else {
+                        DepthInvalidReason::EdgeFiltered
+                    };
+                }
+
+                *out_depth.get_mut(x, 423 - y) = depth;
             }
         } else {
+            if !parallel {
+                for (x, y) in indexes {
+                    let (out_ir_value, raw_depth, _, reason) =
+                        self.process_pixel_stage2(x, y, m_ptr.get_mut(x, y));
+
+                    *out_ir.get_mut(x, 423 - y) = out_ir_value;
+                    *out_depth.get_mut(x, 423 - y) = raw_depth;
+                    *out_reasons.get_mut(x, 423 - y) = reason;
+                }
+            }
+
             #[cfg(feature = "parallel")]
-            depth_mat_iter(|x, y| {
-                let mut m_out = m_ptr.get(x, y);
+            if parallel {
+                depth_mat_iter(&rows, &columns, |x, y| {
+                    let mut m_out = m_ptr.get(x, y);
+
+                    (self.process_pixel_stage2(x, y, &mut m_out), m_out)
+                })
+                .for_each(
+                    |(x, y, ((out_ir_value, raw_depth, _, reason), m_out))| {
+                        *m_ptr.get_mut(x, y) = m_out;
+                        *out_ir.get_mut(x, 423 - y) = out_ir_value;
+                        *out_depth.get_mut(x, 423 - y) = raw_depth;
+                        *out_reasons.get_mut(x, 423 - y) = reason;
+                    },
+                );
+            }
+        }
 
-                (self.process_pixel_stage2(x, y, &mut m_out), m_out)
-            })
-            .for_each(|(x, y, ((out_ir_value, raw_depth, _), m_out))| {
-                *m_ptr.get_mut(x, y) = m_out;
-                *out_ir.get_mut(x, 423 - y) = out_ir_value;
-                *out_depth.get_mut(x, 423 - y) = raw_depth;
-            });
+        if self.config.roi.is_some() {
+            let (rows, columns) = self.roi_ranges(0);
+
+            for (x, y) in (0..DEPTH_HEIGHT).flat_map(|y| (0..DEPTH_WIDTH).zip(repeat(y))) {
+                if !rows.contains(&y) || !columns.contains(&x) {
+                    *out_ir.get_mut(x, 423 - y) = 0.0;
+                    *out_depth.get_mut(x, 423 - y) = 0.0;
+                    *out_reasons.get_mut(x, 423 - y) = DepthInvalidReason::OutsideRoi;
+                }
+            }
         }
 
-        Ok((
-            IrFrame::from_packet(out_ir.buffer, &input),
-            DepthFrame::from_packet(out_depth.buffer, &input),
-        ))
+        (
+            IrFrame::from_packet(out_ir.buffer, input),
+            DepthFrame::from_packet(out_depth.buffer, input),
+            m_ptr,
+            out_reasons.buffer,
+        )
+    }
+}
+
+impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for CpuDepthProcessor {
+    async fn process(&self, input: DepthPacket) -> Result<(IrFrame, DepthFrame), ProcessingError> {
+        let (ir_frame, depth_frame, _, _) = self.process_packet(&input);
+
+        Ok((ir_frame, depth_frame))
     }
 }
 
 #[cfg(feature = "parallel")]
 fn depth_mat_iter<T, F: Fn(usize, usize) -> T + Send + Sync + Copy>(
+    rows: &Range<usize>,
+    columns: &Range<usize>,
     loop_callback: F,
 ) -> std::iter::Flatten<
     std::collections::linked_list::IntoIter<
@@ -716,10 +951,11 @@ where
 {
     use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-    (0..DEPTH_HEIGHT)
+    rows.clone()
         .into_par_iter()
         .flat_map(|y| {
-            (0..DEPTH_WIDTH)
+            columns
+                .clone()
                 .map(|x| (x, y, loop_callback(x, y)))
                 .collect::<Vec<_>>()
         })
@@ -727,3 +963,122 @@ where
         .into_iter()
         .flatten()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::depth::fixtures::{ir_params, packet};
+
+    fn processor(config: &Config) -> CpuDepthProcessor {
+        let mut processor = CpuDepthProcessor::with_config(config).unwrap();
+
+        processor
+            .set_calibration(&ir_params(), &P0Tables::default())
+            .unwrap();
+        processor
+    }
+
+    #[test]
+    fn with_config() {
+        let config = Config {
+            enable_bilateral_filter: false,
+            enable_edge_aware_filter: false,
+            ..Default::default()
+        };
+        let processor = processor(&config);
+
+        assert!(!processor.config().enable_bilateral_filter);
+        assert!(!processor.config().enable_edge_aware_filter);
+        assert_eq!(processor.params.max_depth, config.max_depth * 1000.0);
+    }
+
+    #[test]
+    fn edge_aware_filter_toggle() {
+        let packet = packet();
+        let (_, filtered, _, _) = processor(&Config::default()).process_packet(&packet);
+        let (_, unfiltered, _, unfiltered_reasons) = processor(&Config {
+            enable_edge_aware_filter: false,
+            ..Default::default()
+        })
+        .process_packet(&packet);
+
+        assert_ne!(filtered.buffer, unfiltered.buffer);
+        assert!(!unfiltered_reasons.contains(&DepthInvalidReason::EdgeFiltered));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_matches_serial() {
+        let packet = packet();
+        let configs = [
+            Config::default(),
+            Config {
+                enable_edge_aware_filter: false,
+                ..Default::default()
+            },
+            Config {
+                enable_bilateral_filter: false,
+                roi: Some((100, 50, 64, 48)),
+                ..Default::default()
+            },
+        ];
+        let bits = |buffer: &[f32]| {
+            buffer
+                .iter()
+                .map(|value| value.to_bits())
+                .collect::<Vec<_>>()
+        };
+
+        for config in configs {
+            let processor = processor(&config);
+            let (serial_ir, serial_depth, _, serial_reasons) =
+                processor.process_packet_pixels(&packet, false);
+            let (ir, depth, _, reasons) = processor.process_packet_pixels(&packet, true);
+
+            assert_eq!(bits(&ir.buffer), bits(&serial_ir.buffer));
+            assert_eq!(bits(&depth.buffer), bits(&serial_depth.buffer));
+            assert_eq!(reasons, serial_reasons);
+        }
+    }
+
+    #[test]
+    fn roi_matches_full_output() {
+        let packet = packet();
+        let (x, y, width, height) = (100, 50, 64, 48);
+        let (full_ir, full_depth, _, _) = processor(&Config::default()).process_packet(&packet);
+        let (ir, depth, _, reasons) = processor(&Config {
+            roi: Some((x, y, width, height)),
+            ..Default::default()
+        })
+        .process_packet(&packet);
+
+        for row in 0..DEPTH_HEIGHT {
+            for column in 0..DEPTH_WIDTH {
+                let index = column + row * DEPTH_WIDTH;
+
+                if (x..x + width).contains(&column) && (y..y + height).contains(&row) {
+                    assert_eq!(ir.buffer[index].to_bits(), full_ir.buffer[index].to_bits());
+                    assert_eq!(
+                        depth.buffer[index].to_bits(),
+                        full_depth.buffer[index].to_bits()
+                    );
+                } else {
+                    assert_eq!((ir.buffer[index], depth.buffer[index]), (0.0, 0.0));
+                    assert_eq!(reasons[index], DepthInvalidReason::OutsideRoi);
+                }
+            }
+        }
+
+        for roi in [
+            (500, 0, 64, 48),
+            (usize::MAX, 0, 2, 48),
+            (0, 8, 64, usize::MAX),
+        ] {
+            assert!(CpuDepthProcessor::with_config(&Config {
+                roi: Some(roi),
+                ..Default::default()
+            })
+            .is_err());
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Synthetic depth packet and calibration of the tests.
+
+use crate::{data::IrParams, DEPTH_PACKET_SIZE};
+
+use super::DepthPacket;
+
+/// Packet of pseudo-random bytes, the same on every run.
+pub fn packet() -> DepthPacket {
+    let mut state = 0x2545F491u32;
+
+    DepthPacket {
+        sequence: 0,
+        timestamp: 0,
+        buffer: (0..DEPTH_PACKET_SIZE)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 24) as u8
+            })
+            .collect(),
+    }
+}
+
+/// Calibration without distortion, the principal point at the center of the sensor.
+pub fn ir_params() -> IrParams {
+    IrParams {
+        fx: 365.0,
+        fy: 365.0,
+        cx: 256.0,
+        cy: 212.0,
+        ..Default::default()
+    }
+}
@@ -1,12 +1,30 @@
+#[cfg(any(
+    feature = "cpu_depth",
+    feature = "opencl_depth",
+    feature = "opencl_kde_depth"
+))]
+mod backend;
 #[cfg(feature = "cpu_depth")]
 mod cpu;
+#[cfg(test)]
+mod fixtures;
 #[cfg(feature = "opencl_depth")]
 mod opencl;
 #[cfg(feature = "opencl_kde_depth")]
 mod opencl_kde;
-
-use std::{error::Error, f32::EPSILON, fmt};
-
+#[cfg(feature = "wgpu_depth")]
+mod wgpu;
+
+use std::{error::Error, f32::EPSILON, fmt, future::Future};
+
+#[cfg(feature = "wgpu_depth")]
+pub use self::wgpu::*;
+#[cfg(any(
+    feature = "cpu_depth",
+    feature = "opencl_depth",
+    feature = "opencl_kde_depth"
+))]
+pub use backend::*;
 #[cfg(feature = "cpu_depth")]
 pub use cpu::*;
 #[cfg(feature = "opencl_depth")]
@@ -17,11 +35,17 @@ pub use opencl_kde::*;
 use crate::{
     config::Config,
     data::{IrParams, P0Tables},
+    processor::ProcessorTrait,
     DEPTH_HEIGHT, DEPTH_SIZE, DEPTH_WIDTH, LUT_SIZE,
 };
 
 pub use crate::packet::DepthPacket;
 
+/// Depth or IR frame, row major with the first row at the top of the image.
+///
+/// Every depth processor outputs the same orientation, the packet rows are stored bottom up
+/// so the CPU processor flips its output rows and the OpenCL and wgpu kernels read the packet
+/// rows flipped, frames from different backends can be mixed.
 #[derive(Clone)]
 pub struct DepthFrame {
     pub width: usize,
@@ -32,8 +56,29 @@ pub struct DepthFrame {
     pub timestamp: u32,
 }
 
+/// Depth or IR frame with padded rows, to upload to a GPU texture without a repacking copy,
+/// see [`DepthFrame::with_row_alignment`].
+///
+/// A separate type so padded rows can't reach the processing and export functions,
+/// which expect tightly packed rows.
+#[derive(Clone)]
+pub struct AlignedDepthFrame {
+    pub width: usize,
+    pub height: usize,
+    /// Bytes per row, a multiple of the alignment.
+    pub stride: usize,
+    /// Rows of `stride / 4` values, the padding is NaN.
+    pub buffer: Vec<f32>,
+
+    pub sequence: u32,
+    pub timestamp: u32,
+}
+
 pub type IrFrame = DepthFrame;
 
+/// Per-pixel confidence of the depth, output by `OpenCLKdeDepthProcessor::process_with_confidence`.
+pub type ConfidenceFrame = DepthFrame;
+
 impl DepthFrame {
     pub fn from_packet(buffer: Vec<f32>, packet: &DepthPacket) -> Self {
         Self {
@@ -44,6 +89,274 @@ impl DepthFrame {
             timestamp: packet.timestamp,
         }
     }
+
+    /// Pads every row so the stride is a multiple of `alignment` bytes, as needed to upload
+    /// the buffer to a GPU texture, padding is filled with NaN.
+    ///
+    /// [`Error::RowAlignment`](crate::Error::RowAlignment) if `alignment` isn't a non-zero
+    /// multiple of 4 bytes, the size of a value.
+    pub fn with_row_alignment(self, alignment: usize) -> Result<AlignedDepthFrame, crate::Error> {
+        const VALUE_SIZE: usize = size_of::<f32>();
+
+        if alignment == 0 || alignment.next_multiple_of(VALUE_SIZE) != alignment {
+            return Err(crate::Error::RowAlignment(alignment));
+        }
+
+        let stride = (self.width * VALUE_SIZE).next_multiple_of(alignment);
+        let row_length = stride / VALUE_SIZE;
+        let buffer = if row_length == self.width {
+            self.buffer
+        } else {
+            let mut buffer = Vec::with_capacity(row_length * self.height);
+
+            for row in self
+                .buffer
+                .chunks_exact(self.width.max(1))
+                .take(self.height)
+            {
+                buffer.extend_from_slice(row);
+                buffer.resize(buffer.len() + (row_length - self.width), f32::NAN);
+            }
+
+            buffer
+        };
+
+        Ok(AlignedDepthFrame {
+            width: self.width,
+            height: self.height,
+            stride,
+            buffer,
+            sequence: self.sequence,
+            timestamp: self.timestamp,
+        })
+    }
+
+    /// Map the values to `[0, 1]` with `mapping`, clamping values outside of its range,
+    /// `None` for zero or invalid values.
+    fn normalized(
+        &self,
+        mapping: ToneMapping,
+    ) -> Result<impl Iterator<Item = Option<f32>> + '_, crate::Error> {
+        let curve = mapping.curve;
+        let values = &self.buffer[..self.width * self.height];
+        let (min, max) = match mapping.range {
+            ToneRange::Fixed(min, max) => {
+                let range = (curve.apply(min), curve.apply(max));
+
+                if range.0.is_nan() || range.1.is_nan() || range.0 >= range.1 {
+                    return Err(crate::Error::DepthRange(min, max));
+                }
+
+                range
+            }
+            ToneRange::Percentile(percentile) => {
+                let mut valid: Vec<f32> = values
+                    .iter()
+                    .filter(|value| is_valid(**value))
+                    .map(|value| curve.apply(*value))
+                    .collect();
+                let max = match valid.len() {
+                    0 => 0.0,
+                    length => {
+                        let index =
+                            ((length - 1) as f32 * percentile.clamp(0.0, 100.0) / 100.0) as usize;
+
+                        *valid.select_nth_unstable_by(index, f32::total_cmp).1
+                    }
+                };
+
+                (0.0, max)
+            }
+        };
+        // an empty percentile range maps every value to 0
+        let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+
+        Ok(values.iter().map(move |value| {
+            is_valid(*value).then(|| ((curve.apply(*value) - min) * scale).clamp(0.0, 1.0))
+        }))
+    }
+
+    /// Map the values to the full `u8` range, for 8 bits grayscale images.
+    ///
+    /// Zero or invalid values are mapped to 0.
+    /// The buffer is row major, `width * height` long.
+    ///
+    /// [`Error::DepthRange`](crate::Error::DepthRange) if the fixed range of `mapping` is empty.
+    pub fn to_gray8(&self, mapping: ToneMapping) -> Result<Vec<u8>, crate::Error> {
+        Ok(self
+            .normalized(mapping)?
+            .map(|value| value.map_or(0, |value| (value * u8::MAX as f32).round() as u8))
+            .collect())
+    }
+
+    /// Same as [`DepthFrame::to_gray8`] for 16 bits grayscale images.
+    pub fn to_gray16(&self, mapping: ToneMapping) -> Result<Vec<u16>, crate::Error> {
+        Ok(self
+            .normalized(mapping)?
+            .map(|value| value.map_or(0, |value| (value * u16::MAX as f32).round() as u16))
+            .collect())
+    }
+
+    /// Map the values to a jet colormap, from blue for the lower bound of `mapping`
+    /// to red for the upper one, zero or invalid values are black.
+    ///
+    /// The buffer is RGB, row major, `width * height * 3` long.
+    pub fn to_colormap_rgb(&self, mapping: ToneMapping) -> Result<Vec<u8>, crate::Error> {
+        Ok(self
+            .normalized(mapping)?
+            .flat_map(|value| match value {
+                Some(value) => [3.0, 2.0, 1.0].map(|offset: f32| {
+                    ((1.5 - (4.0 * value - offset).abs()).clamp(0.0, 1.0) * u8::MAX as f32).round()
+                        as u8
+                }),
+                None => [0; 3],
+            })
+            .collect())
+    }
+
+    /// Encode the depth as 16 bits millimeters keeping invalid pixels apart from 0mm,
+    /// decode it with [`DepthFrame::from_u16_mm`].
+    ///
+    /// 0 is reserved for invalid pixels, negative, zero, or NaN depth as output by the processors.
+    /// A valid depth `d` is stored as `round(d) + 1`, clamped to `u16::MAX`,
+    /// so a depth rounding to 0mm is stored as 1.
+    /// The buffer is row major, `width * height` long.
+    pub fn to_u16_mm(&self) -> Vec<u16> {
+        self.buffer[..self.width * self.height]
+            .iter()
+            .map(|value| {
+                if value.is_nan() || *value <= 0.0 {
+                    0
+                } else {
+                    (value.round() + 1.0).min(u16::MAX as f32) as u16
+                }
+            })
+            .collect()
+    }
+
+    /// Decode a depth frame encoded by [`DepthFrame::to_u16_mm`], invalid pixels are NaN.
+    ///
+    /// Sequence and timestamp aren't part of the encoding and are set to 0.
+    /// Returns `None` if `data` isn't `width * height` long.
+    pub fn from_u16_mm(width: usize, height: usize, data: &[u16]) -> Option<Self> {
+        (data.len() == width * height).then(|| Self {
+            width,
+            height,
+            buffer: data
+                .iter()
+                .map(|value| match value {
+                    0 => f32::NAN,
+                    value => (value - 1) as f32,
+                })
+                .collect(),
+            sequence: 0,
+            timestamp: 0,
+        })
+    }
+
+    /// Count the IR amplitudes in `bins` equal bins spanning `[0, 65535]`, the range output
+    /// by the processors, e.g. to pick a [`ToneRange::Fixed`] from percentiles.
+    ///
+    /// Zero or invalid IR isn't counted.
+    pub fn histogram(&self, bins: usize) -> Vec<u32> {
+        const MAX_AMPLITUDE: f32 = 65535.0;
+
+        let mut histogram = vec![0; bins];
+
+        if bins == 0 {
+            return histogram;
+        }
+
+        let scale = bins as f32 / MAX_AMPLITUDE;
+
+        for value in &self.buffer[..self.width * self.height] {
+            if !is_valid(*value) {
+                continue;
+            }
+
+            histogram[((value * scale) as usize).min(bins - 1)] += 1;
+        }
+
+        histogram
+    }
+}
+
+/// Conversion of the values of a [`DepthFrame`] to image intensities, see [`DepthFrame::to_gray8`].
+///
+/// The default is the IR view of the libfreenect2 viewer, a square root up to the 99th percentile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    pub curve: IrToneMap,
+    pub range: ToneRange,
+}
+
+impl ToneMapping {
+    /// Linear mapping of the values from `min` to `max`, e.g. a depth range in millimeters.
+    pub const fn linear(min: f32, max: f32) -> Self {
+        Self {
+            curve: IrToneMap::Linear,
+            range: ToneRange::Fixed(min, max),
+        }
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            curve: IrToneMap::Sqrt,
+            range: ToneRange::Percentile(99.0),
+        }
+    }
+}
+
+/// Values mapped to the darkest and brightest intensities of a [`ToneMapping`],
+/// given before its curve is applied, values outside of the range saturate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneRange {
+    /// From the first value to the second
+    Fixed(f32, f32),
+    /// From zero to this percentile of the valid values, in `[0, 100]`,
+    /// so a few hot pixels don't darken the whole image
+    Percentile(f32),
+}
+
+/// Tone curve of a [`ToneMapping`], IR amplitudes span several orders of magnitude.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IrToneMap {
+    Linear,
+    /// Square root, as the libfreenect2 viewer.
+    #[default]
+    Sqrt,
+    /// `ln(1 + value)`, brings out the darkest parts of the scene.
+    Log,
+}
+
+impl IrToneMap {
+    fn apply(&self, value: f32) -> f32 {
+        match self {
+            Self::Linear => value,
+            Self::Sqrt => value.sqrt(),
+            Self::Log => value.ln_1p(),
+        }
+    }
+}
+
+/// Zero, negative and NaN values are invalid pixels of the processors.
+fn is_valid(value: f32) -> bool {
+    !value.is_nan() && value > 0.0
+}
+
+impl fmt::Debug for AlignedDepthFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlignedDepthFrame")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("stride", &self.stride)
+            .field("buffer_length", &self.buffer.len())
+            .field("sequence", &self.sequence)
+            .field("timestamp", &self.timestamp)
+            .finish()
+    }
 }
 
 impl fmt::Debug for DepthFrame {
@@ -58,9 +371,121 @@ impl fmt::Debug for DepthFrame {
     }
 }
 
+/// Why a depth pixel is invalid (zero).
+///
+/// Not available with the KDE pipeline, which doesn't threshold pixels the same way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum DepthInvalidReason {
+    #[default]
+    Valid,
+    /// Closer than the configured minimum depth
+    BelowMin,
+    /// Farther than the configured maximum depth, might be real far geometry
+    AboveMax,
+    /// The signal of a modulation frequency is too weak, or too noisy to dealias the phase
+    LowAmplitude,
+    /// Removed by the edge-aware filter
+    EdgeFiltered,
+    /// A raw measurement saturated
+    Saturated,
+    /// Almost no light came back, e.g. an absorbing surface or outside of the lens area
+    NoReturn,
+    /// Outside of the region of interest of the config, not processed
+    OutsideRoi,
+}
+
+impl DepthInvalidReason {
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Self::Valid,
+            1 => Self::BelowMin,
+            2 => Self::AboveMax,
+            3 => Self::LowAmplitude,
+            4 => Self::EdgeFiltered,
+            5 => Self::Saturated,
+            6 => Self::NoReturn,
+            7 => Self::OutsideRoi,
+            _ => return None,
+        })
+    }
+}
+
+/// Upper bound of the IR images of [`DepthImagesTrait::process_to_images`],
+/// brighter pixels are saturated, most of the scene is far below [`u16::MAX`].
+pub const IR_IMAGE_MAX: f32 = 20000.0;
+
+/// Quick visualization of depth packets, for live viewers.
+pub trait DepthImagesTrait:
+    DepthProcessorTrait + ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)>
+{
+    /// Process a packet to a grayscale IR image and an RGB depth image,
+    /// the depth colormap spans the configured depth range.
+    fn process_to_images(
+        &self,
+        packet: DepthPacket,
+    ) -> impl Future<Output = Result<(Vec<u8>, Vec<u8>), Box<dyn Error>>> {
+        async {
+            let (ir_frame, depth_frame) = self.process(packet).await?;
+            let config = self.config();
+
+            Ok((
+                ir_frame.to_gray8(ToneMapping::linear(0.0, IR_IMAGE_MAX))?,
+                depth_frame.to_colormap_rgb(ToneMapping::linear(
+                    config.min_depth * 1000.0,
+                    config.max_depth * 1000.0,
+                ))?,
+            ))
+        }
+    }
+}
+
+impl<P: DepthProcessorTrait + ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)>> DepthImagesTrait
+    for P
+{
+}
+
+/// Whether an OpenCL device is available, to fall back to the CPU depth processor otherwise.
+#[cfg(any(feature = "opencl_depth", feature = "opencl_kde_depth"))]
+pub fn opencl_available() -> bool {
+    first_opencl_device().is_ok()
+}
+
+/// First device of the first OpenCL platform, as used by the OpenCL depth processors by default.
+#[cfg(any(feature = "opencl_depth", feature = "opencl_kde_depth"))]
+pub fn first_opencl_device() -> Result<ocl::Device, crate::Error> {
+    let platform = ocl::Platform::first().map_err(|_| crate::Error::NoOpenCLPlatform)?;
+
+    ocl::Device::first(platform).map_err(|_| crate::Error::NoOpenCLPlatform)
+}
+
+/// Build the table decoding the 11-bit packed depth packet values to 16-bit,
+/// as used by every depth processor, it doesn't depend on the calibration.
+///
+/// Values grow by steps doubling every 128 entries, the upper half holds the negated values
+/// and index 1024 is the saturated value `32767`.
+pub fn build_lut11_to_16() -> Box<[i16; LUT_SIZE]> {
+    let mut lut = Box::new([0; LUT_SIZE]);
+    let mut y = 0;
+
+    for x in 0..1024 {
+        let inc = 1 << (x / 128 - (x >= 128) as usize);
+
+        lut[x] = y;
+        lut[1024 + x] = -y;
+        y += inc;
+    }
+
+    lut[1024] = 32767;
+
+    lut
+}
+
 pub trait DepthProcessorTrait {
     fn set_config(&mut self, config: &Config) -> Result<(), Box<dyn Error>>;
 
+    fn config(&self) -> &Config;
+
     fn set_p0_tables(&mut self, p0_tables: &P0Tables) -> Result<(), Box<dyn Error>>;
 
     fn set_x_z_tables(
@@ -74,7 +499,6 @@ pub trait DepthProcessorTrait {
     fn set_ir_params(&mut self, ir_params: &IrParams) -> Result<(), Box<dyn Error>> {
         let mut x_table = [0.0; DEPTH_SIZE];
         let mut z_table = [0.0; DEPTH_SIZE];
-        let mut lut = [0; LUT_SIZE];
 
         const SCALING_FACTOR: f32 = 8192.0;
         const UNAMBIGUOUS_DIST: f32 = 6250.0 / 3.0;
@@ -91,24 +515,23 @@ pub trait DepthProcessorTrait {
             z_table[i] = UNAMBIGUOUS_DIST / (xu * xu + yu * yu + 1.0).sqrt();
         }
 
-        let mut y = 0;
-
-        for x in 0..1024 {
-            let inc = 1 << (x / 128 - (x >= 128) as usize);
-
-            lut[x] = y;
-            lut[1024 + x] = -y;
-            y += inc;
-        }
-
-        lut[1024] = 32767;
-
         self.set_x_z_tables(&x_table, &z_table)?;
-        self.set_lookup_table(&lut)?;
+        self.set_lookup_table(&build_lut11_to_16())?;
 
         Ok(())
     }
 
+    /// Set both the calibration parameters and the p0 tables,
+    /// e.g. from a calibration saved to disk, no device is needed.
+    fn set_calibration(
+        &mut self,
+        ir_params: &IrParams,
+        p0_tables: &P0Tables,
+    ) -> Result<(), Box<dyn Error>> {
+        self.set_ir_params(ir_params)?;
+        self.set_p0_tables(p0_tables)
+    }
+
     fn distort(ir_params: &IrParams, x: f32, y: f32) -> (f32, f32) {
         let x2 = x * x;
         let y2 = y * y;
@@ -186,3 +609,218 @@ pub trait DepthProcessorTrait {
         (x, y)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: usize, height: usize) -> DepthFrame {
+        DepthFrame {
+            width,
+            height,
+            buffer: (0..width * height).map(|value| value as f32).collect(),
+            sequence: 1,
+            timestamp: 2,
+        }
+    }
+
+    #[test]
+    fn with_row_alignment() {
+        let aligned = frame(3, 2).with_row_alignment(16).unwrap();
+
+        assert_eq!((aligned.width, aligned.height, aligned.stride), (3, 2, 16));
+        assert_eq!((aligned.sequence, aligned.timestamp), (1, 2));
+        assert_eq!(aligned.buffer[..3], [0.0, 1.0, 2.0]);
+        assert!(aligned.buffer[3].is_nan());
+        assert_eq!(aligned.buffer[4..7], [3.0, 4.0, 5.0]);
+        assert!(aligned.buffer[7].is_nan());
+
+        // already aligned rows are kept as is
+        let aligned = frame(4, 2).with_row_alignment(8).unwrap();
+
+        assert_eq!(aligned.stride, 16);
+        assert_eq!(aligned.buffer, frame(4, 2).buffer);
+
+        for alignment in [0, 6] {
+            assert!(matches!(
+                frame(3, 2).with_row_alignment(alignment),
+                Err(crate::Error::RowAlignment(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn u16_mm_round_trip() {
+        let depth = [
+            f32::NAN,
+            -5.0,
+            0.0,
+            0.4,
+            0.6,
+            1000.49,
+            1000.5,
+            65534.0,
+            65535.0,
+            70000.0,
+            f32::INFINITY,
+        ];
+        let frame = DepthFrame {
+            width: depth.len(),
+            height: 1,
+            buffer: depth.to_vec(),
+            sequence: 1,
+            timestamp: 2,
+        };
+        let encoded = frame.to_u16_mm();
+
+        assert_eq!(
+            encoded,
+            [0, 0, 0, 1, 2, 1001, 1002, 65535, 65535, 65535, 65535]
+        );
+
+        let decoded = DepthFrame::from_u16_mm(depth.len(), 1, &encoded).unwrap();
+
+        assert!(decoded.buffer[..3].iter().all(|value| value.is_nan()));
+        assert_eq!(
+            decoded.buffer[3..],
+            [0.0, 1.0, 1000.0, 1001.0, 65534.0, 65534.0, 65534.0, 65534.0]
+        );
+        assert_eq!((decoded.sequence, decoded.timestamp), (0, 0));
+        assert!(DepthFrame::from_u16_mm(depth.len(), 2, &encoded).is_none());
+    }
+
+    fn ir_frame(buffer: Vec<f32>) -> DepthFrame {
+        DepthFrame {
+            width: buffer.len(),
+            height: 1,
+            buffer,
+            sequence: 0,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn tone_mapping() {
+        // 100 pixels of 1 to 100, a hot pixel and 2 invalid ones
+        let mut buffer: Vec<f32> = (1..=100).map(|value| (value * value) as f32).collect();
+
+        buffer.extend([1e9, 0.0, f32::NAN]);
+
+        let frame = ir_frame(buffer);
+        let gray = frame.to_gray8(ToneMapping::default()).unwrap();
+
+        assert_eq!(gray.len(), frame.width);
+        // square root, up to the 99th percentile of the valid values
+        assert_eq!(gray[..3], [3, 5, 8]);
+        assert_eq!(gray[99..], [u8::MAX, u8::MAX, 0, 0]);
+
+        let gray = frame
+            .to_gray8(ToneMapping {
+                curve: IrToneMap::Linear,
+                range: ToneRange::Fixed(4.0, 16.0),
+            })
+            .unwrap();
+
+        // 1 is clamped
+        assert_eq!(gray[..5], [0, 0, 106, 255, 255]);
+        assert_eq!(
+            frame.to_gray16(ToneMapping::linear(4.0, 16.0)).unwrap()[..5],
+            [0, 0, 27306, u16::MAX, u16::MAX]
+        );
+
+        // without valid values, and curves making the range empty
+        assert_eq!(
+            ir_frame(vec![0.0, f32::NAN])
+                .to_gray8(ToneMapping::default())
+                .unwrap(),
+            [0, 0]
+        );
+
+        for range in [(400.0, 100.0), (f32::NAN, 100.0), (-4.0, 4.0)] {
+            assert!(matches!(
+                frame.to_gray8(ToneMapping {
+                    curve: IrToneMap::Sqrt,
+                    range: ToneRange::Fixed(range.0, range.1),
+                }),
+                Err(crate::Error::DepthRange(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn ir_image_range() {
+        let frame = ir_frame(vec![
+            f32::NAN,
+            -1.0,
+            0.0,
+            IR_IMAGE_MAX / 2.0,
+            IR_IMAGE_MAX,
+            u16::MAX as f32,
+            f32::INFINITY,
+        ]);
+
+        // brighter pixels than IR_IMAGE_MAX saturate, invalid ones are black
+        assert_eq!(
+            frame
+                .to_gray8(ToneMapping::linear(0.0, IR_IMAGE_MAX))
+                .unwrap(),
+            [0, 0, 0, 128, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn histogram() {
+        let frame = ir_frame(vec![
+            1.0,
+            16383.0,
+            16384.0,
+            49151.0,
+            49152.0,
+            u16::MAX as f32,
+            70000.0,
+            0.0,
+            -1.0,
+            f32::NAN,
+        ]);
+
+        // bins of 16383.75, the last one holding the saturated values
+        assert_eq!(frame.histogram(4), [2, 1, 1, 3]);
+        assert_eq!(frame.histogram(1), [7]);
+        assert!(frame.histogram(0).is_empty());
+        assert_eq!(ir_frame(vec![0.0, f32::NAN]).histogram(2), [0, 0]);
+    }
+
+    #[test]
+    fn colormap_endpoints() {
+        let frame = ir_frame(vec![
+            500.0,
+            1500.0,
+            2500.0,
+            4500.0,
+            100.0,
+            9000.0,
+            0.0,
+            f32::NAN,
+        ]);
+        let rgb = frame
+            .to_colormap_rgb(ToneMapping::linear(500.0, 4500.0))
+            .unwrap();
+        let pixels: Vec<&[u8]> = rgb.chunks_exact(3).collect();
+
+        assert_eq!(rgb.len(), frame.width * 3);
+        // dark blue to dark red, through cyan and green
+        assert_eq!(
+            pixels[..4],
+            [[0, 0, 128], [0, 128, 255], [128, 255, 128], [128, 0, 0]]
+        );
+        // clamped to the endpoints
+        assert_eq!(pixels[4], [0, 0, 128]);
+        assert_eq!(pixels[5], [128, 0, 0]);
+        assert_eq!(pixels[6..], [[0; 3], [0; 3]]);
+
+        assert!(matches!(
+            frame.to_colormap_rgb(ToneMapping::linear(4500.0, 500.0)),
+            Err(crate::Error::DepthRange(..))
+        ));
+    }
+}
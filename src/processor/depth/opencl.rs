@@ -7,11 +7,14 @@ use ocl::{
 };
 
 use crate::{
-    config::Config, data::P0Tables, processor::ProcessorTrait, settings::DepthProcessorParams,
+    config::Config,
+    data::{IrParams, P0Tables},
+    processor::{ProcessingError, ProcessorTrait},
+    settings::DepthProcessorParams,
     DEPTH_HEIGHT, DEPTH_SIZE, DEPTH_WIDTH, LUT_SIZE,
 };
 
-use super::{DepthFrame, DepthPacket, DepthProcessorTrait, IrFrame};
+use super::{DepthFrame, DepthInvalidReason, DepthPacket, DepthProcessorTrait, IrFrame};
 
 macro_rules! build_options {
     (f32 $program_builder:expr => [$($ident:ident = $value:expr $(,)?)*]) => {
@@ -50,12 +53,15 @@ struct Buffers {
     depth: Buffer<f32>,
     ir_sum: Buffer<Float>,
     filtered: Buffer<f32>,
+    invalid_reasons: Buffer<u8>,
 }
 
 struct Kernels {
     process_pixel_stage1_kernel: Kernel,
     filter_pixel_stage1_kernel: Kernel,
     process_pixel_stage2_kernel: Kernel,
+    // same as process_pixel_stage2_kernel, reading the output of filter_pixel_stage1_kernel
+    process_filtered_pixel_stage2_kernel: Kernel,
     filter_pixel_stage2_kernel: Kernel,
 }
 
@@ -84,6 +90,19 @@ impl OpenCLDepthProcessor {
         })
     }
 
+    /// Create a processor from a previously saved calibration, to process recorded packets without a device.
+    pub fn from_calibration(
+        device: Device,
+        ir_params: &IrParams,
+        p0_tables: &P0Tables,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut processor = Self::new(device)?;
+
+        processor.set_calibration(ir_params, p0_tables)?;
+
+        Ok(processor)
+    }
+
     fn create_program(
         params: &DepthProcessorParams,
         config: &Config,
@@ -100,6 +119,16 @@ impl OpenCLDepthProcessor {
         build_options!(
             program_builder => [
                 BFI_BITMASK = 0x180,
+
+                PRESERVE_EDGES = config.preserve_edges as u8,
+
+                DEPTH_VALID = DepthInvalidReason::Valid as u8,
+                DEPTH_BELOW_MIN = DepthInvalidReason::BelowMin as u8,
+                DEPTH_ABOVE_MAX = DepthInvalidReason::AboveMax as u8,
+                DEPTH_LOW_AMPLITUDE = DepthInvalidReason::LowAmplitude as u8,
+                DEPTH_EDGE_FILTERED = DepthInvalidReason::EdgeFiltered as u8,
+                DEPTH_SATURATED = DepthInvalidReason::Saturated as u8,
+                DEPTH_NO_RETURN = DepthInvalidReason::NoReturn as u8,
             ]
         );
 
@@ -233,6 +262,11 @@ impl OpenCLDepthProcessor {
                 .flags(MemFlags::READ_WRITE)
                 .len(DEPTH_SIZE)
                 .build()?,
+            invalid_reasons: pro_que
+                .buffer_builder()
+                .flags(MemFlags::READ_WRITE)
+                .len(DEPTH_SIZE)
+                .build()?,
         };
         let kernels = Kernels {
             process_pixel_stage1_kernel: pro_que
@@ -245,6 +279,7 @@ impl OpenCLDepthProcessor {
                 .arg(&buffers.b)
                 .arg(&buffers.n)
                 .arg(&buffers.ir)
+                .arg(&buffers.invalid_reasons)
                 .build()?,
             filter_pixel_stage1_kernel: pro_que
                 .kernel_builder("filterPixelStage1")
@@ -257,20 +292,23 @@ impl OpenCLDepthProcessor {
                 .build()?,
             process_pixel_stage2_kernel: pro_que
                 .kernel_builder("processPixelStage2")
-                .arg(if config.enable_bilateral_filter {
-                    &buffers.a_filtered
-                } else {
-                    &buffers.a
-                })
-                .arg(if config.enable_bilateral_filter {
-                    &buffers.b_filtered
-                } else {
-                    &buffers.b
-                })
+                .arg(&buffers.a)
+                .arg(&buffers.b)
+                .arg(&buffers.x_table)
+                .arg(&buffers.z_table)
+                .arg(&buffers.depth)
+                .arg(&buffers.ir_sum)
+                .arg(&buffers.invalid_reasons)
+                .build()?,
+            process_filtered_pixel_stage2_kernel: pro_que
+                .kernel_builder("processPixelStage2")
+                .arg(&buffers.a_filtered)
+                .arg(&buffers.b_filtered)
                 .arg(&buffers.x_table)
                 .arg(&buffers.z_table)
                 .arg(&buffers.depth)
                 .arg(&buffers.ir_sum)
+                .arg(&buffers.invalid_reasons)
                 .build()?,
             filter_pixel_stage2_kernel: pro_que
                 .kernel_builder("filterPixelStage2")
@@ -278,6 +316,7 @@ impl OpenCLDepthProcessor {
                 .arg(&buffers.ir_sum)
                 .arg(&buffers.edge_test)
                 .arg(&buffers.filtered)
+                .arg(&buffers.invalid_reasons)
                 .build()?,
         };
 
@@ -287,16 +326,25 @@ impl OpenCLDepthProcessor {
 
 impl DepthProcessorTrait for OpenCLDepthProcessor {
     fn set_config(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
-        self.config = config.clone();
+        if config.roi.is_some() {
+            return Err("Region of interest is only supported by the CPU depth processor".into());
+        }
+        if self.config.program_changed(config) {
+            let (buffers, kernels) = Self::create_program(&self.params, config, &self.device)?;
 
-        let (buffers, kernels) = Self::create_program(&self.params, &config, &self.device)?;
+            self.buffers = buffers;
+            self.kernels = kernels;
+        }
 
-        self.buffers = buffers;
-        self.kernels = kernels;
+        self.config = *config;
 
         Ok(())
     }
 
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
     fn set_p0_tables(&mut self, p0_tables: &P0Tables) -> Result<(), Box<dyn Error>> {
         let mut p0_table = Vec::with_capacity(DEPTH_SIZE);
 
@@ -340,10 +388,31 @@ impl DepthProcessorTrait for OpenCLDepthProcessor {
     }
 }
 
-impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for OpenCLDepthProcessor {
-    async fn process(&self, input: DepthPacket) -> Result<(IrFrame, DepthFrame), Box<dyn Error>> {
-        let mut ir_frame = IrFrame::from_packet(vec![0.0; DEPTH_SIZE], &input);
-        let mut depth_frame = DepthFrame::from_packet(vec![0.0; DEPTH_SIZE], &input);
+impl OpenCLDepthProcessor {
+    /// Process a packet, also returning why each depth pixel is invalid.
+    ///
+    /// With the edge-aware filter disabled, depth isn't clipped so only the stage 2 reasons are reported.
+    pub async fn process_with_invalid_reasons(
+        &self,
+        input: DepthPacket,
+    ) -> Result<(IrFrame, DepthFrame, Vec<DepthInvalidReason>), Box<dyn Error>> {
+        let mut reasons = vec![0u8; DEPTH_SIZE];
+        let (ir_frame, depth_frame) = self.process_packet(&input, Some(&mut reasons))?;
+        let reasons = reasons
+            .into_iter()
+            .map(|reason| DepthInvalidReason::from_u8(reason).ok_or("Unknown invalid reason"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((ir_frame, depth_frame, reasons))
+    }
+
+    fn process_packet(
+        &self,
+        input: &DepthPacket,
+        reasons: Option<&mut [u8]>,
+    ) -> Result<(IrFrame, DepthFrame), ProcessingError> {
+        let mut ir_frame = IrFrame::from_packet(vec![0.0; DEPTH_SIZE], input);
+        let mut depth_frame = DepthFrame::from_packet(vec![0.0; DEPTH_SIZE], input);
 
         let mut event_write = Event::empty();
         let mut event_pps1 = Event::empty();
@@ -352,6 +421,7 @@ impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for OpenCLDepthProcessor
         let mut event_fps2 = Event::empty();
         let mut event_read_ir = Event::empty();
         let mut event_read_depth = Event::empty();
+        let mut event_read_reasons = Event::empty();
 
         self.buffers
             .packet
@@ -394,9 +464,14 @@ impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for OpenCLDepthProcessor
             event_fps1 = event_pps1;
         }
 
+        let process_pixel_stage2_kernel = if self.config.enable_bilateral_filter {
+            &self.kernels.process_filtered_pixel_stage2_kernel
+        } else {
+            &self.kernels.process_pixel_stage2_kernel
+        };
+
         unsafe {
-            self.kernels
-                .process_pixel_stage2_kernel
+            process_pixel_stage2_kernel
                 .cmd()
                 .ewait(&event_fps1)
                 .enew(&mut event_pps2)
@@ -432,9 +507,25 @@ impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for OpenCLDepthProcessor
                 .enq()?;
         }
 
+        if let Some(reasons) = reasons {
+            self.buffers
+                .invalid_reasons
+                .read(reasons)
+                .ewait(&event_fps2)
+                .enew(&mut event_read_reasons)
+                .enq()?;
+            event_read_reasons.wait_for()?;
+        }
+
         event_read_ir.wait_for()?;
         event_read_depth.wait_for()?;
 
         Ok((ir_frame, depth_frame))
     }
 }
+
+impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for OpenCLDepthProcessor {
+    async fn process(&self, input: DepthPacket) -> Result<(IrFrame, DepthFrame), ProcessingError> {
+        self.process_packet(&input, None)
+    }
+}
@@ -47,7 +47,7 @@ float decodePixelMeasurement(global const ushort *data, global const short *lut1
 }
 
 void kernel processPixelStage1(global const short *lut11to16, global const float *z_table, global const float3 *p0_table, global const ushort *data,
-                               global float3 *a_out, global float3 *b_out, global float3 *n_out, global float *ir_out)
+                               global float3 *a_out, global float3 *b_out, global float3 *n_out, global float *ir_out, global uchar *invalid_reasons)
 {
   const uint i = get_global_id(0);
 
@@ -97,6 +97,7 @@ void kernel processPixelStage1(global const short *lut11to16, global const float
   b_out[i] = select(b, (float3)(0.0f), saturated);
   n_out[i] = n;
   ir_out[i] = min(dot(select(n, (float3)(65535.0f), saturated), (float3)(0.333333333f  * AB_MULTIPLIER * AB_OUTPUT_MULTIPLIER)), 65535.0f);
+  invalid_reasons[i] = any(saturated) ? DEPTH_SATURATED : DEPTH_VALID;
 }
 
 /*******************************************************************************
@@ -176,7 +177,7 @@ void kernel filterPixelStage1(global const float3 *a, global const float3 *b, gl
  * Process pixel stage 2
  ******************************************************************************/
 void kernel processPixelStage2(global const float3 *a_in, global const float3 *b_in, global const float *x_table, global const float *z_table,
-                               global float *depth, global float *ir_sums)
+                               global float *depth, global float *ir_sums, global uchar *invalid_reasons)
 {
   const uint i = get_global_id(0);
   float3 a = a_in[i];
@@ -192,6 +193,7 @@ void kernel processPixelStage2(global const float3 *a_in, global const float3 *b
   float ir_max = max(ir.x, max(ir.y, ir.z));
 
   float phase_final = 0.0f;
+  uchar invalid_reason = invalid_reasons[i];
 
   if(ir_min >= INDIVIDUAL_AB_THRESHOLD && ir_sum >= AB_THRESHOLD)
   {
@@ -259,6 +261,15 @@ void kernel processPixelStage2(global const float3 *a_in, global const float3 *b
     float mask3 = MAX_DEALIAS_CONFIDENCE * MAX_DEALIAS_CONFIDENCE >= norm ? 1.0f : 0.0f;
     t10 *= mask3;
     phase_final = true/*(modeMask & 2) != 0*/ ? t11 : t10;
+
+    if(invalid_reason == DEPTH_VALID && phase_final <= 0.0f)
+    {
+      invalid_reason = DEPTH_LOW_AMPLITUDE;
+    }
+  }
+  else if(invalid_reason == DEPTH_VALID)
+  {
+    invalid_reason = ir_sum < AB_THRESHOLD ? DEPTH_NO_RETURN : DEPTH_LOW_AMPLITUDE;
   }
 
   float zmultiplier = z_table[i];
@@ -279,12 +290,13 @@ void kernel processPixelStage2(global const float3 *a_in, global const float3 *b
   float d = cond1 ? depth_fit : depth_linear; // r1.y -> later r2.z
   depth[i] = d;
   ir_sums[i] = ir_sum;
+  invalid_reasons[i] = invalid_reason;
 }
 
 /*******************************************************************************
  * Filter pixel stage 2
  ******************************************************************************/
-void kernel filterPixelStage2(global const float *depth, global const float *ir_sums, global const uchar *max_edge_test, global float *filtered)
+void kernel filterPixelStage2(global const float *depth, global const float *ir_sums, global const uchar *max_edge_test, global float *filtered, global uchar *invalid_reasons)
 {
   const uint i = get_global_id(0);
 
@@ -297,7 +309,7 @@ void kernel filterPixelStage2(global const float *depth, global const float *ir_
 
   if(raw_depth >= MIN_DEPTH && raw_depth <= MAX_DEPTH)
   {
-    if(x < 1 || y < 1 || x > 510 || y > 422)
+    if(PRESERVE_EDGES || x < 1 || y < 1 || x > 510 || y > 422)
     {
       filtered[i] = raw_depth;
     }
@@ -375,4 +387,9 @@ void kernel filterPixelStage2(global const float *depth, global const float *ir_
   {
     filtered[i] = 0.0f;
   }
+
+  if(invalid_reasons[i] == DEPTH_VALID && filtered[i] <= 0.0f)
+  {
+    invalid_reasons[i] = raw_depth < MIN_DEPTH ? DEPTH_BELOW_MIN : raw_depth > MAX_DEPTH ? DEPTH_ABOVE_MAX : DEPTH_EDGE_FILTERED;
+  }
 }
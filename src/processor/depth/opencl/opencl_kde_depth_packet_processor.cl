@@ -378,7 +378,7 @@ void kernel processPixelStage2_phase(global const float3 *a_in, global const flo
 
 }
 
-void kernel filter_kde(global const float4* phase_conf_vec, global const float* gauss_filt_array, global const float* z_table, global const float* x_table, global float* depth)
+void kernel filter_kde(global const float4* phase_conf_vec, global const float* gauss_filt_array, global const float* z_table, global const float* x_table, global float* depth, global float* confidence)
 {
   const uint i = get_global_id(0);
   float kde_val_1, kde_val_2;
@@ -465,6 +465,7 @@ void kernel filter_kde(global const float4* phase_conf_vec, global const float*
 
   //set to zero if confidence is low
   depth[i] = max_val >= KDE_THRESHOLD ? d: 0.0f;
+  confidence[i] = max_val;
 }
 
 
@@ -630,7 +631,7 @@ void kernel processPixelStage2_phase3(global const float3 *a_in, global const fl
 
 
 
-void kernel filter_kde3(global const float *phase_1, global const float *phase_2, global const float *phase_3, global const float* conf1, global const float* conf2, global const float* conf3, global const float* gauss_filt_array, global const float* z_table, global const float* x_table, global float* depth)
+void kernel filter_kde3(global const float *phase_1, global const float *phase_2, global const float *phase_3, global const float* conf1, global const float* conf2, global const float* conf3, global const float* gauss_filt_array, global const float* z_table, global const float* x_table, global float* depth, global float* confidence)
 {
   const uint i = get_global_id(0);
   float kde_val_1, kde_val_2, kde_val_3;
@@ -741,6 +742,7 @@ void kernel filter_kde3(global const float *phase_1, global const float *phase_2
 
   //set to zero if confidence is low
   depth[i] = max_val >= KDE_THRESHOLD ? d: 0.0f;
+  confidence[i] = max_val;
 }
 
 
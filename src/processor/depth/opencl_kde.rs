@@ -7,11 +7,14 @@ use ocl::{
 };
 
 use crate::{
-    config::Config, data::P0Tables, processor::ProcessorTrait, settings::DepthProcessorParams,
+    config::Config,
+    data::{IrParams, P0Tables},
+    processor::{ProcessingError, ProcessorTrait},
+    settings::DepthProcessorParams,
     DEPTH_HEIGHT, DEPTH_SIZE, DEPTH_WIDTH, LUT_SIZE,
 };
 
-use super::{DepthFrame, DepthPacket, DepthProcessorTrait, IrFrame};
+use super::{ConfidenceFrame, DepthFrame, DepthPacket, DepthProcessorTrait, IrFrame};
 
 macro_rules! build_options {
     (f32 $program_builder:expr => [$($ident:ident = $value:expr $(,)?)*]) => {
@@ -48,6 +51,7 @@ struct Buffers {
     b_filtered: Buffer<Float3>,
     edge_test: Buffer<Uchar>,
     depth: Buffer<f32>,
+    confidence: Buffer<f32>,
     conf_1: Buffer<Float>,
     conf_2: Buffer<Float>,
     conf_3: Buffer<Float>,
@@ -62,6 +66,8 @@ struct Kernels {
     process_pixel_stage1_kernel: Kernel,
     filter_pixel_stage1_kernel: Kernel,
     process_pixel_stage2_kernel: Kernel,
+    // same as process_pixel_stage2_kernel, reading the output of filter_pixel_stage1_kernel
+    process_filtered_pixel_stage2_kernel: Kernel,
     filter_pixel_stage2_kernel: Kernel,
 }
 
@@ -90,6 +96,42 @@ impl OpenCLKdeDepthProcessor {
         })
     }
 
+    /// Create a processor from a previously saved calibration, to process recorded packets without a device.
+    pub fn from_calibration(
+        device: Device,
+        ir_params: &IrParams,
+        p0_tables: &P0Tables,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut processor = Self::new(device)?;
+
+        processor.set_calibration(ir_params, p0_tables)?;
+
+        Ok(processor)
+    }
+
+    /// Set the depth processing parameters, the program is rebuilt so `num_hyps`,
+    /// `kde_neigborhood_size` and `kde_sigma_sqr` can be switched at runtime.
+    ///
+    /// The buffers are recreated, set the calibration again afterwards
+    /// (the KDE gaussian kernel is computed along the x/z tables).
+    pub fn set_params(&mut self, params: &DepthProcessorParams) -> Result<(), Box<dyn Error>> {
+        if params.num_hyps != 2 && params.num_hyps != 3 {
+            return Err("Only 2 and 3 phase unwrapping hypotheses are implemented".into());
+        }
+
+        let (buffers, kernels) = Self::create_program(params, &self.config, &self.device)?;
+
+        self.params = *params;
+        self.buffers = buffers;
+        self.kernels = kernels;
+
+        Ok(())
+    }
+
+    pub fn params(&self) -> &DepthProcessorParams {
+        &self.params
+    }
+
     fn create_program(
         params: &DepthProcessorParams,
         config: &Config,
@@ -238,6 +280,11 @@ impl OpenCLKdeDepthProcessor {
                 .flags(MemFlags::READ_WRITE)
                 .len(DEPTH_SIZE)
                 .build()?,
+            confidence: pro_que
+                .buffer_builder()
+                .flags(MemFlags::READ_WRITE)
+                .len(DEPTH_SIZE)
+                .build()?,
             conf_1: pro_que
                 .buffer_builder()
                 .flags(MemFlags::READ_WRITE)
@@ -300,42 +347,16 @@ impl OpenCLKdeDepthProcessor {
                 .arg(&buffers.b_filtered)
                 .arg(&buffers.edge_test)
                 .build()?,
-            process_pixel_stage2_kernel: if params.num_hyps == 3 {
-                pro_que
-                    .kernel_builder("processPixelStage2_phase3")
-                    .arg(if config.enable_bilateral_filter {
-                        &buffers.a_filtered
-                    } else {
-                        &buffers.a
-                    })
-                    .arg(if config.enable_bilateral_filter {
-                        &buffers.b_filtered
-                    } else {
-                        &buffers.b
-                    })
-                    .arg(&buffers.phase_1)
-                    .arg(&buffers.phase_2)
-                    .arg(&buffers.phase_3)
-                    .arg(&buffers.conf_1)
-                    .arg(&buffers.conf_2)
-                    .arg(&buffers.conf_3)
-                    .build()?
-            } else {
-                pro_que
-                    .kernel_builder("processPixelStage2_phase")
-                    .arg(if config.enable_bilateral_filter {
-                        &buffers.a_filtered
-                    } else {
-                        &buffers.a
-                    })
-                    .arg(if config.enable_bilateral_filter {
-                        &buffers.b_filtered
-                    } else {
-                        &buffers.b
-                    })
-                    .arg(&buffers.phase_conf)
-                    .build()?
-            },
+            process_pixel_stage2_kernel: Self::process_pixel_stage2_kernel(
+                &pro_que, params, &buffers, &buffers.a, &buffers.b,
+            )?,
+            process_filtered_pixel_stage2_kernel: Self::process_pixel_stage2_kernel(
+                &pro_que,
+                params,
+                &buffers,
+                &buffers.a_filtered,
+                &buffers.b_filtered,
+            )?,
             filter_pixel_stage2_kernel: if params.num_hyps == 3 {
                 pro_que
                     .kernel_builder("filter_kde3")
@@ -349,6 +370,7 @@ impl OpenCLKdeDepthProcessor {
                     .arg(&buffers.z_table)
                     .arg(&buffers.x_table)
                     .arg(&buffers.depth)
+                    .arg(&buffers.confidence)
                     .build()?
             } else {
                 pro_que
@@ -358,26 +380,65 @@ impl OpenCLKdeDepthProcessor {
                     .arg(&buffers.z_table)
                     .arg(&buffers.x_table)
                     .arg(&buffers.depth)
+                    .arg(&buffers.confidence)
                     .build()?
             },
         };
 
         Ok((buffers, kernels))
     }
+
+    fn process_pixel_stage2_kernel(
+        pro_que: &ProQue,
+        params: &DepthProcessorParams,
+        buffers: &Buffers,
+        a: &Buffer<Float3>,
+        b: &Buffer<Float3>,
+    ) -> Result<Kernel, Box<dyn Error>> {
+        Ok(if params.num_hyps == 3 {
+            pro_que
+                .kernel_builder("processPixelStage2_phase3")
+                .arg(a)
+                .arg(b)
+                .arg(&buffers.phase_1)
+                .arg(&buffers.phase_2)
+                .arg(&buffers.phase_3)
+                .arg(&buffers.conf_1)
+                .arg(&buffers.conf_2)
+                .arg(&buffers.conf_3)
+                .build()?
+        } else {
+            pro_que
+                .kernel_builder("processPixelStage2_phase")
+                .arg(a)
+                .arg(b)
+                .arg(&buffers.phase_conf)
+                .build()?
+        })
+    }
 }
 
 impl DepthProcessorTrait for OpenCLKdeDepthProcessor {
     fn set_config(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
-        self.config = config.clone();
+        if config.roi.is_some() {
+            return Err("Region of interest is only supported by the CPU depth processor".into());
+        }
+        if self.config.program_changed(config) {
+            let (buffers, kernels) = Self::create_program(&self.params, config, &self.device)?;
 
-        let (buffers, kernels) = Self::create_program(&self.params, &config, &self.device)?;
+            self.buffers = buffers;
+            self.kernels = kernels;
+        }
 
-        self.buffers = buffers;
-        self.kernels = kernels;
+        self.config = *config;
 
         Ok(())
     }
 
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
     fn set_p0_tables(&mut self, p0_tables: &P0Tables) -> Result<(), Box<dyn Error>> {
         let mut p0_table = Vec::with_capacity(DEPTH_SIZE);
 
@@ -436,8 +497,27 @@ impl DepthProcessorTrait for OpenCLKdeDepthProcessor {
     }
 }
 
-impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for OpenCLKdeDepthProcessor {
-    async fn process(&self, input: DepthPacket) -> Result<(IrFrame, DepthFrame), Box<dyn Error>> {
+impl OpenCLKdeDepthProcessor {
+    /// Same as [`ProcessorTrait::process`], also reading back the KDE confidence of the selected
+    /// phase hypothesis, depth is invalid where it's below `kde_threshold` or out of range (0).
+    pub async fn process_with_confidence(
+        &self,
+        input: DepthPacket,
+    ) -> Result<(IrFrame, DepthFrame, ConfidenceFrame), Box<dyn Error>> {
+        let (ir_frame, depth_frame, confidence_frame) = self.run(input, true)?;
+
+        Ok((
+            ir_frame,
+            depth_frame,
+            confidence_frame.expect("Expected confidence"),
+        ))
+    }
+
+    fn run(
+        &self,
+        input: DepthPacket,
+        with_confidence: bool,
+    ) -> Result<(IrFrame, DepthFrame, Option<ConfidenceFrame>), ProcessingError> {
         let mut ir_frame = IrFrame::from_packet(vec![0.0; DEPTH_SIZE], &input);
         let mut depth_frame = DepthFrame::from_packet(vec![0.0; DEPTH_SIZE], &input);
 
@@ -490,9 +570,14 @@ impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for OpenCLKdeDepthProces
             event_fps1 = event_pps1;
         }
 
+        let process_pixel_stage2_kernel = if self.config.enable_bilateral_filter {
+            &self.kernels.process_filtered_pixel_stage2_kernel
+        } else {
+            &self.kernels.process_pixel_stage2_kernel
+        };
+
         unsafe {
-            self.kernels
-                .process_pixel_stage2_kernel
+            process_pixel_stage2_kernel
                 .cmd()
                 .ewait(&event_fps1)
                 .enew(&mut event_pps2)
@@ -515,9 +600,31 @@ impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for OpenCLKdeDepthProces
             .enew(&mut event_read_depth)
             .enq()?;
 
+        let confidence_frame = if with_confidence {
+            let mut confidence_frame = ConfidenceFrame::from_packet(vec![0.0; DEPTH_SIZE], &input);
+
+            self.buffers
+                .confidence
+                .read(confidence_frame.buffer.as_mut_slice())
+                .ewait(&event_fps2)
+                .enq()?;
+
+            Some(confidence_frame)
+        } else {
+            None
+        };
+
         event_read_ir.wait_for()?;
         event_read_depth.wait_for()?;
 
+        Ok((ir_frame, depth_frame, confidence_frame))
+    }
+}
+
+impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for OpenCLKdeDepthProcessor {
+    async fn process(&self, input: DepthPacket) -> Result<(IrFrame, DepthFrame), ProcessingError> {
+        let (ir_frame, depth_frame, _) = self.run(input, false)?;
+
         Ok((ir_frame, depth_frame))
     }
 }
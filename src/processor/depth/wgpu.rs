@@ -0,0 +1,597 @@
+use std::{borrow::Cow, error::Error, f32::consts::PI, fmt::Write, sync::mpsc};
+
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, Buffer, BufferDescriptor, BufferUsages,
+    CommandEncoderDescriptor, ComputePass, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, MapMode, PollType, Queue, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource,
+};
+
+use crate::{
+    config::Config,
+    data::{IrParams, P0Tables},
+    processor::{ProcessingError, ProcessorTrait},
+    settings::DepthProcessorParams,
+    DEPTH_HEIGHT, DEPTH_PACKET_SIZE, DEPTH_SIZE, DEPTH_WIDTH, LUT_SIZE,
+};
+
+use super::{DepthFrame, DepthInvalidReason, DepthPacket, DepthProcessorTrait, IrFrame};
+
+/// Must match the `@workgroup_size` of the kernels.
+const WORKGROUP_SIZE: usize = 64;
+/// Size in bytes of a buffer holding one 32 bits value per pixel.
+const FRAME_BUFFER_SIZE: u64 = (DEPTH_SIZE * 4) as u64;
+
+macro_rules! shader_constants {
+    ($source:expr => [$($ident:ident: $ty:ident = $value:expr $(,)?)*]) => {
+        $(
+            writeln!(
+                $source,
+                "const {}: {} = {:?};",
+                stringify!($ident),
+                stringify!($ty),
+                $value
+            )?;
+        )*
+    };
+}
+
+struct Buffers {
+    // Read only
+    lut11to16: Buffer,
+    p0_table: Buffer,
+    x_table: Buffer,
+    z_table: Buffer,
+    packet: Buffer,
+    // Read-Write
+    a: Buffer,
+    b: Buffer,
+    n: Buffer,
+    ir: Buffer,
+    a_filtered: Buffer,
+    b_filtered: Buffer,
+    edge_test: Buffer,
+    depth: Buffer,
+    ir_sum: Buffer,
+    filtered: Buffer,
+    invalid_reasons: Buffer,
+}
+
+struct Kernel {
+    pipeline: ComputePipeline,
+    bind_group: BindGroup,
+}
+
+impl Kernel {
+    fn new(
+        device: &Device,
+        module: &ShaderModule,
+        entry_point: &str,
+        buffers: &[(u32, &Buffer)],
+    ) -> Self {
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: None,
+            module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some(entry_point),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &buffers
+                .iter()
+                .map(|(binding, buffer)| BindGroupEntry {
+                    binding: *binding,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect::<Vec<_>>(),
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+        }
+    }
+
+    fn dispatch(&self, pass: &mut ComputePass) {
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch_workgroups((DEPTH_SIZE / WORKGROUP_SIZE) as u32, 1, 1);
+    }
+}
+
+struct Kernels {
+    process_pixel_stage1_kernel: Kernel,
+    filter_pixel_stage1_kernel: Kernel,
+    process_pixel_stage2_kernel: Kernel,
+    // same as process_pixel_stage2_kernel, reading the output of filter_pixel_stage1_kernel
+    process_filtered_pixel_stage2_kernel: Kernel,
+    filter_pixel_stage2_kernel: Kernel,
+}
+
+/// wgpu depth processor, a port of the OpenCL kernels to WGSL compute shaders.
+///
+/// It runs on any wgpu backend (Vulkan, Metal, DX12), the device must support the default
+/// limits of 8 storage buffers per shader stage.
+pub struct WgpuDepthProcessor {
+    device: Device,
+    queue: Queue,
+    params: DepthProcessorParams,
+    config: Config,
+    buffers: Buffers,
+    kernels: Kernels,
+}
+
+impl WgpuDepthProcessor {
+    pub fn new(device: Device, queue: Queue) -> Result<Self, Box<dyn Error>> {
+        let params = DepthProcessorParams::default();
+        let config = Config::default();
+
+        let buffers = Self::create_buffers(&device);
+        let kernels = Self::create_program(&params, &config, &device, &buffers)?;
+
+        Ok(Self {
+            device,
+            queue,
+            params,
+            config,
+            buffers,
+            kernels,
+        })
+    }
+
+    /// Create a processor from a previously saved calibration, to process recorded packets without a device.
+    pub fn from_calibration(
+        device: Device,
+        queue: Queue,
+        ir_params: &IrParams,
+        p0_tables: &P0Tables,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut processor = Self::new(device, queue)?;
+
+        processor.set_calibration(ir_params, p0_tables)?;
+
+        Ok(processor)
+    }
+
+    fn create_buffers(device: &Device) -> Buffers {
+        let buffer = |label: &str, size: u64, usage: BufferUsages| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let storage = BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC;
+
+        Buffers {
+            lut11to16: buffer(
+                "lut11to16",
+                (LUT_SIZE * 4) as u64,
+                BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            ),
+            // vec3<f32> have a 16 bytes stride
+            p0_table: buffer("p0_table", FRAME_BUFFER_SIZE * 4, storage),
+            x_table: buffer("x_table", FRAME_BUFFER_SIZE, storage),
+            z_table: buffer("z_table", FRAME_BUFFER_SIZE, storage),
+            packet: buffer("packet", DEPTH_PACKET_SIZE as u64, storage),
+            a: buffer("a", FRAME_BUFFER_SIZE * 4, storage),
+            b: buffer("b", FRAME_BUFFER_SIZE * 4, storage),
+            n: buffer("n", FRAME_BUFFER_SIZE * 4, storage),
+            ir: buffer("ir", FRAME_BUFFER_SIZE, storage),
+            a_filtered: buffer("a_filtered", FRAME_BUFFER_SIZE * 4, storage),
+            b_filtered: buffer("b_filtered", FRAME_BUFFER_SIZE * 4, storage),
+            edge_test: buffer("edge_test", FRAME_BUFFER_SIZE, storage),
+            depth: buffer("depth", FRAME_BUFFER_SIZE, storage),
+            ir_sum: buffer("ir_sum", FRAME_BUFFER_SIZE, storage),
+            filtered: buffer("filtered", FRAME_BUFFER_SIZE, storage),
+            invalid_reasons: buffer("invalid_reasons", FRAME_BUFFER_SIZE, storage),
+        }
+    }
+
+    fn create_program(
+        params: &DepthProcessorParams,
+        config: &Config,
+        device: &Device,
+        buffers: &Buffers,
+    ) -> Result<Kernels, Box<dyn Error>> {
+        let mut source = String::new();
+
+        shader_constants!(
+            source => [
+                BFI_BITMASK: u32 = 0x180,
+
+                PRESERVE_EDGES: bool = config.preserve_edges,
+
+                DEPTH_VALID: u32 = DepthInvalidReason::Valid as u32,
+                DEPTH_BELOW_MIN: u32 = DepthInvalidReason::BelowMin as u32,
+                DEPTH_ABOVE_MAX: u32 = DepthInvalidReason::AboveMax as u32,
+                DEPTH_LOW_AMPLITUDE: u32 = DepthInvalidReason::LowAmplitude as u32,
+                DEPTH_EDGE_FILTERED: u32 = DepthInvalidReason::EdgeFiltered as u32,
+                DEPTH_SATURATED: u32 = DepthInvalidReason::Saturated as u32,
+                DEPTH_NO_RETURN: u32 = DepthInvalidReason::NoReturn as u32,
+
+                AB_MULTIPLIER: f32 = params.ab_multiplier,
+                AB_MULTIPLIER_PER_FRQ0: f32 = params.ab_multiplier_per_frq[0],
+                AB_MULTIPLIER_PER_FRQ1: f32 = params.ab_multiplier_per_frq[1],
+                AB_MULTIPLIER_PER_FRQ2: f32 = params.ab_multiplier_per_frq[2],
+                AB_OUTPUT_MULTIPLIER: f32 = params.ab_output_multiplier,
+
+                PHASE_IN_RAD0: f32 = params.phase_in_rad[0],
+                PHASE_IN_RAD1: f32 = params.phase_in_rad[1],
+                PHASE_IN_RAD2: f32 = params.phase_in_rad[2],
+
+                JOINT_BILATERAL_AB_THRESHOLD: f32 = params.joint_bilateral_ab_threshold,
+                JOINT_BILATERAL_MAX_EDGE: f32 = params.joint_bilateral_max_edge,
+                JOINT_BILATERAL_EXP: f32 = params.joint_bilateral_exp,
+                JOINT_BILATERAL_THRESHOLD: f32 = (params.joint_bilateral_ab_threshold * params.joint_bilateral_ab_threshold) / (params.ab_multiplier * params.ab_multiplier),
+                GAUSSIAN_KERNEL_0: f32 = params.gaussian_kernel[0],
+                GAUSSIAN_KERNEL_1: f32 = params.gaussian_kernel[1],
+                GAUSSIAN_KERNEL_2: f32 = params.gaussian_kernel[2],
+                GAUSSIAN_KERNEL_3: f32 = params.gaussian_kernel[3],
+                GAUSSIAN_KERNEL_4: f32 = params.gaussian_kernel[4],
+                GAUSSIAN_KERNEL_5: f32 = params.gaussian_kernel[5],
+                GAUSSIAN_KERNEL_6: f32 = params.gaussian_kernel[6],
+                GAUSSIAN_KERNEL_7: f32 = params.gaussian_kernel[7],
+                GAUSSIAN_KERNEL_8: f32 = params.gaussian_kernel[8],
+
+                PHASE_OFFSET: f32 = params.phase_offset,
+                UNAMBIGUOUS_DIST: f32 = params.unambiguous_dist,
+                INDIVIDUAL_AB_THRESHOLD: f32 = params.individual_ab_threshold,
+                AB_THRESHOLD: f32 = params.ab_threshold,
+                AB_CONFIDENCE_SLOPE: f32 = params.ab_confidence_slope,
+                AB_CONFIDENCE_OFFSET: f32 = params.ab_confidence_offset,
+                MIN_DEALIAS_CONFIDENCE: f32 = params.min_dealias_confidence,
+                MAX_DEALIAS_CONFIDENCE: f32 = params.max_dealias_confidence,
+
+                EDGE_AB_AVG_MIN_VALUE: f32 = params.edge_ab_avg_min_value,
+                EDGE_AB_STD_DEV_THRESHOLD: f32 = params.edge_ab_std_dev_threshold,
+                EDGE_CLOSE_DELTA_THRESHOLD: f32 = params.edge_close_delta_threshold,
+                EDGE_FAR_DELTA_THRESHOLD: f32 = params.edge_far_delta_threshold,
+                EDGE_MAX_DELTA_THRESHOLD: f32 = params.edge_max_delta_threshold,
+                EDGE_AVG_DELTA_THRESHOLD: f32 = params.edge_avg_delta_threshold,
+                MAX_EDGE_COUNT: f32 = params.max_edge_count,
+
+                MIN_DEPTH: f32 = config.min_depth * 1000.0,
+                MAX_DEPTH: f32 = config.max_depth * 1000.0,
+            ]
+        );
+
+        source.push_str(include_str!("./wgpu/wgpu_depth_packet_processor.wgsl"));
+
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("wgpu_depth_packet_processor"),
+            source: ShaderSource::Wgsl(Cow::Owned(source)),
+        });
+        let process_pixel_stage2_kernel = |a: &Buffer, b: &Buffer| {
+            Kernel::new(
+                device,
+                &module,
+                "processPixelStage2",
+                &[
+                    (20, a),
+                    (21, b),
+                    (22, &buffers.x_table),
+                    (23, &buffers.z_table),
+                    (24, &buffers.depth),
+                    (25, &buffers.ir_sum),
+                    (26, &buffers.invalid_reasons),
+                ],
+            )
+        };
+
+        Ok(Kernels {
+            process_pixel_stage1_kernel: Kernel::new(
+                device,
+                &module,
+                "processPixelStage1",
+                &[
+                    (0, &buffers.lut11to16),
+                    (1, &buffers.z_table),
+                    (2, &buffers.p0_table),
+                    (3, &buffers.packet),
+                    (4, &buffers.a),
+                    (5, &buffers.b),
+                    (6, &buffers.n),
+                    (7, &buffers.ir),
+                    (8, &buffers.invalid_reasons),
+                ],
+            ),
+            filter_pixel_stage1_kernel: Kernel::new(
+                device,
+                &module,
+                "filterPixelStage1",
+                &[
+                    (10, &buffers.a),
+                    (11, &buffers.b),
+                    (12, &buffers.n),
+                    (13, &buffers.a_filtered),
+                    (14, &buffers.b_filtered),
+                    (15, &buffers.edge_test),
+                ],
+            ),
+            process_pixel_stage2_kernel: process_pixel_stage2_kernel(&buffers.a, &buffers.b),
+            process_filtered_pixel_stage2_kernel: process_pixel_stage2_kernel(
+                &buffers.a_filtered,
+                &buffers.b_filtered,
+            ),
+            filter_pixel_stage2_kernel: Kernel::new(
+                device,
+                &module,
+                "filterPixelStage2",
+                &[
+                    (30, &buffers.depth),
+                    (31, &buffers.ir_sum),
+                    (32, &buffers.edge_test),
+                    (33, &buffers.filtered),
+                    (34, &buffers.invalid_reasons),
+                ],
+            ),
+        })
+    }
+}
+
+impl DepthProcessorTrait for WgpuDepthProcessor {
+    fn set_config(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+        if config.roi.is_some() {
+            return Err("Region of interest is only supported by the CPU depth processor".into());
+        }
+        if self.config.program_changed(config) {
+            self.kernels = Self::create_program(&self.params, config, &self.device, &self.buffers)?;
+        }
+
+        self.config = *config;
+
+        Ok(())
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn set_p0_tables(&mut self, p0_tables: &P0Tables) -> Result<(), Box<dyn Error>> {
+        let mut p0_table = Vec::with_capacity(DEPTH_SIZE * 16);
+
+        for r in 0..DEPTH_HEIGHT {
+            for c in 0..DEPTH_WIDTH {
+                for value in [
+                    -(p0_tables.p0_table0[r * DEPTH_WIDTH + c] as f32) * 0.000031 * PI,
+                    -(p0_tables.p0_table1[r * DEPTH_WIDTH + c] as f32) * 0.000031 * PI,
+                    -(p0_tables.p0_table2[r * DEPTH_WIDTH + c] as f32) * 0.000031 * PI,
+                    0.0,
+                ] {
+                    p0_table.extend(value.to_le_bytes());
+                }
+            }
+        }
+
+        self.queue
+            .write_buffer(&self.buffers.p0_table, 0, &p0_table);
+
+        Ok(())
+    }
+
+    fn set_x_z_tables(
+        &mut self,
+        x_table: &[f32; DEPTH_SIZE],
+        z_table: &[f32; DEPTH_SIZE],
+    ) -> Result<(), Box<dyn Error>> {
+        for (buffer, table) in [
+            (&self.buffers.x_table, x_table),
+            (&self.buffers.z_table, z_table),
+        ] {
+            self.queue.write_buffer(
+                buffer,
+                0,
+                &table
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn set_lookup_table(&mut self, lut: &[i16; LUT_SIZE]) -> Result<(), Box<dyn Error>> {
+        self.queue.write_buffer(
+            &self.buffers.lut11to16,
+            0,
+            &lut.iter()
+                .flat_map(|value| (*value as i32).to_le_bytes())
+                .collect::<Vec<_>>(),
+        );
+
+        Ok(())
+    }
+}
+
+impl WgpuDepthProcessor {
+    /// Process a packet, also returning why each depth pixel is invalid.
+    ///
+    /// With the edge-aware filter disabled, depth isn't clipped so only the stage 2 reasons are reported.
+    pub async fn process_with_invalid_reasons(
+        &self,
+        input: DepthPacket,
+    ) -> Result<(IrFrame, DepthFrame, Vec<DepthInvalidReason>), Box<dyn Error>> {
+        let mut reasons = vec![0u8; DEPTH_SIZE];
+        let (ir_frame, depth_frame) = self.process_packet(&input, Some(&mut reasons))?;
+        let reasons = reasons
+            .into_iter()
+            .map(|reason| DepthInvalidReason::from_u8(reason).ok_or("Unknown invalid reason"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((ir_frame, depth_frame, reasons))
+    }
+
+    fn process_packet(
+        &self,
+        input: &DepthPacket,
+        reasons: Option<&mut [u8]>,
+    ) -> Result<(IrFrame, DepthFrame), Box<dyn Error>> {
+        let mut ir_frame = IrFrame::from_packet(Vec::with_capacity(DEPTH_SIZE), input);
+        let mut depth_frame = DepthFrame::from_packet(Vec::with_capacity(DEPTH_SIZE), input);
+
+        self.queue
+            .write_buffer(&self.buffers.packet, 0, &input.buffer);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("depth packet"),
+            });
+
+        {
+            // wgpu synchronizes the storage buffers between dispatches
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("depth packet"),
+                timestamp_writes: None,
+            });
+
+            self.kernels.process_pixel_stage1_kernel.dispatch(&mut pass);
+
+            if self.config.enable_bilateral_filter {
+                self.kernels.filter_pixel_stage1_kernel.dispatch(&mut pass);
+            }
+
+            if self.config.enable_bilateral_filter {
+                self.kernels
+                    .process_filtered_pixel_stage2_kernel
+                    .dispatch(&mut pass);
+            } else {
+                self.kernels.process_pixel_stage2_kernel.dispatch(&mut pass);
+            }
+
+            if self.config.enable_edge_aware_filter {
+                self.kernels.filter_pixel_stage2_kernel.dispatch(&mut pass);
+            }
+        }
+
+        // ir, depth, and invalid reasons one after the other
+        let readback = self.device.create_buffer(&BufferDescriptor {
+            label: Some("readback"),
+            size: FRAME_BUFFER_SIZE * 3,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_buffer_to_buffer(&self.buffers.ir, 0, &readback, 0, FRAME_BUFFER_SIZE);
+        encoder.copy_buffer_to_buffer(
+            if self.config.enable_edge_aware_filter {
+                &self.buffers.filtered
+            } else {
+                &self.buffers.depth
+            },
+            0,
+            &readback,
+            FRAME_BUFFER_SIZE,
+            FRAME_BUFFER_SIZE,
+        );
+
+        if reasons.is_some() {
+            encoder.copy_buffer_to_buffer(
+                &self.buffers.invalid_reasons,
+                0,
+                &readback,
+                FRAME_BUFFER_SIZE * 2,
+                FRAME_BUFFER_SIZE,
+            );
+        }
+
+        self.queue.submit([encoder.finish()]);
+
+        let slice = readback.slice(..);
+        let (sender, receiver) = mpsc::channel();
+
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(PollType::Wait)?;
+        receiver.recv()??;
+
+        {
+            let data = slice.get_mapped_range();
+            let mut words = data
+                .chunks_exact(4)
+                .map(|bytes| [bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+            ir_frame
+                .buffer
+                .extend(words.by_ref().take(DEPTH_SIZE).map(f32::from_le_bytes));
+            depth_frame
+                .buffer
+                .extend(words.by_ref().take(DEPTH_SIZE).map(f32::from_le_bytes));
+
+            if let Some(reasons) = reasons {
+                for (reason, word) in reasons.iter_mut().zip(words) {
+                    *reason = u32::from_le_bytes(word) as u8;
+                }
+            }
+        }
+
+        readback.unmap();
+
+        Ok((ir_frame, depth_frame))
+    }
+}
+
+impl ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)> for WgpuDepthProcessor {
+    async fn process(&self, input: DepthPacket) -> Result<(IrFrame, DepthFrame), ProcessingError> {
+        Ok(self.process_packet(&input, None)?)
+    }
+}
+
+#[cfg(all(test, feature = "cpu_depth"))]
+mod tests {
+    use super::*;
+    use crate::processor::depth::{
+        fixtures::{ir_params, packet},
+        CpuDepthProcessor,
+    };
+
+    /// Number of pixels where `a` and `b` differ by more than `tolerance`, NaN only matches NaN.
+    fn mismatches(a: &DepthFrame, b: &DepthFrame, tolerance: f32) -> usize {
+        a.buffer
+            .iter()
+            .zip(&b.buffer)
+            .filter(|(a, b)| match (a.is_nan(), b.is_nan()) {
+                (false, false) => (*a - *b).abs() > tolerance * a.abs().max(1.0),
+                (a_nan, b_nan) => a_nan != b_nan,
+            })
+            .count()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a GPU adapter"]
+    async fn matches_cpu_processor() {
+        let ir_params = ir_params();
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+            .unwrap();
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default())
+            .await
+            .unwrap();
+        let gpu =
+            WgpuDepthProcessor::from_calibration(device, queue, &ir_params, &P0Tables::default())
+                .unwrap();
+        let mut cpu = CpuDepthProcessor::new().unwrap();
+
+        cpu.set_calibration(&ir_params, &P0Tables::default())
+            .unwrap();
+
+        let (cpu_ir, cpu_depth) = cpu.process(packet()).await.unwrap();
+        let (gpu_ir, gpu_depth) = gpu.process(packet()).await.unwrap();
+
+        // the filters threshold float values, rounding differences flip a few pixels
+        let allowed = DEPTH_SIZE / 1000;
+
+        assert!(mismatches(&cpu_ir, &gpu_ir, 1e-3) <= allowed);
+        assert!(mismatches(&cpu_depth, &gpu_depth, 1e-3) <= allowed);
+    }
+}
@@ -0,0 +1,446 @@
+/*
+ * This file is part of the OpenKinect Project. http://www.openkinect.org
+ *
+ * Copyright (c) 2014 individual OpenKinect contributors. See the CONTRIB file
+ * for details.
+ *
+ * This code is licensed to you under the terms of the Apache License, version
+ * 2.0, or, at your option, the terms of the GNU General Public License,
+ * version 2.0. See the APACHE20 and GPL2 files for the text of the licenses,
+ * or the following URLs:
+ * http://www.apache.org/licenses/LICENSE-2.0
+ * http://www.gnu.org/licenses/gpl-2.0.txt
+ *
+ * If you redistribute this file in source form, modified or unmodified, you
+ * may:
+ *   1) Leave this header intact and distribute it under the same terms,
+ *      accompanying it with the APACHE20 and GPL20 files, or
+ *   2) Delete the Apache 2.0 clause and accompany it with the GPL2 file, or
+ *   3) Delete the GPL v2 clause and accompany it with the APACHE20 file
+ * In all cases you must keep the copyright notice intact and include a copy
+ * of the CONTRIB file.
+ *
+ * Binary distributions must follow the binary distribution requirements of
+ * either License.
+ */
+
+// WGSL port of opencl_depth_packet_processor.cl, the constants are prepended by the processor.
+// WGSL has no 8 or 16 bits types: the packet is read as 32 bits words, the lookup table and
+// the edge test / invalid reason flags are stored on 32 bits.
+
+const PI: f32 = 3.14159265358979323846;
+
+const PHASE = vec3<f32>(PHASE_IN_RAD0, PHASE_IN_RAD1, PHASE_IN_RAD2);
+const AB_MULTIPLIER_PER_FRQ = vec3<f32>(AB_MULTIPLIER_PER_FRQ0, AB_MULTIPLIER_PER_FRQ1, AB_MULTIPLIER_PER_FRQ2);
+
+/*******************************************************************************
+ * Process pixel stage 1
+ ******************************************************************************/
+
+// uniform arrays have a 16 bytes stride, the 2048 entries are packed by 4
+@group(0) @binding(0) var<uniform> stage1_lut11to16: array<vec4<i32>, 512>;
+@group(0) @binding(1) var<storage, read> stage1_z_table: array<f32>;
+@group(0) @binding(2) var<storage, read> stage1_p0_table: array<vec3<f32>>;
+@group(0) @binding(3) var<storage, read> stage1_data: array<u32>;
+@group(0) @binding(4) var<storage, read_write> stage1_a_out: array<vec3<f32>>;
+@group(0) @binding(5) var<storage, read_write> stage1_b_out: array<vec3<f32>>;
+@group(0) @binding(6) var<storage, read_write> stage1_n_out: array<vec3<f32>>;
+@group(0) @binding(7) var<storage, read_write> stage1_ir_out: array<f32>;
+@group(0) @binding(8) var<storage, read_write> stage1_invalid_reasons: array<u32>;
+
+fn lut11to16(index: u32) -> f32
+{
+  return f32(stage1_lut11to16[index >> 2u][index & 3u]);
+}
+
+fn dataWord(index: u32) -> u32
+{
+  let word = stage1_data[index >> 1u];
+
+  return select(word & 0xffffu, word >> 16u, (index & 1u) == 1u);
+}
+
+fn decodePixelMeasurement(sub: u32, x: u32, y: u32) -> f32
+{
+  let row_idx = (424u * sub + y) * 352u;
+  let idx = ((x >> 2u) + ((x << 7u) & BFI_BITMASK)) * 11u;
+
+  let col_idx = idx >> 4u;
+  let upper_bytes = idx & 15u;
+  let lower_bytes = 16u - upper_bytes;
+
+  let data_idx0 = row_idx + col_idx;
+  let data_idx1 = row_idx + col_idx + 1u;
+
+  if(x < 1u || 510u < x || col_idx > 352u)
+  {
+    return lut11to16(0u);
+  }
+
+  return lut11to16(((dataWord(data_idx0) >> upper_bytes) | (dataWord(data_idx1) << lower_bytes)) & 2047u);
+}
+
+@compute @workgroup_size(64)
+fn processPixelStage1(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+  let i = global_id.x;
+
+  let x = i % 512u;
+  let y = i / 512u;
+
+  let y_tmp = 423u - y;
+  let y_in = select(423u - y_tmp, y_tmp + 212u, y_tmp < 212u);
+
+  let invalid = 0.0 >= stage1_z_table[i];
+  let p0 = stage1_p0_table[i];
+
+  let p0x_sin = -sin(PHASE + p0.x);
+  let p0y_sin = -sin(PHASE + p0.y);
+  let p0z_sin = -sin(PHASE + p0.z);
+  let p0x_cos = cos(PHASE + p0.x);
+  let p0y_cos = cos(PHASE + p0.y);
+  let p0z_cos = cos(PHASE + p0.z);
+
+  let v0 = vec3<f32>(decodePixelMeasurement(0u, x, y_in),
+                     decodePixelMeasurement(1u, x, y_in),
+                     decodePixelMeasurement(2u, x, y_in));
+  let v1 = vec3<f32>(decodePixelMeasurement(3u, x, y_in),
+                     decodePixelMeasurement(4u, x, y_in),
+                     decodePixelMeasurement(5u, x, y_in));
+  let v2 = vec3<f32>(decodePixelMeasurement(6u, x, y_in),
+                     decodePixelMeasurement(7u, x, y_in),
+                     decodePixelMeasurement(8u, x, y_in));
+
+  var a = vec3<f32>(dot(v0, p0x_cos),
+                    dot(v1, p0y_cos),
+                    dot(v2, p0z_cos)) * AB_MULTIPLIER_PER_FRQ;
+  var b = vec3<f32>(dot(v0, p0x_sin),
+                    dot(v1, p0y_sin),
+                    dot(v2, p0z_sin)) * AB_MULTIPLIER_PER_FRQ;
+
+  a = select(a, vec3<f32>(0.0), invalid);
+  b = select(b, vec3<f32>(0.0), invalid);
+  let n = sqrt(a * a + b * b);
+
+  let saturated = vec3<bool>(any(v0 == vec3<f32>(32767.0)),
+                             any(v1 == vec3<f32>(32767.0)),
+                             any(v2 == vec3<f32>(32767.0)));
+
+  stage1_a_out[i] = select(a, vec3<f32>(0.0), saturated);
+  stage1_b_out[i] = select(b, vec3<f32>(0.0), saturated);
+  stage1_n_out[i] = n;
+  stage1_ir_out[i] = min(dot(select(n, vec3<f32>(65535.0), saturated), vec3<f32>(0.333333333 * AB_MULTIPLIER * AB_OUTPUT_MULTIPLIER)), 65535.0);
+  stage1_invalid_reasons[i] = select(DEPTH_VALID, DEPTH_SATURATED, any(saturated));
+}
+
+/*******************************************************************************
+ * Filter pixel stage 1
+ ******************************************************************************/
+
+@group(0) @binding(10) var<storage, read> filter1_a: array<vec3<f32>>;
+@group(0) @binding(11) var<storage, read> filter1_b: array<vec3<f32>>;
+@group(0) @binding(12) var<storage, read> filter1_n: array<vec3<f32>>;
+@group(0) @binding(13) var<storage, read_write> filter1_a_out: array<vec3<f32>>;
+@group(0) @binding(14) var<storage, read_write> filter1_b_out: array<vec3<f32>>;
+@group(0) @binding(15) var<storage, read_write> filter1_max_edge_test: array<u32>;
+
+@compute @workgroup_size(64)
+fn filterPixelStage1(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+  let i = global_id.x;
+
+  let x = i % 512u;
+  let y = i / 512u;
+
+  let self_a = filter1_a[i];
+  let self_b = filter1_b[i];
+
+  var gaussian = array<f32, 9>(GAUSSIAN_KERNEL_0, GAUSSIAN_KERNEL_1, GAUSSIAN_KERNEL_2, GAUSSIAN_KERNEL_3, GAUSSIAN_KERNEL_4, GAUSSIAN_KERNEL_5, GAUSSIAN_KERNEL_6, GAUSSIAN_KERNEL_7, GAUSSIAN_KERNEL_8);
+
+  if(x < 1u || y < 1u || x > 510u || y > 422u)
+  {
+    filter1_a_out[i] = self_a;
+    filter1_b_out[i] = self_b;
+    filter1_max_edge_test[i] = 1u;
+    return;
+  }
+
+  var threshold = vec3<f32>(JOINT_BILATERAL_THRESHOLD);
+  var joint_bilateral_exp = vec3<f32>(JOINT_BILATERAL_EXP);
+
+  let self_norm = filter1_n[i];
+  let self_normalized_a = self_a / self_norm;
+  let self_normalized_b = self_b / self_norm;
+
+  var weight_acc = vec3<f32>(0.0);
+  var weighted_a_acc = vec3<f32>(0.0);
+  var weighted_b_acc = vec3<f32>(0.0);
+  var dist_acc = vec3<f32>(0.0);
+
+  let c0 = self_norm * self_norm < threshold;
+
+  threshold = select(threshold, vec3<f32>(0.0), c0);
+  joint_bilateral_exp = select(joint_bilateral_exp, vec3<f32>(0.0), c0);
+
+  var j = 0u;
+
+  for(var yi = 0u; yi < 3u; yi++)
+  {
+    var i_other = (y + yi - 1u) * 512u + x - 1u;
+
+    for(var xi = 0u; xi < 3u; xi++)
+    {
+      let other_a = filter1_a[i_other];
+      let other_b = filter1_b[i_other];
+      let other_norm = filter1_n[i_other];
+      let other_normalized_a = other_a / other_norm;
+      let other_normalized_b = other_b / other_norm;
+
+      let c1 = other_norm * other_norm < threshold;
+
+      let dist = 0.5 * (1.0 - (self_normalized_a * other_normalized_a + self_normalized_b * other_normalized_b));
+      let weight = select(gaussian[j] * exp(-1.442695 * joint_bilateral_exp * dist), vec3<f32>(0.0), c1);
+
+      weighted_a_acc += weight * other_a;
+      weighted_b_acc += weight * other_b;
+      weight_acc += weight;
+      dist_acc += select(dist, vec3<f32>(0.0), c1);
+
+      j++;
+      i_other++;
+    }
+  }
+
+  let c2 = vec3<f32>(0.0) < weight_acc;
+  filter1_a_out[i] = select(vec3<f32>(0.0), weighted_a_acc / weight_acc, c2);
+  filter1_b_out[i] = select(vec3<f32>(0.0), weighted_b_acc / weight_acc, c2);
+
+  filter1_max_edge_test[i] = select(0u, 1u, all(dist_acc < vec3<f32>(JOINT_BILATERAL_MAX_EDGE)));
+}
+
+/*******************************************************************************
+ * Process pixel stage 2
+ ******************************************************************************/
+
+@group(0) @binding(20) var<storage, read> stage2_a_in: array<vec3<f32>>;
+@group(0) @binding(21) var<storage, read> stage2_b_in: array<vec3<f32>>;
+@group(0) @binding(22) var<storage, read> stage2_x_table: array<f32>;
+@group(0) @binding(23) var<storage, read> stage2_z_table: array<f32>;
+@group(0) @binding(24) var<storage, read_write> stage2_depth: array<f32>;
+@group(0) @binding(25) var<storage, read_write> stage2_ir_sums: array<f32>;
+@group(0) @binding(26) var<storage, read_write> stage2_invalid_reasons: array<u32>;
+
+@compute @workgroup_size(64)
+fn processPixelStage2(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+  let i = global_id.x;
+  let a = stage2_a_in[i];
+  let b = stage2_b_in[i];
+
+  var phase = atan2(b, a);
+  phase = select(phase, phase + 2.0 * PI, phase < vec3<f32>(0.0));
+  // atan2(0, 0) is undefined in WGSL, where OpenCL gives 0
+  phase = select(phase, vec3<f32>(0.0), (phase != phase) | ((a == vec3<f32>(0.0)) & (b == vec3<f32>(0.0))));
+  let ir = sqrt(a * a + b * b) * AB_MULTIPLIER;
+
+  let ir_sum = ir.x + ir.y + ir.z;
+  let ir_min = min(ir.x, min(ir.y, ir.z));
+  let ir_max = max(ir.x, max(ir.y, ir.z));
+
+  var phase_final = 0.0;
+  var invalid_reason = stage2_invalid_reasons[i];
+
+  if(ir_min >= INDIVIDUAL_AB_THRESHOLD && ir_sum >= AB_THRESHOLD)
+  {
+    let t = phase / (2.0 * PI) * vec3<f32>(3.0, 15.0, 2.0);
+
+    let t0 = t.x;
+    let t1 = t.y;
+    let t2 = t.z;
+
+    let t5 = (floor((t1 - t0) * 0.333333 + 0.5) * 3.0 + t0);
+    var t3 = (-t2 + t5);
+    let t4 = t3 * 2.0;
+
+    let c1 = t4 >= -t4; // true if t4 positive
+
+    let f1 = select(-2.0, 2.0, c1);
+    let f2 = select(-0.5, 0.5, c1);
+    t3 *= f2;
+    t3 = (t3 - floor(t3)) * f1;
+
+    let c2 = 0.5 < abs(t3) && abs(t3) < 1.5;
+
+    var t6 = select(t5, t5 + 15.0, c2);
+    var t7 = select(t1, t1 + 15.0, c2);
+
+    var t8 = (floor((-t2 + t6) * 0.5 + 0.5) * 2.0 + t2) * 0.5;
+
+    t6 *= 0.333333; // = / 3
+    t7 *= 0.066667; // = / 15
+
+    let t9 = (t8 + t6 + t7); // transformed phase measurements (they are transformed and divided by the values the original values were multiplied with)
+    var t10 = t9 * 0.333333; // some avg
+
+    t6 *= 2.0 * PI;
+    t7 *= 2.0 * PI;
+    t8 *= 2.0 * PI;
+
+    // some cross product
+    let t8_new = t7 * 0.826977 - t8 * 0.110264;
+    let t6_new = t8 * 0.551318 - t6 * 0.826977;
+    let t7_new = t6 * 0.110264 - t7 * 0.551318;
+
+    t8 = t8_new;
+    t6 = t6_new;
+    t7 = t7_new;
+
+    let norm = t8 * t8 + t6 * t6 + t7 * t7;
+    let mask = select(0.0, 1.0, t9 >= 0.0);
+    t10 *= mask;
+
+    let slope_positive = 0.0 < AB_CONFIDENCE_SLOPE;
+
+    var ir_x = select(ir_max, ir_min, slope_positive);
+
+    ir_x = log(ir_x);
+    ir_x = (ir_x * AB_CONFIDENCE_SLOPE * 0.301030 + AB_CONFIDENCE_OFFSET) * 3.321928;
+    ir_x = exp(ir_x);
+    ir_x = clamp(ir_x, MIN_DEALIAS_CONFIDENCE, MAX_DEALIAS_CONFIDENCE);
+    ir_x *= ir_x;
+
+    let mask2 = select(0.0, 1.0, ir_x >= norm);
+
+    let t11 = t10 * mask2;
+
+    let mask3 = select(0.0, 1.0, MAX_DEALIAS_CONFIDENCE * MAX_DEALIAS_CONFIDENCE >= norm);
+    t10 *= mask3;
+    phase_final = t11; // (modeMask & 2) != 0, t10 otherwise
+
+    if(invalid_reason == DEPTH_VALID && phase_final <= 0.0)
+    {
+      invalid_reason = DEPTH_LOW_AMPLITUDE;
+    }
+  }
+  else if(invalid_reason == DEPTH_VALID)
+  {
+    invalid_reason = select(DEPTH_LOW_AMPLITUDE, DEPTH_NO_RETURN, ir_sum < AB_THRESHOLD);
+  }
+
+  let zmultiplier = stage2_z_table[i];
+  var xmultiplier = stage2_x_table[i];
+
+  phase_final = select(phase_final, phase_final + PHASE_OFFSET, 0.0 < phase_final);
+
+  let depth_linear = zmultiplier * phase_final;
+  let max_depth = phase_final * UNAMBIGUOUS_DIST * 2.0;
+
+  let cond1 = /*(modeMask & 32) != 0*/ 0.0 < depth_linear && 0.0 < max_depth;
+
+  xmultiplier = (xmultiplier * 90.0) / (max_depth * max_depth * 8192.0);
+
+  var depth_fit = depth_linear / (-depth_linear * xmultiplier + 1.0);
+  depth_fit = select(depth_fit, 0.0, depth_fit < 0.0);
+
+  let d = select(depth_linear, depth_fit, cond1); // r1.y -> later r2.z
+  stage2_depth[i] = d;
+  stage2_ir_sums[i] = ir_sum;
+  stage2_invalid_reasons[i] = invalid_reason;
+}
+
+/*******************************************************************************
+ * Filter pixel stage 2
+ ******************************************************************************/
+
+@group(0) @binding(30) var<storage, read> filter2_depth: array<f32>;
+@group(0) @binding(31) var<storage, read> filter2_ir_sums: array<f32>;
+@group(0) @binding(32) var<storage, read> filter2_max_edge_test: array<u32>;
+@group(0) @binding(33) var<storage, read_write> filter2_filtered: array<f32>;
+@group(0) @binding(34) var<storage, read_write> filter2_invalid_reasons: array<u32>;
+
+@compute @workgroup_size(64)
+fn filterPixelStage2(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+  let i = global_id.x;
+
+  let x = i % 512u;
+  let y = i / 512u;
+
+  let raw_depth = filter2_depth[i];
+  let ir_sum = filter2_ir_sums[i];
+  let edge_test = filter2_max_edge_test[i];
+
+  var filtered = 0.0;
+
+  if(raw_depth >= MIN_DEPTH && raw_depth <= MAX_DEPTH)
+  {
+    if(PRESERVE_EDGES || x < 1u || y < 1u || x > 510u || y > 422u)
+    {
+      filtered = raw_depth;
+    }
+    else
+    {
+      var ir_sum_acc = ir_sum;
+      var squared_ir_sum_acc = ir_sum * ir_sum;
+      var min_depth = raw_depth;
+      var max_depth = raw_depth;
+
+      for(var yi = 0u; yi < 3u; yi++)
+      {
+        var i_other = (y + yi - 1u) * 512u + x - 1u;
+
+        for(var xi = 0u; xi < 3u; xi++)
+        {
+          if(i_other != i)
+          {
+            let raw_depth_other = filter2_depth[i_other];
+            let ir_sum_other = filter2_ir_sums[i_other];
+
+            ir_sum_acc += ir_sum_other;
+            squared_ir_sum_acc += ir_sum_other * ir_sum_other;
+
+            if(0.0 < raw_depth_other)
+            {
+              min_depth = min(min_depth, raw_depth_other);
+              max_depth = max(max_depth, raw_depth_other);
+            }
+          }
+
+          i_other++;
+        }
+      }
+
+      var tmp0 = sqrt(squared_ir_sum_acc * 9.0 - ir_sum_acc * ir_sum_acc) / 9.0;
+      let edge_avg = max(ir_sum_acc / 9.0, EDGE_AB_AVG_MIN_VALUE);
+      tmp0 /= edge_avg;
+
+      let abs_min_diff = abs(raw_depth - min_depth);
+      let abs_max_diff = abs(raw_depth - max_depth);
+
+      let avg_diff = (abs_min_diff + abs_max_diff) * 0.5;
+      let max_abs_diff = max(abs_min_diff, abs_max_diff);
+
+      let cond0 =
+          0.0 < raw_depth &&
+          tmp0 >= EDGE_AB_STD_DEV_THRESHOLD &&
+          EDGE_CLOSE_DELTA_THRESHOLD < abs_min_diff &&
+          EDGE_FAR_DELTA_THRESHOLD < abs_max_diff &&
+          EDGE_MAX_DELTA_THRESHOLD < max_abs_diff &&
+          EDGE_AVG_DELTA_THRESHOLD < avg_diff;
+
+      if(!cond0 && edge_test != 0u)
+      {
+        let edge_count = 0.0;
+
+        filtered = select(raw_depth, 0.0, edge_count > MAX_EDGE_COUNT);
+      }
+    }
+  }
+
+  filter2_filtered[i] = filtered;
+
+  if(filter2_invalid_reasons[i] == DEPTH_VALID && filtered <= 0.0)
+  {
+    filter2_invalid_reasons[i] = select(select(DEPTH_EDGE_FILTERED, DEPTH_ABOVE_MAX, raw_depth > MAX_DEPTH), DEPTH_BELOW_MIN, raw_depth < MIN_DEPTH);
+  }
+}
@@ -0,0 +1,53 @@
+use std::error::Error;
+
+use thiserror::Error;
+
+use super::color::ColorSpace;
+
+/// Error of a [`ProcessorTrait`](super::ProcessorTrait), to tell the failure classes apart.
+#[derive(Error, Debug)]
+pub enum ProcessingError {
+    /// The JPEG decoder failed, often a corrupted packet worth skipping
+    #[error("JPEG decoding failed: {0}")]
+    JpegDecode(Box<dyn Error>),
+    #[cfg(any(feature = "opencl_depth", feature = "opencl_kde_depth"))]
+    #[error(transparent)]
+    OpenCl(#[from] ocl::Error),
+    #[error("Frame is {}x{}, expected {}x{}", actual.0, actual.1, expected.0, expected.1)]
+    DimensionMismatch {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    /// The buffer is shorter than the frame dimensions, or of another length than required
+    #[error("Frame buffer is {actual} bytes, expected {expected}")]
+    BufferLength { expected: usize, actual: usize },
+    #[error("Color space {0:?} isn't supported by this processor")]
+    UnsupportedColorSpace(ColorSpace),
+    #[error("{0}")]
+    Other(Box<dyn Error>),
+}
+
+impl ProcessingError {
+    /// Wrap an error of a JPEG decoder, for `map_err`.
+    pub fn jpeg_decode(error: impl Into<Box<dyn Error>>) -> Self {
+        Self::JpegDecode(error.into())
+    }
+}
+
+impl From<Box<dyn Error>> for ProcessingError {
+    fn from(error: Box<dyn Error>) -> Self {
+        Self::Other(error)
+    }
+}
+
+impl From<&str> for ProcessingError {
+    fn from(error: &str) -> Self {
+        Self::Other(error.into())
+    }
+}
+
+impl From<String> for ProcessingError {
+    fn from(error: String) -> Self {
+        Self::Other(error.into())
+    }
+}
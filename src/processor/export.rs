@@ -0,0 +1,288 @@
+//! Depth frame compression for network transport.
+//!
+//! Depth frames are encoded with RVL, the run-length and variable-length coding described by
+//! Wilson in "Fast Lossless Depth Image Compression" and used by Kinect streaming middleware.
+//! Depth is quantized to whole millimeters, which is lossless at the precision of the sensor.
+
+use super::depth::DepthFrame;
+use crate::COLOR_SIZE;
+
+/// Size in bytes of the header preceding the RVL stream, holding width, height, sequence and timestamp.
+const HEADER_SIZE: usize = 16;
+/// Largest frame decompressed, the depth registered to the color camera.
+const MAX_SIZE: usize = COLOR_SIZE;
+
+/// Compress a depth frame, invalid pixels are stored as zero.
+///
+/// Frames larger than 1920x1080 can't be decompressed.
+///
+/// # Arguments
+///
+/// * `frame` - Depth frame in millimeters
+pub fn compress_depth(frame: &DepthFrame) -> Vec<u8> {
+    let mut writer = NibbleWriter {
+        buffer: Vec::with_capacity(HEADER_SIZE + frame.buffer.len()),
+        word: 0,
+        nibbles: 0,
+    };
+
+    for value in [
+        frame.width as u32,
+        frame.height as u32,
+        frame.sequence,
+        frame.timestamp,
+    ] {
+        writer.buffer.extend(value.to_le_bytes());
+    }
+
+    let depth: Vec<u16> = frame.buffer[..frame.width * frame.height]
+        .iter()
+        .map(|depth| match depth.is_finite() {
+            true => depth.round().clamp(0.0, u16::MAX as f32) as u16,
+            false => 0,
+        })
+        .collect();
+    let mut previous = 0i32;
+    let mut index = 0;
+
+    while index < depth.len() {
+        let zeros = depth[index..]
+            .iter()
+            .take_while(|value| **value == 0)
+            .count();
+
+        index += zeros;
+        writer.encode(zeros as u32);
+
+        let nonzeros = depth[index..]
+            .iter()
+            .take_while(|value| **value != 0)
+            .count();
+
+        writer.encode(nonzeros as u32);
+
+        for value in &depth[index..index + nonzeros] {
+            let current = *value as i32;
+            let delta = current - previous;
+
+            // zigzag encoding, so small negative deltas stay small
+            writer.encode(((delta << 1) ^ (delta >> 31)) as u32);
+            previous = current;
+        }
+
+        index += nonzeros;
+    }
+
+    writer.finish()
+}
+
+/// Decompress a depth frame produced by [`compress_depth`].
+///
+/// Returns `None` if the data is truncated or malformed, or the frame is larger than 1920x1080.
+pub fn decompress_depth(data: &[u8]) -> Option<DepthFrame> {
+    if data.len() < HEADER_SIZE {
+        return None;
+    }
+
+    let header: Vec<u32> = data[..HEADER_SIZE]
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect();
+    let (width, height) = (header[0] as usize, header[1] as usize);
+    let size = width.checked_mul(height).filter(|size| *size <= MAX_SIZE)?;
+    let mut reader = NibbleReader {
+        words: data[HEADER_SIZE..].chunks_exact(4),
+        word: 0,
+        nibbles: 0,
+    };
+    let mut buffer = Vec::with_capacity(size);
+    let mut previous = 0i32;
+
+    while buffer.len() < size {
+        let zeros = reader.decode()? as usize;
+
+        if zeros > size - buffer.len() {
+            return None;
+        }
+
+        buffer.resize(buffer.len() + zeros, 0.0);
+
+        let nonzeros = reader.decode()? as usize;
+
+        if nonzeros > size - buffer.len() {
+            return None;
+        }
+
+        for _ in 0..nonzeros {
+            let positive = reader.decode()? as i32;
+            let delta = (positive >> 1) ^ -(positive & 1);
+            let current = previous.checked_add(delta)?;
+
+            if !(0..=u16::MAX as i32).contains(&current) {
+                return None;
+            }
+
+            buffer.push(current as f32);
+            previous = current;
+        }
+    }
+
+    Some(DepthFrame {
+        width,
+        height,
+        buffer,
+        sequence: header[2],
+        timestamp: header[3],
+    })
+}
+
+/// Packs 4 bits nibbles, most significant first, into little endian 32 bits words.
+struct NibbleWriter {
+    buffer: Vec<u8>,
+    word: u32,
+    nibbles: usize,
+}
+
+impl NibbleWriter {
+    /// Variable length encoding, 3 bits of data per nibble and the 4th one flagging a continuation.
+    fn encode(&mut self, mut value: u32) {
+        loop {
+            let mut nibble = value & 0x7;
+
+            value >>= 3;
+
+            if value != 0 {
+                nibble |= 0x8;
+            }
+
+            self.word = (self.word << 4) | nibble;
+            self.nibbles += 1;
+
+            if self.nibbles == 8 {
+                self.buffer.extend(self.word.to_le_bytes());
+                self.word = 0;
+                self.nibbles = 0;
+            }
+
+            if value == 0 {
+                break;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nibbles > 0 {
+            self.word <<= 4 * (8 - self.nibbles);
+            self.buffer.extend(self.word.to_le_bytes());
+        }
+
+        self.buffer
+    }
+}
+
+struct NibbleReader<'a> {
+    words: std::slice::ChunksExact<'a, u8>,
+    word: u32,
+    nibbles: usize,
+}
+
+impl NibbleReader<'_> {
+    fn decode(&mut self) -> Option<u32> {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            if self.nibbles == 0 {
+                self.word = u32::from_le_bytes(self.words.next()?.try_into().unwrap());
+                self.nibbles = 8;
+            }
+
+            let nibble = self.word >> 28;
+
+            self.word <<= 4;
+            self.nibbles -= 1;
+
+            // no valid value needs more than 32 bits
+            if shift > 29 {
+                return None;
+            }
+
+            value |= (nibble & 0x7) << shift;
+            shift += 3;
+
+            if nibble & 0x8 == 0 {
+                return Some(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEPTH_HEIGHT, DEPTH_WIDTH};
+
+    fn frame() -> DepthFrame {
+        DepthFrame {
+            width: DEPTH_WIDTH,
+            height: DEPTH_HEIGHT,
+            buffer: (0..DEPTH_WIDTH * DEPTH_HEIGHT)
+                .map(|index| match index % 7 {
+                    0 | 1 => 0.0,
+                    2 => f32::NAN,
+                    _ => 500.0 + (index % 4000) as f32 + 0.25,
+                })
+                .collect(),
+            sequence: 3,
+            timestamp: 4,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let frame = frame();
+        let data = compress_depth(&frame);
+        let decompressed = decompress_depth(&data).unwrap();
+
+        assert!(data.len() < frame.buffer.len() * 2);
+        assert_eq!(
+            (decompressed.width, decompressed.height),
+            (DEPTH_WIDTH, DEPTH_HEIGHT)
+        );
+        assert_eq!((decompressed.sequence, decompressed.timestamp), (3, 4));
+
+        for (value, decompressed) in frame.buffer.iter().zip(&decompressed.buffer) {
+            let expected = if value.is_nan() { 0.0 } else { value.round() };
+
+            assert_eq!(*decompressed, expected);
+        }
+    }
+
+    #[test]
+    fn malformed_data() {
+        let data = compress_depth(&frame());
+
+        assert!(decompress_depth(&data[..HEADER_SIZE - 1]).is_none());
+        assert!(decompress_depth(&data[..data.len() - 4]).is_none());
+
+        // dimensions overflowing or too large to allocate
+        for (width, height) in [(u32::MAX, u32::MAX), (65535, 65535)] {
+            let mut header = data[..HEADER_SIZE].to_vec();
+
+            header[..4].copy_from_slice(&width.to_le_bytes());
+            header[4..8].copy_from_slice(&height.to_le_bytes());
+            // a single zero run of the whole frame
+            header.extend(0x7777_7770u32.to_le_bytes());
+
+            assert!(decompress_depth(&header).is_none());
+        }
+
+        // a zero run longer than the frame
+        let mut header = data[..HEADER_SIZE].to_vec();
+
+        header[..8].copy_from_slice(&[2, 0, 0, 0, 1, 0, 0, 0]);
+        header.extend(0x3000_0000u32.to_le_bytes());
+
+        assert!(decompress_depth(&header).is_none());
+    }
+}
@@ -0,0 +1,241 @@
+use nalgebra::{Isometry3, Point3, Vector3};
+
+use crate::{data::IrParams, DEPTH_HEIGHT, DEPTH_WIDTH};
+
+use super::{depth::DepthFrame, ProcessingError};
+
+/// Truncated signed distance function volume.
+///
+/// A cube of `resolution`³ voxels of `voxel_size` meters, starting at `origin` in world space.
+/// Depth frames are fused into it with a running weighted average, in the spirit of KinectFusion.
+pub struct TsdfVolume {
+    resolution: usize,
+    voxel_size: f32,
+    origin: Point3<f32>,
+    /// Truncation distance (meter)
+    truncation: f32,
+    /// Upper bound of the per voxel weight, lower values adapt faster to scene changes
+    max_weight: f32,
+    tsdf: Vec<f32>,
+    weights: Vec<f32>,
+}
+
+impl TsdfVolume {
+    pub fn new(resolution: usize, voxel_size: f32, origin: Point3<f32>, truncation: f32) -> Self {
+        let voxel_count = resolution * resolution * resolution;
+
+        Self {
+            resolution,
+            voxel_size,
+            origin,
+            truncation,
+            max_weight: 64.0,
+            tsdf: vec![1.0; voxel_count],
+            weights: vec![0.0; voxel_count],
+        }
+    }
+
+    pub fn set_max_weight(&mut self, max_weight: f32) {
+        self.max_weight = max_weight;
+    }
+
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    pub fn voxel_size(&self) -> f32 {
+        self.voxel_size
+    }
+
+    pub fn origin(&self) -> Point3<f32> {
+        self.origin
+    }
+
+    /// Reset every voxel to unobserved.
+    pub fn clear(&mut self) {
+        self.tsdf.fill(1.0);
+        self.weights.fill(0.0);
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + (y + z * self.resolution) * self.resolution
+    }
+
+    fn voxel_center(&self, x: usize, y: usize, z: usize) -> Point3<f32> {
+        self.origin + Vector3::new(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5) * self.voxel_size
+    }
+
+    /// Fuse an undistorted depth frame into the volume.
+    ///
+    /// [`ProcessingError::DimensionMismatch`] if the frame isn't 512x424, the size of the intrinsics.
+    ///
+    /// # Arguments
+    ///
+    /// * `undistorted` - Depth frame in millimeters, as returned by the registration
+    /// * `ir_params` - Intrinsics of the depth camera
+    /// * `pose` - Camera to world transform (meter)
+    pub fn integrate(
+        &mut self,
+        undistorted: &DepthFrame,
+        ir_params: &IrParams,
+        pose: &Isometry3<f32>,
+    ) -> Result<(), ProcessingError> {
+        let (width, height) = (undistorted.width, undistorted.height);
+
+        if (width, height) != (DEPTH_WIDTH, DEPTH_HEIGHT)
+            || undistorted.buffer.len() < width * height
+        {
+            return Err(ProcessingError::DimensionMismatch {
+                expected: (DEPTH_WIDTH, DEPTH_HEIGHT),
+                actual: (width, height),
+            });
+        }
+
+        for z in 0..self.resolution {
+            for y in 0..self.resolution {
+                for x in 0..self.resolution {
+                    let point = pose.inverse_transform_point(&self.voxel_center(x, y, z));
+
+                    if point.z <= 0.0 {
+                        continue;
+                    }
+
+                    // inverse of the back-projection done in Registration::point_to_xyz
+                    let u = point.x * ir_params.fx / point.z + ir_params.cx - 0.5;
+                    let v = point.y * ir_params.fy / point.z + ir_params.cy - 0.5;
+                    let (u, v) = (u.round(), v.round());
+
+                    if u < 0.0 || v < 0.0 || u >= width as f32 || v >= height as f32 {
+                        continue;
+                    }
+
+                    // scaling factor, so that value of 1 is one meter.
+                    let depth = undistorted.buffer[v as usize * width + u as usize] / 1000.0;
+
+                    // depth value is not valid
+                    if depth.is_nan() || depth <= 0.001 {
+                        continue;
+                    }
+
+                    let sdf = depth - point.z;
+
+                    // voxel is hidden behind the observed surface
+                    if sdf < -self.truncation {
+                        continue;
+                    }
+
+                    let index = self.index(x, y, z);
+                    let tsdf = (sdf / self.truncation).min(1.0);
+                    let weight = self.weights[index];
+
+                    self.tsdf[index] = (self.tsdf[index] * weight + tsdf) / (weight + 1.0);
+                    self.weights[index] = (weight + 1.0).min(self.max_weight);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Extract the surface as the zero crossings of the volume, in world space (meter).
+    pub fn extract_point_cloud(&self) -> Vec<Point3<f32>> {
+        let mut points = Vec::new();
+
+        for z in 0..self.resolution {
+            for y in 0..self.resolution {
+                for x in 0..self.resolution {
+                    let index = self.index(x, y, z);
+
+                    if self.weights[index] <= 0.0 {
+                        continue;
+                    }
+
+                    let tsdf = self.tsdf[index];
+                    let center = self.voxel_center(x, y, z);
+
+                    // look for a sign change with the next voxel on each axis
+                    for (nx, ny, nz) in [(x + 1, y, z), (x, y + 1, z), (x, y, z + 1)] {
+                        if nx >= self.resolution || ny >= self.resolution || nz >= self.resolution {
+                            continue;
+                        }
+
+                        let other_index = self.index(nx, ny, nz);
+
+                        if self.weights[other_index] <= 0.0 {
+                            continue;
+                        }
+
+                        let other_tsdf = self.tsdf[other_index];
+
+                        if (tsdf > 0.0) == (other_tsdf > 0.0) {
+                            continue;
+                        }
+
+                        let t = tsdf / (tsdf - other_tsdf);
+                        let other_center = self.voxel_center(nx, ny, nz);
+
+                        points.push(center + (other_center - center) * t);
+                    }
+                }
+            }
+        }
+
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane(width: usize, height: usize, depth: f32) -> DepthFrame {
+        DepthFrame {
+            width,
+            height,
+            buffer: vec![depth; width * height],
+            sequence: 0,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn plane_zero_crossing() {
+        let ir_params = IrParams {
+            fx: 365.0,
+            fy: 365.0,
+            cx: 256.0,
+            cy: 212.0,
+            ..Default::default()
+        };
+        // 32 cm cube in front of the camera, around the plane 1 m away
+        let mut volume = TsdfVolume::new(16, 0.02, Point3::new(-0.16, -0.16, 0.84), 0.05);
+
+        volume
+            .integrate(
+                &plane(DEPTH_WIDTH, DEPTH_HEIGHT, 1000.0),
+                &ir_params,
+                &Isometry3::identity(),
+            )
+            .unwrap();
+
+        let points = volume.extract_point_cloud();
+
+        assert_eq!(points.len(), 16 * 16);
+
+        for point in points {
+            assert!((point.z - 1.0).abs() < 1e-4, "{point}");
+        }
+
+        assert!(matches!(
+            volume.integrate(
+                &plane(DEPTH_HEIGHT, DEPTH_WIDTH, 1000.0),
+                &ir_params,
+                &Isometry3::identity()
+            ),
+            Err(ProcessingError::DimensionMismatch {
+                actual: (DEPTH_HEIGHT, DEPTH_WIDTH),
+                ..
+            })
+        ));
+    }
+}
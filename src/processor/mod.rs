@@ -1,11 +1,21 @@
 pub mod color;
+pub mod denoise;
 pub mod depth;
+mod error;
+pub mod export;
+#[cfg(feature = "fusion")]
+pub mod fusion;
+pub mod pacing;
 mod registration;
+pub mod temporal;
 
 use std::{future::Future, marker::PhantomData};
 
 use crate::Error;
 
+use color::{ColorFrame, ColorPacket};
+use depth::{DepthFrame, DepthPacket, IrFrame};
+pub use error::ProcessingError;
 pub use registration::*;
 
 pub trait ProcessTrait: Sized {
@@ -13,17 +23,12 @@ pub trait ProcessTrait: Sized {
         self,
         processor: &P,
     ) -> impl Future<Output = Result<O, Error>> {
-        async {
-            processor
-                .process(self)
-                .await
-                .map_err(|error| Error::Processing(error))
-        }
+        async { processor.process(self).await.map_err(Error::Processing) }
     }
 }
 
 pub trait ProcessorTrait<I, O> {
-    fn process(&self, input: I) -> impl Future<Output = Result<O, Box<dyn std::error::Error>>>;
+    fn process(&self, input: I) -> impl Future<Output = Result<O, ProcessingError>>;
 
     fn pipe<'a, 'b, T, P>(&'a self, processor: &'b P) -> PipedProcessor<'a, 'b, I, O, T, Self, P>
     where
@@ -40,10 +45,32 @@ pub trait ProcessorTrait<I, O> {
     }
 }
 
+/// Process a color and a depth packet concurrently.
+///
+/// Both processing futures are joined on the current task, so they overlap as soon as
+/// one of the processors yields (e.g. a GPU backend waiting on its queue).
+pub async fn process_rgbd<CP, DP>(
+    color_packet: ColorPacket,
+    depth_packet: DepthPacket,
+    color_processor: &CP,
+    depth_processor: &DP,
+) -> Result<(ColorFrame, IrFrame, DepthFrame), Error>
+where
+    CP: ProcessorTrait<ColorPacket, ColorFrame>,
+    DP: ProcessorTrait<DepthPacket, (IrFrame, DepthFrame)>,
+{
+    let (color_frame, (ir_frame, depth_frame)) = tokio::try_join!(
+        color_packet.process(color_processor),
+        depth_packet.process(depth_processor)
+    )?;
+
+    Ok((color_frame, ir_frame, depth_frame))
+}
+
 pub struct NoopProcessor;
 
 impl<T> ProcessorTrait<T, ()> for NoopProcessor {
-    async fn process(&self, _: T) -> Result<(), Box<dyn std::error::Error>> {
+    async fn process(&self, _: T) -> Result<(), ProcessingError> {
         Ok(())
     }
 }
@@ -65,7 +92,7 @@ where
     P1: ProcessorTrait<I, T>,
     P2: ProcessorTrait<T, O>,
 {
-    async fn process(&self, input: I) -> Result<O, Box<dyn std::error::Error>> {
+    async fn process(&self, input: I) -> Result<O, ProcessingError> {
         self.processor2
             .process(self.processor1.process(input).await?)
             .await
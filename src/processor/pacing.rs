@@ -0,0 +1,142 @@
+//! Pacing of frames to a steady frame rate, e.g. to encode a video or a time-lapse.
+//!
+//! The device timestamps jitter with the USB transfers, and frames are sometimes lost.
+//! Instead of only skipping frames, the pacer maps each frame to the slots of a fixed rate
+//! output stream, dropping the frames arriving between two slots and holding a frame over
+//! the slots missed before it.
+
+use std::time::Duration;
+
+/// Ticks of the packet timestamps per second, they tick every 0.1 ms.
+const TIMESTAMP_FREQUENCY: f64 = 10000.0;
+
+/// Stateful pacer deciding how many times each frame is output, feed it the frames of a stream in order.
+pub struct FramePacer {
+    target_fps: f32,
+    max_correction: Duration,
+    previous_timestamp: Option<u32>,
+    /// Time of the current frame since the first one, in timestamp ticks
+    time: f64,
+    /// Time of the next output slot, in timestamp ticks
+    next_slot: f64,
+    output_frames: u64,
+}
+
+impl FramePacer {
+    /// # Arguments
+    ///
+    /// * `target_fps` - Frame rate of the output stream
+    /// * `max_correction` - How much earlier than its slot a frame may be and still fill it
+    pub fn new(target_fps: f32, max_correction: Duration) -> Self {
+        Self {
+            target_fps,
+            max_correction,
+            previous_timestamp: None,
+            time: 0.0,
+            next_slot: 0.0,
+            output_frames: 0,
+        }
+    }
+
+    pub fn set_target_fps(&mut self, target_fps: f32) {
+        self.target_fps = target_fps;
+    }
+
+    pub fn target_fps(&self) -> f32 {
+        self.target_fps
+    }
+
+    pub fn set_max_correction(&mut self, max_correction: Duration) {
+        self.max_correction = max_correction;
+    }
+
+    pub fn max_correction(&self) -> Duration {
+        self.max_correction
+    }
+
+    /// Frames output since the first frame, the index of the next output frame.
+    pub fn output_frames(&self) -> u64 {
+        self.output_frames
+    }
+
+    /// Restart the output stream at the next frame, e.g. after the stream was paused.
+    pub fn reset(&mut self) {
+        self.previous_timestamp = None;
+        self.time = 0.0;
+        self.next_slot = 0.0;
+        self.output_frames = 0;
+    }
+
+    /// Number of times the frame with this device timestamp must be output,
+    /// 0 drops it and more than 1 holds it over the slots missed before it.
+    pub fn pace(&mut self, timestamp: u32) -> u32 {
+        if let Some(previous_timestamp) = self.previous_timestamp {
+            self.time += timestamp.wrapping_sub(previous_timestamp) as f64;
+        }
+
+        self.previous_timestamp = Some(timestamp);
+
+        let interval = TIMESTAMP_FREQUENCY / self.target_fps as f64;
+        let reach = self.time + self.max_correction.as_secs_f64() * TIMESTAMP_FREQUENCY;
+
+        if reach < self.next_slot {
+            return 0;
+        }
+
+        let slots = ((reach - self.next_slot) / interval) as u32 + 1;
+
+        self.next_slot += slots as f64 * interval;
+        self.output_frames += slots as u64;
+
+        slots
+    }
+}
+
+impl Default for FramePacer {
+    /// 30 fps with a correction of 5 ms, about the USB jitter.
+    fn default() -> Self {
+        Self::new(30.0, Duration::from_millis(5))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Timestamps of a 30 fps stream, jittering by up to 2 ms.
+    fn timestamps(count: u32) -> impl Iterator<Item = u32> {
+        (0..count).map(|index| index * 333 + [0, 20, 5, 15][index as usize % 4])
+    }
+
+    #[test]
+    fn steady_cadence() {
+        let mut pacer = FramePacer::default();
+        let repeats: Vec<_> = timestamps(30)
+            .map(|timestamp| pacer.pace(timestamp))
+            .collect();
+
+        assert!(repeats.iter().all(|&repeat| repeat == 1));
+
+        pacer.reset();
+        pacer.set_target_fps(15.0);
+
+        let repeats: Vec<_> = timestamps(30)
+            .map(|timestamp| pacer.pace(timestamp))
+            .collect();
+
+        assert_eq!(repeats.iter().sum::<u32>(), 15);
+        assert!(repeats.chunks(2).all(|pair| pair == [1, 0]));
+    }
+
+    #[test]
+    fn lost_frames_are_held() {
+        let mut pacer = FramePacer::default();
+        let repeats: Vec<_> = timestamps(8)
+            .filter(|timestamp| !(1000..2000).contains(timestamp))
+            .map(|timestamp| pacer.pace(timestamp.wrapping_add(u32::MAX - 1000)))
+            .collect();
+
+        assert_eq!(repeats, [1, 1, 1, 4, 1]);
+        assert_eq!(pacer.output_frames(), 8);
+    }
+}
@@ -1,11 +1,20 @@
-use std::f32::{INFINITY, NAN};
+use std::{
+    f32::{INFINITY, NAN},
+    io::{self, Write},
+};
+
+#[cfg(feature = "serde")]
+use std::io::Read;
 
 use crate::{
     data::{ColorParams, IrParams},
-    COLOR_SIZE, COLOR_WIDTH, DEPTH_HEIGHT, DEPTH_SIZE, DEPTH_WIDTH,
+    COLOR_HEIGHT, COLOR_SIZE, COLOR_WIDTH, DEPTH_HEIGHT, DEPTH_SIZE, DEPTH_WIDTH,
 };
 
-use super::{color::ColorFrame, depth::DepthFrame};
+use super::{
+    color::{ColorFrame, ColorSpace},
+    depth::DepthFrame,
+};
 
 const FILTER_WIDTH_HALF: isize = 2;
 const FILTER_HEIGHT_HALF: isize = 1;
@@ -15,30 +24,109 @@ const FILTER_TOLERANCE: f32 = 0.01;
 const DEPTH_Q: f32 = 0.01;
 const COLOR_Q: f32 = 0.002199;
 
+/// XYZ points of a depth frame in meters, row major, see [`Registration::point_cloud`].
+#[derive(Clone)]
+pub struct PointCloudFrame {
+    pub width: usize,
+    pub height: usize,
+    /// `[NaN; 3]` for pixels without a valid depth.
+    pub points: Vec<[f32; 3]>,
+
+    pub sequence: u32,
+    pub timestamp: u32,
+}
+
 /// Registration will only work contiguous color space
+///
+/// It only needs the calibration parameters, set with [`Registration::set_ir_params`] and
+/// [`Registration::set_color_params`], so recorded frames can be registered without a device.
+/// Prefer [`Registration::builder`] which checks both are set.
 pub struct Registration {
     /// Depth camera parameters.
     ir_params: IrParams,
     /// Color camera parameters.
     color_params: ColorParams,
+    /// The maps are only computed once both parameters are set.
+    has_ir_params: bool,
+    has_color_params: bool,
     distort_map: Box<[usize; DEPTH_SIZE]>,
     depth_to_color_map_x: Box<[f32; DEPTH_SIZE]>,
     depth_to_color_map_y: Box<[f32; DEPTH_SIZE]>,
     depth_to_color_map_yi: Box<[usize; DEPTH_SIZE]>,
 }
 
+/// Builder of a [`Registration`], failing if a calibration parameter is missing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RegistrationBuilder {
+    ir_params: Option<IrParams>,
+    color_params: Option<ColorParams>,
+}
+
+impl RegistrationBuilder {
+    pub fn ir_params(mut self, ir_params: &IrParams) -> Self {
+        self.ir_params = Some(*ir_params);
+        self
+    }
+
+    pub fn color_params(mut self, color_params: &ColorParams) -> Self {
+        self.color_params = Some(*color_params);
+        self
+    }
+
+    /// Build the registration, computing the depth to color maps once.
+    pub fn build(self) -> Result<Registration, crate::Error> {
+        let mut registration = Registration::new();
+
+        registration.set_ir_params(
+            &self
+                .ir_params
+                .ok_or(crate::Error::MissingCalibration("IR parameters"))?,
+        );
+        registration.set_color_params(
+            &self
+                .color_params
+                .ok_or(crate::Error::MissingCalibration("Color parameters"))?,
+        );
+
+        Ok(registration)
+    }
+}
+
+/// Calibration saved by [`Registration::save_calibration`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Calibration {
+    ir_params: IrParams,
+    color_params: ColorParams,
+}
+
+/// Allocate a map directly on the heap, `Box::new` would build it on the stack first.
+fn boxed_map<T: Clone>(value: T) -> Box<[T; DEPTH_SIZE]> {
+    vec![value; DEPTH_SIZE]
+        .into_boxed_slice()
+        .try_into()
+        .ok()
+        .unwrap()
+}
+
 impl Registration {
     pub fn new() -> Self {
         Self {
             ir_params: Default::default(),
             color_params: Default::default(),
-            distort_map: Box::new([0; DEPTH_SIZE]),
-            depth_to_color_map_x: Box::new([0.0; DEPTH_SIZE]),
-            depth_to_color_map_y: Box::new([0.0; DEPTH_SIZE]),
-            depth_to_color_map_yi: Box::new([0; DEPTH_SIZE]),
+            has_ir_params: false,
+            has_color_params: false,
+            distort_map: boxed_map(0),
+            depth_to_color_map_x: boxed_map(0.0),
+            depth_to_color_map_y: boxed_map(0.0),
+            depth_to_color_map_yi: boxed_map(0),
         }
     }
 
+    pub fn builder() -> RegistrationBuilder {
+        RegistrationBuilder::default()
+    }
+
     fn fill_depth_to_color_map(&mut self) {
         for y in 0..DEPTH_HEIGHT {
             for x in 0..DEPTH_WIDTH {
@@ -64,14 +152,53 @@ impl Registration {
         }
     }
 
+    /// The maps are computed once the color parameters are set too.
     pub fn set_ir_params(&mut self, ir_params: &IrParams) {
         self.ir_params = *ir_params;
-        self.fill_depth_to_color_map();
+        self.has_ir_params = true;
+
+        if self.has_color_params {
+            self.fill_depth_to_color_map();
+        }
     }
 
+    /// The maps are computed once the IR parameters are set too.
     pub fn set_color_params(&mut self, color_params: &ColorParams) {
         self.color_params = *color_params;
-        self.fill_depth_to_color_map();
+        self.has_color_params = true;
+
+        if self.has_ir_params {
+            self.fill_depth_to_color_map();
+        }
+    }
+
+    /// Save the calibration parameters as JSON, to register frames later without querying the device.
+    ///
+    /// The p0 tables needed by the depth processors aren't part of the registration,
+    /// serialize [`P0Tables`](crate::data::P0Tables) alongside.
+    #[cfg(feature = "serde")]
+    pub fn save_calibration<W: Write>(&self, writer: W) -> Result<(), crate::Error> {
+        serde_json::to_writer_pretty(
+            writer,
+            &Calibration {
+                ir_params: self.ir_params,
+                color_params: self.color_params,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Create a registration from calibration parameters saved by [`Registration::save_calibration`].
+    #[cfg(feature = "serde")]
+    pub fn load_calibration<R: Read>(reader: R) -> Result<Self, crate::Error> {
+        let calibration: Calibration = serde_json::from_reader(reader)?;
+        let mut registration = Self::new();
+
+        registration.set_ir_params(&calibration.ir_params);
+        registration.set_color_params(&calibration.color_params);
+
+        Ok(registration)
     }
 
     pub fn undistort_depth_and_color(
@@ -80,11 +207,22 @@ impl Registration {
         depth_frame: &DepthFrame,
         enable_filter: bool,
     ) -> (ColorFrame, DepthFrame) {
+        // every depth processor outputs DEPTH_WIDTH x DEPTH_HEIGHT frames
+        debug_assert_eq!(
+            (depth_frame.width, depth_frame.height),
+            (DEPTH_WIDTH, DEPTH_HEIGHT)
+        );
+        debug_assert_eq!(
+            (color_frame.width, color_frame.height),
+            (COLOR_WIDTH, COLOR_HEIGHT)
+        );
+
         let bytes_per_pixel = color_frame.color_space.bytes_per_pixel();
         let mut registered_frame = ColorFrame {
             color_space: color_frame.color_space,
             width: DEPTH_WIDTH,
             height: DEPTH_HEIGHT,
+            stride: DEPTH_WIDTH * bytes_per_pixel,
             buffer: vec![0; DEPTH_SIZE * bytes_per_pixel],
             sequence: color_frame.sequence,
             timestamp: color_frame.timestamp,
@@ -128,12 +266,7 @@ impl Registration {
                 continue;
             }
 
-            // calculating x offset for color image based on depth value
-            let cx = ((self.depth_to_color_map_x[i] + (self.color_params.shift_m / z))
-                * self.color_params.fx
-                + self.color_params.cx.round()) as usize;
-            // getting y offset for depth image
-            let cy = self.depth_to_color_map_yi[i];
+            let (cx, cy) = self.color_pixel(i, z);
             // combining offsets
             let c_off = cx + cy * COLOR_WIDTH;
 
@@ -186,16 +319,65 @@ impl Registration {
                 }
             }
 
-            let c_off = c_off * bytes_per_pixel;
             let r_off = i * bytes_per_pixel;
 
             registered_frame.buffer[r_off..r_off + bytes_per_pixel]
-                .copy_from_slice(&color_frame.buffer[c_off..c_off + bytes_per_pixel]);
+                .copy_from_slice(color_frame.pixel(c_off % COLOR_WIDTH, c_off / COLOR_WIDTH));
         }
 
         (registered_frame, undistorted_frame)
     }
 
+    /// Color pixel coordinates of an undistorted depth pixel, given its depth.
+    fn color_pixel(&self, index: usize, z: f32) -> (usize, usize) {
+        (
+            // calculating x offset for color image based on depth value
+            ((self.depth_to_color_map_x[index] + (self.color_params.shift_m / z))
+                * self.color_params.fx
+                + self.color_params.cx.round()) as usize,
+            // getting y offset for depth image
+            self.depth_to_color_map_yi[index],
+        )
+    }
+
+    /// Project the depth in the color camera, producing a full resolution depth frame
+    /// where each color pixel gets the depth of the closest point projecting to it.
+    ///
+    /// The result is sparse, color pixels no depth pixel projects to are set to zero.
+    pub fn register_depth_to_color(&self, depth_frame: &DepthFrame) -> DepthFrame {
+        // initializing with values outside of the Kinect2 range so the closest point wins
+        let mut buffer = vec![INFINITY; COLOR_SIZE];
+
+        for i in 0..DEPTH_SIZE {
+            let z = depth_frame.buffer[self.distort_map[i]];
+
+            // checking for invalid depth value
+            if z.is_nan() || z <= 0.0 {
+                continue;
+            }
+
+            let (cx, cy) = self.color_pixel(i, z);
+            let c_off = cx + cy * COLOR_WIDTH;
+
+            // z-buffering, keep the closest point when occluded ones map to the same pixel
+            if cx < COLOR_WIDTH && c_off < COLOR_SIZE && z < buffer[c_off] {
+                buffer[c_off] = z;
+            }
+        }
+
+        for z in buffer.iter_mut().filter(|z| z.is_infinite()) {
+            *z = 0.0;
+        }
+
+        DepthFrame {
+            width: COLOR_WIDTH,
+            height: COLOR_HEIGHT,
+            buffer,
+            sequence: depth_frame.sequence,
+            timestamp: depth_frame.timestamp,
+        }
+    }
+
     pub fn undistort_depth(&self, depth_frame: &DepthFrame) -> DepthFrame {
         let mut undistorted_frame = DepthFrame {
             width: DEPTH_WIDTH,
@@ -221,6 +403,45 @@ impl Registration {
         undistorted_frame
     }
 
+    /// Fraction of valid depth pixels that received a non-zero color after registration.
+    /// A low value hints at a misaligned mounting or a partially obscured color lens.
+    pub fn color_coverage(
+        &self,
+        undistorted_frame: &DepthFrame,
+        registered_frame: &ColorFrame,
+    ) -> f32 {
+        let bytes_per_pixel = registered_frame.color_space.bytes_per_pixel();
+        let mut valid_depth = 0;
+        let mut colored = 0;
+
+        if bytes_per_pixel == 0 {
+            return 0.0;
+        }
+
+        for (z, pixel) in undistorted_frame
+            .buffer
+            .iter()
+            .zip(registered_frame.pixels())
+        {
+            // checking for invalid depth value
+            if z.is_nan() || *z <= 0.0 {
+                continue;
+            }
+
+            valid_depth += 1;
+
+            if pixel.iter().any(|value| *value != 0) {
+                colored += 1;
+            }
+        }
+
+        if valid_depth == 0 {
+            0.0
+        } else {
+            colored as f32 / valid_depth as f32
+        }
+    }
+
     pub fn xyz_to_point(&self, dx: usize, dy: usize, dz: f32) -> (f32, f32) {
         let index = dx + dy * DEPTH_WIDTH;
 
@@ -239,16 +460,14 @@ impl Registration {
         x: usize,
         y: usize,
     ) -> (f32, f32, f32, Vec<u8>) {
-        let bytes_per_pixel = registered_frame.color_space.bytes_per_pixel();
-        let (x, y, z) = self.point_to_xyz(undistorted_frame, x, y);
-        let c_off = DEPTH_WIDTH * y as usize + x as usize;
-        let pixel = if z.is_nan() {
-            vec![0; bytes_per_pixel]
+        let (px, py, pz) = self.point_to_xyz(undistorted_frame, x, y);
+        let pixel = if pz.is_nan() {
+            vec![0; registered_frame.color_space.bytes_per_pixel()]
         } else {
-            registered_frame.buffer[c_off..c_off + bytes_per_pixel].to_vec()
+            registered_frame.pixel(x, y).to_vec()
         };
 
-        (x, y, z, pixel)
+        (px, py, pz, pixel)
     }
 
     pub fn point_to_xyz(
@@ -271,6 +490,209 @@ impl Registration {
         }
     }
 
+    /// Back-projects a batch of `(x, y)` pixels of an undistorted frame like
+    /// [`Registration::point_to_xyz`], with `None` for pixels without valid depth
+    /// or outside of the frame.
+    pub fn points_to_xyz(
+        &self,
+        undistorted_frame: &DepthFrame,
+        pixels: &[(usize, usize)],
+    ) -> Vec<Option<[f32; 3]>> {
+        pixels
+            .iter()
+            .map(|&(x, y)| {
+                if x >= DEPTH_WIDTH || y >= DEPTH_HEIGHT {
+                    return None;
+                }
+
+                let (px, py, pz) = self.point_to_xyz(undistorted_frame, x, y);
+
+                (!pz.is_nan()).then_some([px, py, pz])
+            })
+            .collect()
+    }
+
+    /// Bounding box of the valid points of an undistorted frame, as `(min, max)` XYZ
+    /// in meters like [`Registration::point_to_xyz`], `None` without valid points.
+    pub fn scene_bounds(&self, undistorted_frame: &DepthFrame) -> Option<([f32; 3], [f32; 3])> {
+        let mut bounds: Option<([f32; 3], [f32; 3])> = None;
+
+        for y in 0..DEPTH_HEIGHT {
+            for x in 0..DEPTH_WIDTH {
+                let (px, py, pz) = self.point_to_xyz(undistorted_frame, x, y);
+
+                if pz.is_nan() {
+                    continue;
+                }
+
+                let (min, max) = bounds.get_or_insert(([px, py, pz], [px, py, pz]));
+
+                for (axis, value) in [px, py, pz].into_iter().enumerate() {
+                    min[axis] = min[axis].min(value);
+                    max[axis] = max[axis].max(value);
+                }
+            }
+        }
+
+        bounds
+    }
+
+    /// Write the point cloud of a registered frame pair as a PLY file, with `x y z` in meters
+    /// and `red green blue` vertex properties.
+    ///
+    /// Pixels without a valid depth are dropped instead of being written at the origin.
+    ///
+    /// # Arguments
+    ///
+    /// * `binary` - Write a binary little endian PLY instead of an ASCII one
+    pub fn export_ply<W: Write>(
+        &self,
+        undistorted_frame: &DepthFrame,
+        registered_frame: &ColorFrame,
+        writer: &mut W,
+        binary: bool,
+    ) -> io::Result<()> {
+        let mut points = Vec::new();
+
+        for y in 0..DEPTH_HEIGHT {
+            for x in 0..DEPTH_WIDTH {
+                let (px, py, pz) = self.point_to_xyz(undistorted_frame, x, y);
+
+                if pz.is_nan() {
+                    continue;
+                }
+
+                let color = rgb(registered_frame.color_space, registered_frame.pixel(x, y));
+
+                points.push((px, py, pz, color));
+            }
+        }
+
+        writeln!(writer, "ply")?;
+        writeln!(
+            writer,
+            "format {} 1.0",
+            if binary {
+                "binary_little_endian"
+            } else {
+                "ascii"
+            }
+        )?;
+        writeln!(writer, "element vertex {}", points.len())?;
+        writeln!(writer, "property float x")?;
+        writeln!(writer, "property float y")?;
+        writeln!(writer, "property float z")?;
+        writeln!(writer, "property uchar red")?;
+        writeln!(writer, "property uchar green")?;
+        writeln!(writer, "property uchar blue")?;
+        writeln!(writer, "end_header")?;
+
+        for (x, y, z, [r, g, b]) in points {
+            if binary {
+                writer.write_all(&x.to_le_bytes())?;
+                writer.write_all(&y.to_le_bytes())?;
+                writer.write_all(&z.to_le_bytes())?;
+                writer.write_all(&[r, g, b])?;
+            } else {
+                writeln!(writer, "{x} {y} {z} {r} {g} {b}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Back-project a whole undistorted frame in one pass, in meters like
+    /// [`Registration::point_to_xyz`], invalid pixels are `[NaN; 3]`.
+    pub fn point_cloud(&self, undistorted_frame: &DepthFrame) -> PointCloudFrame {
+        let mut points = Vec::with_capacity(DEPTH_SIZE);
+
+        for y in 0..DEPTH_HEIGHT {
+            for x in 0..DEPTH_WIDTH {
+                let (px, py, pz) = self.point_to_xyz(undistorted_frame, x, y);
+
+                points.push([px, py, pz]);
+            }
+        }
+
+        PointCloudFrame {
+            width: DEPTH_WIDTH,
+            height: DEPTH_HEIGHT,
+            points,
+            sequence: undistorted_frame.sequence,
+            timestamp: undistorted_frame.timestamp,
+        }
+    }
+
+    /// Same as [`Registration::point_cloud`] with the RGB color of each point
+    /// from the registered frame, black for invalid pixels.
+    pub fn point_cloud_with_color(
+        &self,
+        undistorted_frame: &DepthFrame,
+        registered_frame: &ColorFrame,
+    ) -> (PointCloudFrame, Vec<[u8; 3]>) {
+        let point_cloud = self.point_cloud(undistorted_frame);
+        let colors = point_cloud
+            .points
+            .iter()
+            .enumerate()
+            .map(|(index, point)| {
+                if point[2].is_nan() {
+                    [0; 3]
+                } else {
+                    rgb(
+                        registered_frame.color_space,
+                        registered_frame.pixel(index % DEPTH_WIDTH, index / DEPTH_WIDTH),
+                    )
+                }
+            })
+            .collect();
+
+        (point_cloud, colors)
+    }
+
+    /// Estimate the surface normal of each pixel, in the depth camera frame and unit length.
+    ///
+    /// Normals come from the cross product of the horizontal and vertical differences between
+    /// the neighbors 3D points, so a plane facing the camera gives normals along +Z.
+    /// They are `[NaN; 3]` on the borders and where any of the four neighbors is invalid.
+    pub fn compute_normals(&self, undistorted_frame: &DepthFrame) -> Vec<[f32; 3]> {
+        let mut normals = vec![[NAN; 3]; DEPTH_SIZE];
+
+        for y in 1..DEPTH_HEIGHT - 1 {
+            for x in 1..DEPTH_WIDTH - 1 {
+                let point = |x, y| {
+                    let (x, y, z) = self.point_to_xyz(undistorted_frame, x, y);
+
+                    [x, y, z]
+                };
+                let (left, right) = (point(x - 1, y), point(x + 1, y));
+                let (up, down) = (point(x, y - 1), point(x, y + 1));
+
+                if [left, right, up, down]
+                    .iter()
+                    .any(|point| point[2].is_nan())
+                {
+                    continue;
+                }
+
+                let dx: [f32; 3] = std::array::from_fn(|i| right[i] - left[i]);
+                let dy: [f32; 3] = std::array::from_fn(|i| down[i] - up[i]);
+                let normal = [
+                    dx[1] * dy[2] - dx[2] * dy[1],
+                    dx[2] * dy[0] - dx[0] * dy[2],
+                    dx[0] * dy[1] - dx[1] * dy[0],
+                ];
+                let norm = normal.iter().map(|value| value * value).sum::<f32>().sqrt();
+
+                if norm > 0.0 {
+                    normals[x + y * DEPTH_WIDTH] = normal.map(|value| value / norm);
+                }
+            }
+        }
+
+        normals
+    }
+
     pub fn distort(&self, mx: usize, my: usize) -> (f32, f32) {
         // see http://en.wikipedia.org/wiki/Distortion_(optics) for description
         let dx = (mx as f32 - self.ir_params.cx) / self.ir_params.fx;
@@ -327,7 +749,295 @@ impl Registration {
         (
             (wx / (self.color_params.fx * COLOR_Q))
                 - (self.color_params.shift_m / self.color_params.shift_d),
-            (wy / COLOR_Q) + self.color_params.cy,
+            // the polynomial is fitted for square pixels, rescale for asymmetric focal lengths
+            (wy / COLOR_Q) * (self.color_params.fy / self.color_params.fx) + self.color_params.cy,
+        )
+    }
+}
+
+/// RGB color of a pixel, black for unknown color spaces.
+fn rgb(color_space: ColorSpace, pixel: &[u8]) -> [u8; 3] {
+    match color_space {
+        ColorSpace::BGR | ColorSpace::BGRA | ColorSpace::BGRX => [pixel[2], pixel[1], pixel[0]],
+        ColorSpace::Unknown => [0; 3],
+        _ => [pixel[0], pixel[1], pixel[2]],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames() -> (DepthFrame, ColorFrame) {
+        let mut depth = vec![NAN; DEPTH_SIZE];
+
+        depth[0] = 1000.0;
+        depth[DEPTH_WIDTH + 1] = 1500.0;
+        depth[DEPTH_SIZE - 1] = 4000.0;
+        // zero depth is invalid and must be dropped
+        depth[2] = 0.0;
+
+        (
+            DepthFrame {
+                width: DEPTH_WIDTH,
+                height: DEPTH_HEIGHT,
+                buffer: depth,
+                sequence: 0,
+                timestamp: 0,
+            },
+            ColorFrame {
+                color_space: ColorSpace::BGRX,
+                width: DEPTH_WIDTH,
+                height: DEPTH_HEIGHT,
+                stride: DEPTH_WIDTH * 4,
+                buffer: vec![128; DEPTH_SIZE * 4],
+                sequence: 0,
+                timestamp: 0,
+                exposure: 0.0,
+                gain: 0.0,
+                gamma: 0.0,
+            },
         )
     }
+
+    fn registration() -> Registration {
+        Registration::builder()
+            .ir_params(&IrParams {
+                fx: 365.0,
+                fy: 365.0,
+                cx: 256.0,
+                cy: 212.0,
+                ..Default::default()
+            })
+            .color_params(&Default::default())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn points_to_xyz() {
+        let (depth, _) = frames();
+        let registration = registration();
+        let points = registration.points_to_xyz(
+            &depth,
+            &[
+                (0, 0),
+                (3, 0),
+                (2, 0),
+                (DEPTH_WIDTH, 0),
+                (0, DEPTH_HEIGHT),
+                (1, 1),
+            ],
+        );
+        let (x, y, z) = registration.point_to_xyz(&depth, 0, 0);
+
+        assert_eq!(points[0], Some([x, y, z]));
+        assert_eq!(z, 1.0);
+        assert_eq!(points[1..5], [None; 4]);
+        assert_eq!(points[5].unwrap()[2], 1.5);
+    }
+
+    #[test]
+    fn builder_requires_both_params() {
+        assert!(matches!(
+            Registration::builder()
+                .color_params(&Default::default())
+                .build(),
+            Err(crate::Error::MissingCalibration(_))
+        ));
+        assert!(matches!(
+            Registration::builder()
+                .ir_params(&Default::default())
+                .build(),
+            Err(crate::Error::MissingCalibration(_))
+        ));
+        assert!(Registration::builder()
+            .ir_params(&Default::default())
+            .color_params(&Default::default())
+            .build()
+            .is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn calibration_round_trip() {
+        let mut registration = registration();
+
+        registration.set_color_params(&ColorParams {
+            fx: 1081.37,
+            fy: 1081.37,
+            cx: 959.5,
+            cy: 539.5,
+            shift_m: 52.0,
+            mx_x1y0: 0.00064,
+            my_x0y1: 0.00064,
+            ..Default::default()
+        });
+
+        let mut json = Vec::new();
+
+        registration.save_calibration(&mut json).unwrap();
+
+        let loaded = Registration::load_calibration(json.as_slice()).unwrap();
+
+        assert_eq!(loaded.ir_params, registration.ir_params);
+        assert_eq!(loaded.color_params, registration.color_params);
+        assert_eq!(loaded.distort_map, registration.distort_map);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn p0_tables_round_trip() {
+        let mut p0_tables = crate::data::P0Tables::default();
+
+        p0_tables.p0_table1[1] = 42;
+        p0_tables.p0_table2[DEPTH_SIZE - 1] = u16::MAX;
+
+        let json = serde_json::to_vec(&p0_tables).unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<crate::data::P0Tables>(&json).unwrap(),
+            p0_tables
+        );
+    }
+
+    #[test]
+    fn compute_normals_flat_plane() {
+        let depth = DepthFrame {
+            width: DEPTH_WIDTH,
+            height: DEPTH_HEIGHT,
+            buffer: vec![1000.0; DEPTH_SIZE],
+            sequence: 0,
+            timestamp: 0,
+        };
+        let normals = registration().compute_normals(&depth);
+
+        for y in 0..DEPTH_HEIGHT {
+            for x in 0..DEPTH_WIDTH {
+                let normal = normals[x + y * DEPTH_WIDTH];
+
+                if x == 0 || y == 0 || x == DEPTH_WIDTH - 1 || y == DEPTH_HEIGHT - 1 {
+                    assert!(normal.iter().all(|value| value.is_nan()));
+                } else {
+                    assert!(normal[0].abs() < 1e-4 && normal[1].abs() < 1e-4);
+                    assert!((normal[2] - 1.0).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scene_bounds_flat_plane() {
+        let mut depth = DepthFrame {
+            width: DEPTH_WIDTH,
+            height: DEPTH_HEIGHT,
+            buffer: vec![1000.0; DEPTH_SIZE],
+            sequence: 0,
+            timestamp: 0,
+        };
+        let registration = registration();
+        let (min, max) = registration.scene_bounds(&depth).unwrap();
+
+        assert!((min[0] - (0.5 - 256.0) / 365.0).abs() < 1e-6);
+        assert!((max[0] - (511.5 - 256.0) / 365.0).abs() < 1e-6);
+        assert!((min[1] - (0.5 - 212.0) / 365.0).abs() < 1e-6);
+        assert!((max[1] - (423.5 - 212.0) / 365.0).abs() < 1e-6);
+        assert_eq!((min[2], max[2]), (1.0, 1.0));
+
+        depth.buffer.fill(NAN);
+
+        assert!(registration.scene_bounds(&depth).is_none());
+    }
+
+    #[test]
+    fn point_cloud_matches_point_to_xyz() {
+        let mut depth = DepthFrame {
+            width: DEPTH_WIDTH,
+            height: DEPTH_HEIGHT,
+            buffer: vec![1000.0; DEPTH_SIZE],
+            sequence: 0,
+            timestamp: 0,
+        };
+
+        depth.buffer[10] = NAN;
+
+        let registration = registration();
+        let point_cloud = registration.point_cloud(&depth);
+
+        assert_eq!(point_cloud.points.len(), DEPTH_SIZE);
+        assert!(point_cloud.points[10].iter().all(|value| value.is_nan()));
+
+        let (x, y, z) = registration.point_to_xyz(&depth, 300, 200);
+
+        assert_eq!(point_cloud.points[300 + 200 * DEPTH_WIDTH], [x, y, z]);
+    }
+
+    #[test]
+    fn point_to_xyz_pixel_reads_registered_pixel() {
+        let (depth, mut color) = frames();
+
+        color.buffer[(DEPTH_WIDTH + 1) * 4..][..4].copy_from_slice(&[1, 2, 3, 4]);
+
+        let (_, _, z, pixel) = registration().point_to_xyz_pixel(&depth, &color, 1, 1);
+
+        assert!(!z.is_nan());
+        assert_eq!(pixel, [1, 2, 3, 4]);
+
+        // rows padded from 2048 to 2304 bytes
+        let color = color.with_row_alignment(768).unwrap();
+        let (_, _, _, pixel) = registration().point_to_xyz_pixel(&depth, &color, 1, 1);
+
+        assert_eq!(pixel, [1, 2, 3, 4]);
+    }
+
+    fn vertex_count(ply: &[u8]) -> usize {
+        let header_end = ply
+            .windows(11)
+            .position(|window| window == b"end_header\n")
+            .unwrap();
+
+        std::str::from_utf8(&ply[..header_end])
+            .unwrap()
+            .lines()
+            .find_map(|line| line.strip_prefix("element vertex "))
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn export_ply_ascii() {
+        let (depth, color) = frames();
+        let mut ply = Vec::new();
+
+        registration()
+            .export_ply(&depth, &color, &mut ply, false)
+            .unwrap();
+
+        let text = String::from_utf8(ply.clone()).unwrap();
+        let body = text.split("end_header\n").nth(1).unwrap();
+
+        assert_eq!(vertex_count(&ply), 3);
+        assert_eq!(body.lines().count(), 3);
+        assert!(body.lines().next().unwrap().ends_with(" 1 128 128 128"));
+    }
+
+    #[test]
+    fn export_ply_binary() {
+        let (depth, color) = frames();
+        let mut ply = Vec::new();
+
+        registration()
+            .export_ply(&depth, &color, &mut ply, true)
+            .unwrap();
+
+        let header_size = ply
+            .windows(11)
+            .position(|window| window == b"end_header\n")
+            .unwrap()
+            + 11;
+
+        assert_eq!(vertex_count(&ply), 3);
+        assert_eq!(ply.len() - header_size, 3 * (3 * 4 + 3));
+    }
 }
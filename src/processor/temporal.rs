@@ -0,0 +1,132 @@
+//! Temporal filtering of depth frames, against the shimmering of the silhouettes.
+//!
+//! Pixels near depth discontinuities jump between the foreground and the background from
+//! one frame to the next even on static scenes, a depth value is only kept once it persisted
+//! over several frames, unlike the spatial edge filter of the depth processors.
+
+use super::depth::DepthFrame;
+
+/// Stateful filter zeroing the pixels whose depth didn't stay stable over the last frames,
+/// feed it the frames of a stream in order.
+pub struct TemporalEdgeFilter {
+    persistence: u16,
+    tolerance: f32,
+    reference: Vec<f32>,
+    stable_frames: Vec<u16>,
+}
+
+impl TemporalEdgeFilter {
+    /// # Arguments
+    ///
+    /// * `persistence` - Consecutive frames a depth value must be seen to be kept, 1 keeps all
+    /// * `tolerance` - Maximum depth change between two frames of a persisting value (millimeter)
+    pub fn new(persistence: u16, tolerance: f32) -> Self {
+        Self {
+            persistence,
+            tolerance,
+            reference: Vec::new(),
+            stable_frames: Vec::new(),
+        }
+    }
+
+    pub fn set_persistence(&mut self, persistence: u16) {
+        self.persistence = persistence;
+    }
+
+    pub fn persistence(&self) -> u16 {
+        self.persistence
+    }
+
+    pub fn set_tolerance(&mut self, tolerance: f32) {
+        self.tolerance = tolerance;
+    }
+
+    pub fn tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    /// Forget the previous frames, e.g. when the camera moved.
+    pub fn reset(&mut self) {
+        self.reference.clear();
+        self.stable_frames.clear();
+    }
+
+    /// Filter the next frame of the stream, unstable and invalid pixels are set to 0.
+    pub fn filter(&mut self, frame: &DepthFrame) -> DepthFrame {
+        let size = frame.width * frame.height;
+
+        if self.reference.len() != size {
+            self.reference = vec![0.0; size];
+            self.stable_frames = vec![0; size];
+        }
+
+        let mut output = frame.clone();
+
+        for (index, value) in output.buffer[..size].iter_mut().enumerate() {
+            let stable_frames = &mut self.stable_frames[index];
+
+            if value.is_nan() || *value <= 0.0 {
+                *stable_frames = 0;
+                *value = 0.0;
+
+                continue;
+            }
+
+            if *stable_frames > 0 && (*value - self.reference[index]).abs() <= self.tolerance {
+                *stable_frames = stable_frames.saturating_add(1);
+            } else {
+                *stable_frames = 1;
+            }
+
+            self.reference[index] = *value;
+
+            if *stable_frames < self.persistence {
+                *value = 0.0;
+            }
+        }
+
+        output
+    }
+}
+
+impl Default for TemporalEdgeFilter {
+    fn default() -> Self {
+        Self::new(3, 30.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(buffer: Vec<f32>) -> DepthFrame {
+        DepthFrame {
+            width: buffer.len(),
+            height: 1,
+            buffer,
+            sequence: 0,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn flickering_pixels_are_zeroed() {
+        let mut filter = TemporalEdgeFilter::new(3, 10.0);
+
+        // static, flickering between foreground and background, invalid
+        let frames = [
+            [1000.0, 1000.0, f32::NAN],
+            [1005.0, 3000.0, f32::NAN],
+            [1000.0, 1000.0, f32::NAN],
+            [1002.0, 3000.0, f32::NAN],
+        ];
+        let outputs: Vec<_> = frames
+            .iter()
+            .map(|buffer| filter.filter(&frame(buffer.to_vec())).buffer)
+            .collect();
+
+        assert_eq!(outputs[1], [0.0, 0.0, 0.0]);
+        assert_eq!(outputs[2], [1000.0, 0.0, 0.0]);
+        assert_eq!(outputs[3], [1002.0, 0.0, 0.0]);
+    }
+}
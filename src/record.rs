@@ -0,0 +1,885 @@
+//! Capture of synchronized color and depth frames to a directory, for dataset collection.
+//!
+//! Images are written without compression so no encoder dependency is needed,
+//! any PNG or OpenEXR reader can load them.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    data::{ColorParams, IrParams, P0Tables},
+    device::{Device, Opened},
+    packet::ColorPacket,
+    processor::{
+        depth::{CpuDepthProcessor, DepthFrame, ToneMapping},
+        ProcessorTrait,
+    },
+    Error, PacketSync,
+};
+
+/// Encoding of the recorded depth frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// 16-bit grayscale PNG in millimeters, invalid depth is 0.
+    Png16,
+    /// Single channel `Z` float OpenEXR in millimeters, keeping the sub-millimeter precision.
+    Exr,
+}
+
+impl RecordFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Png16 => "png",
+            Self::Exr => "exr",
+        }
+    }
+
+    fn encode(&self, frame: &DepthFrame) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Png16 => Ok(encode_png16(
+                frame.width,
+                frame.height,
+                &frame.to_gray16(ToneMapping::linear(0.0, u16::MAX as f32))?,
+            )),
+            Self::Exr => Ok(encode_exr(
+                frame.width,
+                frame.height,
+                &frame.buffer[..frame.width * frame.height],
+            )),
+        }
+    }
+}
+
+/// Summary of a directory written by [`Device::record_to_dir`], see [`validate_recording`].
+///
+/// Packet timestamps aren't recorded so the duration isn't known, it's about
+/// `frames` / 30 s when no pair was dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingInfo {
+    /// Number of valid pairs before the first corrupted or missing file.
+    pub frames: usize,
+    /// `None` if the recording holds no depth frame.
+    pub format: Option<RecordFormat>,
+    /// First corrupted pair, the pairs after it aren't checked.
+    pub corruption: Option<RecordingCorruption>,
+}
+
+/// Corrupted file of a recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingCorruption {
+    /// Index of the pair.
+    pub index: usize,
+    pub path: PathBuf,
+    /// Byte offset in the file where the corruption was detected.
+    pub offset: usize,
+    pub reason: &'static str,
+}
+
+/// Check the integrity of a recording without decoding the images.
+///
+/// Pairs are checked in order until one is missing, JPEG markers, PNG chunk lengths and CRCs
+/// and OpenEXR scanline offsets are verified, a pair with only one of its files is corrupted.
+/// Errors only if the directory or `calibration.json` can't be read.
+pub fn validate_recording(dir: &Path) -> Result<RecordingInfo, Error> {
+    fs::metadata(dir.join("calibration.json"))?;
+
+    let format = [RecordFormat::Png16, RecordFormat::Exr]
+        .into_iter()
+        .find(|format| depth_path(dir, 0, *format).exists());
+    let mut info = RecordingInfo {
+        frames: 0,
+        format,
+        corruption: None,
+    };
+    let (depth_format, check_depth): (_, Checker) = match format {
+        Some(RecordFormat::Exr) => (RecordFormat::Exr, check_exr),
+        _ => (RecordFormat::Png16, check_png),
+    };
+
+    loop {
+        let index = info.frames;
+        let files = [
+            (
+                dir.join(format!("color_{index:04}.jpg")),
+                check_jpeg as Checker,
+            ),
+            (depth_path(dir, index, depth_format), check_depth),
+        ];
+
+        if files.iter().all(|(path, _)| !path.exists()) {
+            return Ok(info);
+        }
+
+        for (path, check) in files {
+            let result = match fs::read(&path) {
+                Ok(data) => check(&data),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Err((0, "Missing file")),
+                Err(error) => return Err(error.into()),
+            };
+
+            if let Err((offset, reason)) = result {
+                info.corruption = Some(RecordingCorruption {
+                    index,
+                    path,
+                    offset,
+                    reason,
+                });
+
+                return Ok(info);
+            }
+        }
+
+        info.frames += 1;
+    }
+}
+
+/// Check of a file, the offset and reason of the corruption on error.
+type Checker = fn(&[u8]) -> Result<(), (usize, &'static str)>;
+
+fn depth_path(dir: &Path, index: usize, format: RecordFormat) -> PathBuf {
+    dir.join(format!("depth_{index:04}.{}", format.extension()))
+}
+
+fn check_jpeg(data: &[u8]) -> Result<(), (usize, &'static str)> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return Err((0, "Missing JPEG start of image marker"));
+    }
+    if !data.ends_with(&[0xff, 0xd9]) {
+        return Err((data.len(), "Missing JPEG end of image marker"));
+    }
+
+    Ok(())
+}
+
+fn check_png(data: &[u8]) -> Result<(), (usize, &'static str)> {
+    if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err((0, "Invalid PNG signature"));
+    }
+
+    let mut offset = 8;
+
+    while offset < data.len() {
+        let Some(length) = data.get(offset..offset + 4) else {
+            return Err((offset, "Truncated PNG chunk"));
+        };
+        let end = offset + 12 + u32::from_be_bytes(length.try_into().unwrap()) as usize;
+        let Some(chunk) = data.get(offset + 4..end) else {
+            return Err((offset, "Truncated PNG chunk"));
+        };
+        let (chunk, crc) = chunk.split_at(chunk.len() - 4);
+
+        if crc32(chunk) != u32::from_be_bytes(crc.try_into().unwrap()) {
+            return Err((offset, "Invalid PNG chunk CRC"));
+        }
+        if &chunk[..4] == b"IEND" {
+            return Ok(());
+        }
+
+        offset = end;
+    }
+
+    Err((data.len(), "Missing PNG end chunk"))
+}
+
+fn check_exr(data: &[u8]) -> Result<(), (usize, &'static str)> {
+    if !data.starts_with(&[0x76, 0x2f, 0x31, 0x01]) {
+        return Err((0, "Invalid OpenEXR magic number"));
+    }
+
+    let mut offset = 8;
+    let mut height = None;
+    let truncated = |offset| (offset, "Truncated OpenEXR header");
+
+    // attributes are name, type, size and value, until an empty name
+    loop {
+        let name_length = data
+            .get(offset..)
+            .ok_or(truncated(offset))?
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(truncated(offset))?;
+
+        if name_length == 0 {
+            offset += 1;
+            break;
+        }
+
+        let name = &data[offset..offset + name_length];
+        let kind_length = data
+            .get(offset + name_length + 1..)
+            .ok_or(truncated(offset))?
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(truncated(offset))?;
+        let size_offset = offset + name_length + kind_length + 2;
+        let size = data
+            .get(size_offset..size_offset + 4)
+            .ok_or(truncated(offset))?;
+        let value_offset = size_offset + 4;
+        let value = data
+            .get(value_offset..value_offset + u32::from_le_bytes(size.try_into().unwrap()) as usize)
+            .ok_or(truncated(offset))?;
+
+        if name == b"dataWindow" && value.len() == 16 {
+            let y_min = i32::from_le_bytes(value[4..8].try_into().unwrap());
+            let y_max = i32::from_le_bytes(value[12..16].try_into().unwrap());
+
+            height = Some((y_max as i64 - y_min as i64 + 1).max(0) as usize);
+        }
+
+        offset = value_offset + value.len();
+    }
+
+    let height = height.ok_or((offset, "Missing OpenEXR data window"))?;
+    let offsets = height
+        .checked_mul(8)
+        .and_then(|length| data.get(offset..offset.checked_add(length)?))
+        .ok_or((offset, "Truncated OpenEXR offset table"))?;
+
+    for (index, scanline) in offsets.chunks_exact(8).enumerate() {
+        let scanline = u64::from_le_bytes(scanline.try_into().unwrap()) as usize;
+        let size = data
+            .get(scanline.saturating_add(4)..scanline.saturating_add(8))
+            .ok_or((offset + index * 8, "Invalid OpenEXR scanline offset"))?;
+
+        if data.len() < scanline + 8 + u32::from_le_bytes(size.try_into().unwrap()) as usize {
+            return Err((scanline, "Truncated OpenEXR scanline"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Lock of the color exposure once auto exposure converged, for recordings without flicker.
+///
+/// Auto exposure is considered converged once the exposure and gain reported by the color
+/// packets stayed within `tolerance` for `stable_frames` consecutive packets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExposureLock {
+    pub stable_frames: usize,
+    /// Maximum relative change between two packets.
+    pub tolerance: f32,
+}
+
+impl Default for ExposureLock {
+    fn default() -> Self {
+        Self {
+            stable_frames: 15,
+            tolerance: 0.01,
+        }
+    }
+}
+
+/// Tracks the exposure reported by the color packets until it converged.
+struct ExposureTracker {
+    lock: ExposureLock,
+    last: Option<(f32, f32)>,
+    stable_frames: usize,
+}
+
+impl ExposureTracker {
+    fn new(lock: ExposureLock) -> Self {
+        Self {
+            lock,
+            last: None,
+            stable_frames: 0,
+        }
+    }
+
+    fn is_stable(&self, previous: f32, value: f32) -> bool {
+        (value - previous).abs() <= self.lock.tolerance * previous.abs().max(f32::EPSILON)
+    }
+
+    /// Whether auto exposure converged, after pushing the values of `packet`.
+    fn converged(&mut self, packet: &ColorPacket) -> bool {
+        let current = (packet.exposure, packet.gain);
+
+        match self.last.replace(current) {
+            Some((exposure, gain))
+                if self.is_stable(exposure, current.0) && self.is_stable(gain, current.1) =>
+            {
+                self.stable_frames += 1;
+            }
+            _ => self.stable_frames = 0,
+        }
+
+        self.stable_frames >= self.lock.stable_frames
+    }
+}
+
+impl Device<Opened> {
+    /// Capture `frames` synchronized color and depth pairs to `dir`, the device is started if needed
+    /// and stopped again afterwards.
+    ///
+    /// Writes `calibration.json`, then for each pair `color_NNNN.jpg` with the JPEG data of the
+    /// packet as is and `depth_NNNN.png` or `depth_NNNN.exr` depending on `format`.
+    /// Depth is decoded on the CPU, flipped vertically as done by [`CpuDepthProcessor`].
+    pub async fn record_to_dir(
+        &mut self,
+        dir: &Path,
+        frames: usize,
+        format: RecordFormat,
+    ) -> Result<(), Error> {
+        self.record(dir, frames, format, None).await
+    }
+
+    /// Same as [`Device::record_to_dir`], waiting for auto exposure to converge and locking
+    /// the color exposure before writing the first pair, so the brightness doesn't flicker.
+    ///
+    /// The exposure stays locked afterwards, see [`Device::set_color_auto_exposure`]
+    /// to restore auto exposure.
+    pub async fn record_to_dir_locked(
+        &mut self,
+        dir: &Path,
+        frames: usize,
+        format: RecordFormat,
+        lock: ExposureLock,
+    ) -> Result<(), Error> {
+        self.record(dir, frames, format, Some(lock)).await
+    }
+
+    async fn record(
+        &mut self,
+        dir: &Path,
+        frames: usize,
+        format: RecordFormat,
+        lock: Option<ExposureLock>,
+    ) -> Result<(), Error> {
+        fs::create_dir_all(dir)?;
+        self.check_depth_parsing()?;
+
+        let (started, depth_processor) = start_recording(self, dir).await;
+        let result = match depth_processor {
+            Ok(depth_processor) => {
+                self.record_pairs(&depth_processor, dir, frames, format, lock)
+                    .await
+            }
+            Err(error) => Err(error),
+        };
+
+        if started {
+            let stopped = self.stop().await;
+
+            return result.and(stopped);
+        }
+
+        result
+    }
+
+    async fn record_pairs(
+        &mut self,
+        depth_processor: &CpuDepthProcessor,
+        dir: &Path,
+        frames: usize,
+        format: RecordFormat,
+        lock: Option<ExposureLock>,
+    ) -> Result<(), Error> {
+        let mut packet_sync = PacketSync::new();
+        let mut exposure_tracker = lock.map(ExposureTracker::new);
+        let mut index = 0;
+
+        while index < frames {
+            if let Some(packet) = self.poll_color_packet().await? {
+                if let Some(tracker) = &mut exposure_tracker {
+                    if tracker.converged(&packet) {
+                        self.lock_color_exposure().await?;
+                        exposure_tracker = None;
+                    }
+                }
+
+                packet_sync.push_color_packet(packet);
+            }
+            if let Some(packet) = self.poll_depth_packet().await? {
+                packet_sync.push_depth_packet(packet);
+            }
+
+            if let Some((color_packet, depth_packet)) = packet_sync.poll_packets() {
+                // pairs are dropped until the exposure is locked
+                if exposure_tracker.is_some() {
+                    continue;
+                }
+
+                let (_, depth_frame) = depth_processor.process(depth_packet).await?;
+
+                fs::write(
+                    dir.join(format!("color_{index:04}.jpg")),
+                    color_packet.jpeg_buffer,
+                )?;
+                fs::write(
+                    dir.join(format!("depth_{index:04}.{}", format.extension())),
+                    format.encode(&depth_frame)?,
+                )?;
+
+                index += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Device side of a recording, to test the start and calibration order without a device.
+trait RecordingDevice {
+    fn running(&self) -> bool;
+    async fn start(&mut self) -> Result<(), Error>;
+    fn ir_params(&self) -> &IrParams;
+    fn color_params(&self) -> &ColorParams;
+    fn p0_tables(&self) -> &P0Tables;
+}
+
+impl RecordingDevice for Device<Opened> {
+    fn running(&self) -> bool {
+        self.running()
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        self.start().await
+    }
+
+    fn ir_params(&self) -> &IrParams {
+        self.get_ir_params()
+    }
+
+    fn color_params(&self) -> &ColorParams {
+        self.get_color_params()
+    }
+
+    fn p0_tables(&self) -> &P0Tables {
+        self.get_p0_tables()
+    }
+}
+
+/// Start the device if it's stopped, then write `calibration.json` and create the depth processor,
+/// the calibration is only read from the device when it starts.
+///
+/// Also returns whether the device was started, even on error, so it's stopped again.
+async fn start_recording(
+    device: &mut impl RecordingDevice,
+    dir: &Path,
+) -> (bool, Result<CpuDepthProcessor, Error>) {
+    let started = !device.running();
+
+    if started {
+        if let Err(error) = device.start().await {
+            return (false, Err(error));
+        }
+    }
+
+    let depth_processor = fs::write(
+        dir.join("calibration.json"),
+        calibration_json(device.ir_params(), device.color_params()),
+    )
+    .map_err(Error::from)
+    .and_then(|()| {
+        CpuDepthProcessor::from_calibration(device.ir_params(), device.p0_tables())
+            .map_err(|error| Error::Processing(error.into()))
+    });
+
+    (started, depth_processor)
+}
+
+fn json_object(fields: &[(&str, f32)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| {
+            if value.is_finite() {
+                format!("    \"{name}\": {value:?}")
+            } else {
+                // like serde_json, JSON has no NaN or infinity
+                format!("    \"{name}\": null")
+            }
+        })
+        .collect();
+
+    format!("{{\n{}\n  }}", fields.join(",\n"))
+}
+
+/// Same schema as [`Registration::save_calibration`](crate::processor::Registration::save_calibration),
+/// so a recording can be registered with `load_calibration`.
+fn calibration_json(ir_params: &IrParams, color_params: &ColorParams) -> String {
+    let ir = json_object(&[
+        ("fx", ir_params.fx),
+        ("fy", ir_params.fy),
+        ("cx", ir_params.cx),
+        ("cy", ir_params.cy),
+        ("k1", ir_params.k1),
+        ("k2", ir_params.k2),
+        ("k3", ir_params.k3),
+        ("p1", ir_params.p1),
+        ("p2", ir_params.p2),
+    ]);
+    let color = json_object(&[
+        ("fx", color_params.fx),
+        ("fy", color_params.fy),
+        ("cx", color_params.cx),
+        ("cy", color_params.cy),
+        ("shift_d", color_params.shift_d),
+        ("shift_m", color_params.shift_m),
+        ("mx_x3y0", color_params.mx_x3y0),
+        ("mx_x0y3", color_params.mx_x0y3),
+        ("mx_x2y1", color_params.mx_x2y1),
+        ("mx_x1y2", color_params.mx_x1y2),
+        ("mx_x2y0", color_params.mx_x2y0),
+        ("mx_x0y2", color_params.mx_x0y2),
+        ("mx_x1y1", color_params.mx_x1y1),
+        ("mx_x1y0", color_params.mx_x1y0),
+        ("mx_x0y1", color_params.mx_x0y1),
+        ("mx_x0y0", color_params.mx_x0y0),
+        ("my_x3y0", color_params.my_x3y0),
+        ("my_x0y3", color_params.my_x0y3),
+        ("my_x2y1", color_params.my_x2y1),
+        ("my_x1y2", color_params.my_x1y2),
+        ("my_x2y0", color_params.my_x2y0),
+        ("my_x0y2", color_params.my_x0y2),
+        ("my_x1y1", color_params.my_x1y1),
+        ("my_x1y0", color_params.my_x1y0),
+        ("my_x0y1", color_params.my_x0y1),
+        ("my_x0y0", color_params.my_x0y0),
+    ]);
+
+    format!("{{\n  \"ir_params\": {ir},\n  \"color_params\": {color}\n}}\n")
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let start = png.len() + 4;
+
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+
+    let crc = crc32(&png[start..]);
+
+    png.extend(crc.to_be_bytes());
+}
+
+/// Encode a 16-bit grayscale PNG, the zlib stream only holds stored blocks.
+fn encode_png16(width: usize, height: usize, data: &[u16]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(height * (1 + width * 2));
+
+    for row in data.chunks(width) {
+        // filter type none
+        raw.push(0);
+
+        // PNG stores 16-bit samples as big endian
+        for value in row {
+            raw.extend(value.to_be_bytes());
+        }
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(u16::MAX as usize);
+    let last = blocks.len() - 1;
+
+    for (index, block) in blocks.enumerate() {
+        zlib.push((index == last) as u8);
+        zlib.extend((block.len() as u16).to_le_bytes());
+        zlib.extend((!(block.len() as u16)).to_le_bytes());
+        zlib.extend(block);
+    }
+
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // bit depth 16, grayscale, deflate, adaptive filtering, no interlace
+    header.extend([16, 0, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &zlib);
+    png_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+fn exr_attribute(exr: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    exr.extend(name.as_bytes());
+    exr.push(0);
+    exr.extend(kind.as_bytes());
+    exr.push(0);
+    exr.extend((value.len() as i32).to_le_bytes());
+    exr.extend(value);
+}
+
+/// Encode a single channel float scanline OpenEXR image without compression.
+fn encode_exr(width: usize, height: usize, data: &[f32]) -> Vec<u8> {
+    let mut exr = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+    // channel Z, float pixels, not linear, no subsampling
+    let mut channels = b"Z\0".to_vec();
+
+    channels.extend(2i32.to_le_bytes());
+    channels.extend([0; 4]);
+    channels.extend(1i32.to_le_bytes());
+    channels.extend(1i32.to_le_bytes());
+    channels.push(0);
+
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+
+    exr_attribute(&mut exr, "channels", "chlist", &channels);
+    exr_attribute(&mut exr, "compression", "compression", &[0]);
+    exr_attribute(&mut exr, "dataWindow", "box2i", &window);
+    exr_attribute(&mut exr, "displayWindow", "box2i", &window);
+    exr_attribute(&mut exr, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(&mut exr, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    exr_attribute(&mut exr, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(&mut exr, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    exr.push(0);
+
+    let row_size = width * 4;
+    let first_row = exr.len() + height * 8;
+
+    for y in 0..height {
+        exr.extend(((first_row + y * (8 + row_size)) as u64).to_le_bytes());
+    }
+
+    for (y, row) in data.chunks(width).enumerate() {
+        exr.extend((y as i32).to_le_bytes());
+        exr.extend((row_size as i32).to_le_bytes());
+
+        for value in row {
+            exr.extend(value.to_le_bytes());
+        }
+    }
+
+    exr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "serde")]
+    use crate::processor::Registration;
+
+    /// Device whose calibration is only known once started, like a real one.
+    #[derive(Default)]
+    struct StoppedDevice {
+        running: bool,
+        ir_params: IrParams,
+        color_params: ColorParams,
+        p0_tables: P0Tables,
+    }
+
+    impl RecordingDevice for StoppedDevice {
+        fn running(&self) -> bool {
+            self.running
+        }
+
+        async fn start(&mut self) -> Result<(), Error> {
+            self.running = true;
+            self.ir_params.fx = 365.5;
+            self.color_params.fx = 1081.5;
+
+            Ok(())
+        }
+
+        fn ir_params(&self) -> &IrParams {
+            &self.ir_params
+        }
+
+        fn color_params(&self) -> &ColorParams {
+            &self.color_params
+        }
+
+        fn p0_tables(&self) -> &P0Tables {
+            &self.p0_tables
+        }
+    }
+
+    #[tokio::test]
+    async fn calibration_is_read_once_started() {
+        let dir = std::env::temp_dir().join(format!("kinect-one-record-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut device = StoppedDevice::default();
+        let (started, depth_processor) = start_recording(&mut device, &dir).await;
+        let calibration = fs::read_to_string(dir.join("calibration.json")).unwrap();
+
+        assert!(started && device.running);
+        assert!(depth_processor.is_ok());
+        assert!(calibration.contains("\"fx\": 365.5"));
+        assert!(calibration.contains("\"fx\": 1081.5"));
+
+        // a running device is left as is
+        let (started, _) = start_recording(&mut device, &dir).await;
+
+        assert!(!started);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn jpeg_markers() {
+        assert_eq!(check_jpeg(&[0xff, 0xd8, 0, 0xff, 0xd9]), Ok(()));
+        assert_eq!(
+            check_jpeg(&[0, 0xd8, 0xff, 0xd9]),
+            Err((0, "Missing JPEG start of image marker"))
+        );
+        assert_eq!(
+            check_jpeg(&[0xff, 0xd8, 0, 0]),
+            Err((4, "Missing JPEG end of image marker"))
+        );
+    }
+
+    #[test]
+    fn png_chunks() {
+        let png = encode_png16(4, 3, &[1000; 12]);
+
+        assert_eq!(check_png(&png), Ok(()));
+        assert_eq!(check_png(&png[1..]), Err((0, "Invalid PNG signature")));
+
+        // signature, then IHDR of 13 bytes, the IDAT chunk follows
+        let idat = 8 + 12 + 13;
+        let mut corrupted = png.clone();
+
+        corrupted[idat + 10] ^= 1;
+
+        assert_eq!(check_png(&corrupted), Err((idat, "Invalid PNG chunk CRC")));
+        assert_eq!(
+            check_png(&png[..idat + 10]),
+            Err((idat, "Truncated PNG chunk"))
+        );
+        assert_eq!(
+            check_png(&png[..png.len() - 12]),
+            Err((png.len() - 12, "Missing PNG end chunk"))
+        );
+    }
+
+    #[test]
+    fn exr_scanlines() {
+        let exr = encode_exr(4, 3, &[1000.0; 12]);
+
+        assert_eq!(check_exr(&exr), Ok(()));
+        assert_eq!(
+            check_exr(&exr[1..]),
+            Err((0, "Invalid OpenEXR magic number"))
+        );
+        assert_eq!(
+            check_exr(&exr[..exr.len() - 1]).unwrap_err().1,
+            "Truncated OpenEXR scanline"
+        );
+
+        // y_min and y_max of the data window, the height doesn't fit an i32
+        let window = exr
+            .windows(10)
+            .position(|name| name == b"dataWindow")
+            .unwrap()
+            + b"dataWindow\0box2i\0".len()
+            + 4;
+        let mut corrupted = exr.clone();
+
+        corrupted[window + 4..window + 8].copy_from_slice(&i32::MIN.to_le_bytes());
+        corrupted[window + 12..window + 16].copy_from_slice(&i32::MAX.to_le_bytes());
+
+        assert_eq!(
+            check_exr(&corrupted).unwrap_err().1,
+            "Truncated OpenEXR offset table"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn calibration_json_round_trips() {
+        let ir_params = IrParams {
+            fx: 365.5,
+            fy: 365.5,
+            cx: 257.25,
+            cy: 206.75,
+            k1: 0.09,
+            k2: -0.27,
+            k3: 0.095,
+            ..Default::default()
+        };
+        let color_params = ColorParams {
+            fx: 1081.37,
+            fy: 1081.37,
+            cx: 959.5,
+            cy: 539.5,
+            shift_d: 863.0,
+            shift_m: 52.0,
+            mx_x1y0: 0.6,
+            my_x0y1: 0.6,
+            mx_x0y0: 0.1,
+            my_x0y0: -0.2,
+            ..Default::default()
+        };
+        let json = calibration_json(&ir_params, &color_params);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            serde_json::from_value::<IrParams>(value["ir_params"].clone()).unwrap(),
+            ir_params
+        );
+        assert_eq!(
+            serde_json::from_value::<ColorParams>(value["color_params"].clone()).unwrap(),
+            color_params
+        );
+        assert!(Registration::load_calibration(json.as_bytes()).is_ok());
+
+        let mut saved = Vec::new();
+
+        Registration::load_calibration(json.as_bytes())
+            .unwrap()
+            .save_calibration(&mut saved)
+            .unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&saved).unwrap(),
+            value
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn calibration_json_has_no_non_finite_numbers() {
+        let ir_params = IrParams {
+            fx: f32::NAN,
+            fy: f32::INFINITY,
+            ..Default::default()
+        };
+        let json = calibration_json(&ir_params, &ColorParams::default());
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert!(value["ir_params"]["fx"].is_null());
+        assert!(value["ir_params"]["fy"].is_null());
+    }
+}
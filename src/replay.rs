@@ -0,0 +1,532 @@
+//! Recording of the raw color and depth packets, replayed without a device.
+//!
+//! The file starts with the `KINECTPK` magic and a little endian `u16` format version,
+//! then holds one record per packet in the order they were written: a kind byte,
+//! `0` for color and `1` for depth, the packet fields and the length prefixed data.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    packet::parser::ColorStreamParser, ColorPacket, DepthPacket, Error, DEPTH_PACKET_SIZE,
+};
+
+const MAGIC: &[u8; 8] = b"KINECTPK";
+/// Version of the packet recording format, bumped on any change of the layout.
+pub const FORMAT_VERSION: u16 = 1;
+
+const COLOR_RECORD: u8 = 0;
+const DEPTH_RECORD: u8 = 1;
+
+/// Largest data of a color record, the stream parser drops bigger packets.
+const MAX_COLOR_DATA: u32 = ColorStreamParser::CAPACITY as u32;
+
+/// Check the data length of a record before allocating, depth packets are always complete.
+fn check_length(kind: u8, length: u32) -> Result<(), &'static str> {
+    match kind {
+        COLOR_RECORD if length > MAX_COLOR_DATA => Err("Record data longer than a packet"),
+        DEPTH_RECORD if length != DEPTH_PACKET_SIZE as u32 => {
+            Err("Depth record data not of the packet size")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes packets to a recording, see [`Player`] to read them back.
+pub struct Recorder<W: Write> {
+    writer: W,
+}
+
+impl Recorder<BufWriter<File>> {
+    /// Create or truncate the recording at `path`.
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut writer: W) -> Result<Self, Error> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+
+        Ok(Self { writer })
+    }
+
+    pub fn write_color_packet(&mut self, packet: &ColorPacket) -> Result<(), Error> {
+        self.writer.write_all(&[COLOR_RECORD])?;
+
+        for value in [packet.sequence, packet.timestamp] {
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        for value in [packet.exposure, packet.gain, packet.gamma] {
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+
+        self.write_data(&packet.jpeg_buffer)
+    }
+
+    pub fn write_depth_packet(&mut self, packet: &DepthPacket) -> Result<(), Error> {
+        self.writer.write_all(&[DEPTH_RECORD])?;
+
+        for value in [packet.sequence, packet.timestamp] {
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+
+        self.write_data(&packet.buffer)
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(data)?;
+
+        Ok(())
+    }
+
+    /// Flush the recording and get the writer back.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+/// Reads the packets of a recording, with the same polling interface as [`crate::Device`].
+///
+/// Packets of each stream are returned in the recorded order. Unlike the polls of a device,
+/// where `None` means no packet is ready yet, `None` means the recording ended, see [`Self::finished`].
+pub struct Player<R: Read> {
+    reader: R,
+    color_packets: VecDeque<ColorPacket>,
+    depth_packets: VecDeque<DepthPacket>,
+    finished: bool,
+}
+
+/// Summary of a packet recording, see [`Player::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingInfo {
+    /// Number of valid color records before the first corrupted one.
+    pub color_packets: usize,
+    /// Number of valid depth records before the first corrupted one.
+    pub depth_packets: usize,
+    /// Between the timestamps of the first and the last valid record, zero without records.
+    pub duration: Duration,
+    /// First corrupted record, the records after it aren't checked.
+    pub corruption: Option<RecordingCorruption>,
+}
+
+/// Corrupted record of a packet recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingCorruption {
+    /// Byte offset of the start of the record, the recording is valid up to it.
+    pub offset: u64,
+    pub reason: &'static str,
+}
+
+impl Player<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+
+    /// Check the integrity of the recording at `path` without keeping the packet data.
+    ///
+    /// Records are checked in order until the end of the file, a record of an unknown kind
+    /// or whose data is cut short is a corruption.
+    /// Errors like [`Self::new`] for an invalid header, or if the file can't be read.
+    pub fn validate(path: &Path) -> Result<RecordingInfo, Error> {
+        validate_recording(BufReader::new(File::open(path)?))
+    }
+}
+
+fn validate_recording<R: Read>(reader: R) -> Result<RecordingInfo, Error> {
+    let mut player = Player::new(reader)?;
+    let mut info = RecordingInfo {
+        color_packets: 0,
+        depth_packets: 0,
+        duration: Duration::ZERO,
+        corruption: None,
+    };
+    let mut offset = (MAGIC.len() + 2) as u64;
+    let mut first_timestamp = None;
+
+    loop {
+        let mut kind = [0];
+
+        if player.reader.read(&mut kind)? == 0 {
+            return Ok(info);
+        }
+
+        let (words, counter) = match kind[0] {
+            COLOR_RECORD => (5, &mut info.color_packets),
+            DEPTH_RECORD => (2, &mut info.depth_packets),
+            _ => {
+                info.corruption = Some(RecordingCorruption {
+                    offset,
+                    reason: "Unknown record kind",
+                });
+
+                return Ok(info);
+            }
+        };
+        let mut header = [0; 24];
+        let header = &mut header[..(words + 1) * 4];
+        let length = match player.read_exact(header) {
+            Ok(()) => u32::from_le_bytes(header[header.len() - 4..].try_into().unwrap()),
+            Err(Error::InvalidRecording(reason)) => {
+                info.corruption = Some(RecordingCorruption { offset, reason });
+
+                return Ok(info);
+            }
+            Err(error) => return Err(error),
+        };
+
+        if let Err(reason) = check_length(kind[0], length) {
+            info.corruption = Some(RecordingCorruption { offset, reason });
+
+            return Ok(info);
+        }
+
+        let length = length as u64;
+
+        if io::copy(&mut player.reader.by_ref().take(length), &mut io::sink())? != length {
+            info.corruption = Some(RecordingCorruption {
+                offset,
+                reason: "Truncated record",
+            });
+
+            return Ok(info);
+        }
+
+        let timestamp = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let first = *first_timestamp.get_or_insert(timestamp);
+
+        // timestamps tick every 0.1 ms
+        info.duration = Duration::from_micros(timestamp.wrapping_sub(first) as u64 * 100);
+        *counter += 1;
+        offset += 1 + header.len() as u64 + length;
+    }
+}
+
+impl<R: Read> Player<R> {
+    /// Check the header of the recording, [`Error::RecordingVersion`] for another format version.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0; 10];
+
+        reader.read_exact(&mut header)?;
+
+        if &header[..8] != MAGIC {
+            return Err(Error::InvalidRecording("Invalid magic number"));
+        }
+
+        let version = u16::from_le_bytes([header[8], header[9]]);
+
+        if version != FORMAT_VERSION {
+            return Err(Error::RecordingVersion(version));
+        }
+
+        Ok(Self {
+            reader,
+            color_packets: VecDeque::new(),
+            depth_packets: VecDeque::new(),
+            finished: false,
+        })
+    }
+
+    /// Whether every packet was read from the recording, some may still be buffered.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    pub async fn poll_color_packet(&mut self) -> Result<Option<ColorPacket>, Error> {
+        while self.color_packets.is_empty() && self.read_record()? {}
+
+        Ok(self.color_packets.pop_front())
+    }
+
+    pub async fn poll_depth_packet(&mut self) -> Result<Option<DepthPacket>, Error> {
+        while self.depth_packets.is_empty() && self.read_record()? {}
+
+        Ok(self.depth_packets.pop_front())
+    }
+
+    /// Read the next record into its stream queue, `false` at the end of the recording.
+    fn read_record(&mut self) -> Result<bool, Error> {
+        if self.finished {
+            return Ok(false);
+        }
+
+        let mut kind = [0];
+
+        if self.reader.read(&mut kind)? == 0 {
+            self.finished = true;
+
+            return Ok(false);
+        }
+
+        match kind[0] {
+            COLOR_RECORD => {
+                let [sequence, timestamp, exposure, gain, gamma] = self.read_words::<5>()?;
+                let jpeg_buffer = self.read_data(COLOR_RECORD)?;
+
+                self.color_packets.push_back(ColorPacket {
+                    sequence,
+                    timestamp,
+                    exposure: f32::from_bits(exposure),
+                    gain: f32::from_bits(gain),
+                    gamma: f32::from_bits(gamma),
+                    jpeg_buffer,
+                });
+            }
+            DEPTH_RECORD => {
+                let [sequence, timestamp] = self.read_words::<2>()?;
+                let buffer = self.read_data(DEPTH_RECORD)?;
+
+                self.depth_packets.push_back(DepthPacket {
+                    sequence,
+                    timestamp,
+                    buffer,
+                });
+            }
+            _ => return Err(Error::InvalidRecording("Unknown record kind")),
+        }
+
+        Ok(true)
+    }
+
+    fn read_words<const N: usize>(&mut self) -> Result<[u32; N], Error> {
+        let mut words = [0; N];
+
+        for word in &mut words {
+            let mut bytes = [0; 4];
+
+            self.read_exact(&mut bytes)?;
+            *word = u32::from_le_bytes(bytes);
+        }
+
+        Ok(words)
+    }
+
+    /// Read length prefixed data, checking the length before allocating.
+    fn read_data(&mut self, kind: u8) -> Result<Vec<u8>, Error> {
+        let [length] = self.read_words::<1>()?;
+
+        check_length(kind, length).map_err(Error::InvalidRecording)?;
+
+        let mut data = vec![0; length as usize];
+
+        self.read_exact(&mut data)?;
+
+        Ok(data)
+    }
+
+    fn read_exact(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.reader.read_exact(buffer).map_err(|error| {
+            if error.kind() == io::ErrorKind::UnexpectedEof {
+                Error::InvalidRecording("Truncated record")
+            } else {
+                error.into()
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn packets_round_trip() {
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+
+        for sequence in 0..3 {
+            recorder
+                .write_depth_packet(&DepthPacket {
+                    sequence,
+                    timestamp: sequence * 10,
+                    buffer: vec![sequence as u8; DEPTH_PACKET_SIZE],
+                })
+                .unwrap();
+        }
+
+        recorder
+            .write_color_packet(&ColorPacket {
+                sequence: 7,
+                timestamp: 70,
+                exposure: 1.5,
+                gain: 2.0,
+                gamma: 0.5,
+                jpeg_buffer: vec![0xff, 0xd8, 0xff, 0xd9],
+            })
+            .unwrap();
+
+        let recording = recorder.finish().unwrap();
+        let mut player = Player::new(Cursor::new(&recording)).unwrap();
+
+        let color = player.poll_color_packet().await.unwrap().unwrap();
+
+        assert_eq!((color.sequence, color.timestamp, color.gain), (7, 70, 2.0));
+        assert_eq!(color.jpeg_buffer, [0xff, 0xd8, 0xff, 0xd9]);
+
+        for sequence in 0..3 {
+            let depth = player.poll_depth_packet().await.unwrap().unwrap();
+
+            assert_eq!(
+                (depth.sequence, depth.buffer[0]),
+                (sequence, sequence as u8)
+            );
+        }
+
+        assert!(player.poll_depth_packet().await.unwrap().is_none());
+        assert!(player.finished());
+
+        assert!(matches!(
+            Player::new(Cursor::new(&recording[..recording.len() - 1]))
+                .unwrap()
+                .poll_color_packet()
+                .await,
+            Err(Error::InvalidRecording(_))
+        ));
+    }
+
+    fn recording() -> Vec<u8> {
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+
+        for sequence in 0..3 {
+            recorder
+                .write_depth_packet(&DepthPacket {
+                    sequence,
+                    timestamp: 1000 + sequence * 333,
+                    buffer: vec![0; DEPTH_PACKET_SIZE],
+                })
+                .unwrap();
+            recorder
+                .write_color_packet(&ColorPacket {
+                    sequence,
+                    timestamp: 1000 + sequence * 333,
+                    exposure: 1.0,
+                    gain: 1.0,
+                    gamma: 1.0,
+                    jpeg_buffer: vec![0xff, 0xd8, 0xff, 0xd9],
+                })
+                .unwrap();
+        }
+
+        recorder.finish().unwrap()
+    }
+
+    #[test]
+    fn validate_counts_records() {
+        let info = validate_recording(Cursor::new(recording())).unwrap();
+
+        assert_eq!((info.color_packets, info.depth_packets), (3, 3));
+        assert_eq!(info.duration, Duration::from_micros(666 * 100));
+        assert_eq!(info.corruption, None);
+    }
+
+    #[test]
+    fn validate_reports_corruption_offset() {
+        let recording = recording();
+        // header, then a depth record of 1 + 3 words + a packet
+        let second_record = 10 + 13 + DEPTH_PACKET_SIZE;
+
+        let info = validate_recording(Cursor::new(&recording[..recording.len() - 1])).unwrap();
+
+        assert_eq!((info.color_packets, info.depth_packets), (2, 3));
+        assert_eq!(info.corruption.unwrap().reason, "Truncated record");
+
+        let mut unknown_kind = recording.clone();
+
+        unknown_kind[second_record] = 7;
+
+        let info = validate_recording(Cursor::new(&unknown_kind)).unwrap();
+
+        assert_eq!((info.color_packets, info.depth_packets), (0, 1));
+        assert_eq!(info.duration, Duration::ZERO);
+        assert_eq!(
+            info.corruption,
+            Some(RecordingCorruption {
+                offset: second_record as u64,
+                reason: "Unknown record kind",
+            })
+        );
+
+        let info = validate_recording(Cursor::new(&recording[..second_record + 6])).unwrap();
+
+        assert_eq!(info.depth_packets, 1);
+        assert_eq!(info.corruption.unwrap().offset, second_record as u64);
+    }
+
+    #[tokio::test]
+    async fn record_length_is_checked() {
+        let color_record = 10 + 13 + DEPTH_PACKET_SIZE;
+        // length prefix of the first depth record after the kind and 2 words, then of the first color record
+        let cases = [
+            (
+                10,
+                10 + 1 + 8,
+                16,
+                "Depth record data not of the packet size",
+            ),
+            (
+                10,
+                10 + 1 + 8,
+                u32::MAX,
+                "Depth record data not of the packet size",
+            ),
+            (
+                color_record,
+                color_record + 1 + 20,
+                u32::MAX,
+                "Record data longer than a packet",
+            ),
+        ];
+
+        for (offset, length, value, reason) in cases {
+            let mut recording = recording();
+
+            recording[length..length + 4].copy_from_slice(&value.to_le_bytes());
+
+            let mut player = Player::new(Cursor::new(&recording)).unwrap();
+            let result = match offset {
+                10 => player.poll_depth_packet().await.map(|_| ()),
+                _ => player.poll_color_packet().await.map(|_| ()),
+            };
+
+            assert!(matches!(result, Err(Error::InvalidRecording(error)) if error == reason));
+            assert_eq!(
+                validate_recording(Cursor::new(&recording))
+                    .unwrap()
+                    .corruption,
+                Some(RecordingCorruption {
+                    offset: offset as u64,
+                    reason,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn validate_checks_header() {
+        let mut recording = recording();
+
+        recording[8] = 2;
+
+        assert!(matches!(
+            validate_recording(Cursor::new(&recording)),
+            Err(Error::RecordingVersion(2))
+        ));
+
+        recording[0] = b'X';
+
+        assert!(matches!(
+            validate_recording(Cursor::new(&recording)),
+            Err(Error::InvalidRecording(_))
+        ));
+    }
+}
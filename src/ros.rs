@@ -0,0 +1,427 @@
+//! ROS 2 `sensor_msgs` compatible messages.
+//!
+//! The messages mirror the fields of `sensor_msgs/msg/Image` and `sensor_msgs/msg/CameraInfo`,
+//! and can be serialized to CDR, the wire format used by ROS 2,
+//! so frames can be bridged into ROS without depending on a ROS client library.
+
+use std::array;
+
+use crate::{
+    data::{ColorParams, IrParams, Matrix3, Matrix3x4},
+    processor::{
+        color::{ColorFrame, ColorSpace},
+        depth::DepthFrame,
+    },
+    COLOR_HEIGHT, COLOR_WIDTH, DEPTH_HEIGHT, DEPTH_WIDTH,
+};
+
+/// `std_msgs/msg/Header`
+#[derive(Debug, Default, Clone)]
+pub struct Header {
+    pub sec: i32,
+    pub nanosec: u32,
+    pub frame_id: String,
+}
+
+/// Encoding of a depth image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthEncoding {
+    /// 32 bits float in meters
+    Float32,
+    /// 16 bits unsigned integer in millimeters
+    Uint16,
+}
+
+/// `sensor_msgs/msg/Image`
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub encoding: &'static str,
+    pub is_bigendian: u8,
+    /// Row length in bytes
+    pub step: u32,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Convert a depth frame, invalid pixels are set to zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Depth frame in millimeters
+    pub fn from_depth(header: Header, frame: &DepthFrame, encoding: DepthEncoding) -> Self {
+        let (encoding, bytes_per_pixel, data) = match encoding {
+            DepthEncoding::Float32 => (
+                "32FC1",
+                4,
+                frame
+                    .buffer
+                    .iter()
+                    .flat_map(|depth| {
+                        let depth = if depth.is_finite() {
+                            depth / 1000.0
+                        } else {
+                            0.0
+                        };
+
+                        depth.to_le_bytes()
+                    })
+                    .collect(),
+            ),
+            DepthEncoding::Uint16 => (
+                "16UC1",
+                2,
+                frame
+                    .buffer
+                    .iter()
+                    .flat_map(|depth| {
+                        let depth = if depth.is_finite() {
+                            depth.round().clamp(0.0, u16::MAX as f32) as u16
+                        } else {
+                            0
+                        };
+
+                        depth.to_le_bytes()
+                    })
+                    .collect(),
+            ),
+        };
+
+        Self {
+            header,
+            height: frame.height as u32,
+            width: frame.width as u32,
+            encoding,
+            is_bigendian: 0,
+            step: (frame.width * bytes_per_pixel) as u32,
+            data,
+        }
+    }
+
+    /// Convert a color frame to `rgb8`.
+    ///
+    /// Returns `None` when the color space isn't an RGB variant.
+    pub fn from_color(header: Header, frame: &ColorFrame) -> Option<Self> {
+        let swap = match frame.color_space {
+            ColorSpace::RGB | ColorSpace::RGBA | ColorSpace::RGBX => false,
+            ColorSpace::BGR | ColorSpace::BGRA | ColorSpace::BGRX => true,
+            ColorSpace::YCbCr | ColorSpace::Unknown => return None,
+        };
+        let data = frame
+            .pixels()
+            .flat_map(|pixel| match swap {
+                true => [pixel[2], pixel[1], pixel[0]],
+                false => [pixel[0], pixel[1], pixel[2]],
+            })
+            .collect();
+
+        Some(Self {
+            header,
+            height: frame.height as u32,
+            width: frame.width as u32,
+            encoding: "rgb8",
+            is_bigendian: 0,
+            step: (frame.width * 3) as u32,
+            data,
+        })
+    }
+
+    /// Serialize to little endian CDR, including the encapsulation header.
+    pub fn to_cdr(&self) -> Vec<u8> {
+        let mut writer = CdrWriter::with_capacity(self.data.len() + 64);
+
+        writer.header(&self.header);
+        writer.u32(self.height);
+        writer.u32(self.width);
+        writer.string(self.encoding);
+        writer.u8(self.is_bigendian);
+        writer.u32(self.step);
+        writer.u32(self.data.len() as u32);
+        writer.bytes(&self.data);
+
+        writer.buffer
+    }
+}
+
+/// `sensor_msgs/msg/RegionOfInterest`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RegionOfInterest {
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub height: u32,
+    pub width: u32,
+    pub do_rectify: bool,
+}
+
+/// `sensor_msgs/msg/CameraInfo`
+#[derive(Debug, Clone)]
+pub struct CameraInfo {
+    pub header: Header,
+    pub height: u32,
+    pub width: u32,
+    pub distortion_model: &'static str,
+    /// Distortion coefficients
+    pub d: Vec<f64>,
+    /// Intrinsic camera matrix, row major
+    pub k: [f64; 9],
+    /// Rectification matrix, row major
+    pub r: [f64; 9],
+    /// Projection matrix, row major
+    pub p: [f64; 12],
+    pub binning_x: u32,
+    pub binning_y: u32,
+    pub roi: RegionOfInterest,
+}
+
+impl CameraInfo {
+    fn new(
+        header: Header,
+        width: usize,
+        height: usize,
+        (k, d): (Matrix3, [f32; 5]),
+        p: Matrix3x4,
+    ) -> Self {
+        let (k, p) = (k.as_flattened(), p.as_flattened());
+
+        Self {
+            header,
+            height: height as u32,
+            width: width as u32,
+            distortion_model: "plumb_bob",
+            d: d.map(|value| value as f64).to_vec(),
+            k: array::from_fn(|index| k[index] as f64),
+            r: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            p: array::from_fn(|index| p[index] as f64),
+            binning_x: 0,
+            binning_y: 0,
+            roi: RegionOfInterest::default(),
+        }
+    }
+
+    /// Camera info of the depth and IR images.
+    pub fn from_ir_params(header: Header, ir_params: &IrParams) -> Self {
+        Self::new(
+            header,
+            DEPTH_WIDTH,
+            DEPTH_HEIGHT,
+            ir_params.opencv_params(),
+            ir_params.projection_matrix(),
+        )
+    }
+
+    /// Camera info of the color images.
+    pub fn from_color_params(header: Header, color_params: &ColorParams) -> Self {
+        Self::new(
+            header,
+            COLOR_WIDTH,
+            COLOR_HEIGHT,
+            color_params.opencv_params(),
+            color_params.projection_matrix(),
+        )
+    }
+
+    /// Serialize to little endian CDR, including the encapsulation header.
+    pub fn to_cdr(&self) -> Vec<u8> {
+        let mut writer = CdrWriter::with_capacity(512);
+
+        writer.header(&self.header);
+        writer.u32(self.height);
+        writer.u32(self.width);
+        writer.string(self.distortion_model);
+        writer.u32(self.d.len() as u32);
+        self.d.iter().for_each(|value| writer.f64(*value));
+        self.k.iter().for_each(|value| writer.f64(*value));
+        self.r.iter().for_each(|value| writer.f64(*value));
+        self.p.iter().for_each(|value| writer.f64(*value));
+        writer.u32(self.binning_x);
+        writer.u32(self.binning_y);
+        writer.u32(self.roi.x_offset);
+        writer.u32(self.roi.y_offset);
+        writer.u32(self.roi.height);
+        writer.u32(self.roi.width);
+        writer.u8(self.roi.do_rectify as u8);
+
+        writer.buffer
+    }
+}
+
+/// Little endian CDR writer.
+struct CdrWriter {
+    buffer: Vec<u8>,
+}
+
+impl CdrWriter {
+    /// Size of the encapsulation header, alignment is relative to its end
+    const ENCAPSULATION_SIZE: usize = 4;
+
+    fn with_capacity(capacity: usize) -> Self {
+        let mut buffer = Vec::with_capacity(capacity + Self::ENCAPSULATION_SIZE);
+
+        // CDR_LE representation identifier, and options
+        buffer.extend_from_slice(&[0x00, 0x01, 0x00, 0x00]);
+
+        Self { buffer }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        let offset = self.buffer.len() - Self::ENCAPSULATION_SIZE;
+        let padding = (alignment - offset % alignment) % alignment;
+
+        self.buffer.resize(self.buffer.len() + padding, 0);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.align(4);
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.align(8);
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.buffer.extend_from_slice(value);
+    }
+
+    /// Strings are null terminated, and their length includes the terminator.
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32 + 1);
+        self.bytes(value.as_bytes());
+        self.u8(0);
+    }
+
+    fn header(&mut self, header: &Header) {
+        self.i32(header.sec);
+        self.u32(header.nanosec);
+        self.string(&header.frame_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Header {
+        Header {
+            sec: 1,
+            nanosec: 2,
+            frame_id: "kinect".to_string(),
+        }
+    }
+
+    #[test]
+    fn image_cdr() {
+        let frame = DepthFrame {
+            width: 2,
+            height: 1,
+            buffer: vec![1000.4, f32::NAN],
+            sequence: 0,
+            timestamp: 0,
+        };
+        let image = Image::from_depth(header(), &frame, DepthEncoding::Uint16);
+
+        #[rustfmt::skip]
+        let expected = [
+            0x00, 0x01, 0x00, 0x00, // encapsulation
+            1, 0, 0, 0, // sec
+            2, 0, 0, 0, // nanosec
+            7, 0, 0, 0, b'k', b'i', b'n', b'e', b'c', b't', 0, // frame_id
+            0, // padding
+            1, 0, 0, 0, // height
+            2, 0, 0, 0, // width
+            6, 0, 0, 0, b'1', b'6', b'U', b'C', b'1', 0, // encoding
+            0, // is_bigendian
+            0, // padding
+            4, 0, 0, 0, // step
+            4, 0, 0, 0, 0xe8, 0x03, 0x00, 0x00, // data
+        ];
+
+        assert_eq!(image.to_cdr(), expected);
+
+        let image = Image::from_depth(header(), &frame, DepthEncoding::Float32);
+
+        assert_eq!((image.encoding, image.step), ("32FC1", 8));
+        assert_eq!(image.data[..4], (1000.4f32 / 1000.0).to_le_bytes());
+        assert_eq!(image.data[4..], [0; 4]);
+    }
+
+    #[test]
+    fn color_image_is_packed() {
+        let frame = ColorFrame {
+            color_space: ColorSpace::BGRX,
+            width: 1,
+            height: 2,
+            stride: 4,
+            buffer: vec![1, 2, 3, 0, 4, 5, 6, 0],
+            sequence: 0,
+            timestamp: 0,
+            exposure: 0.0,
+            gain: 0.0,
+            gamma: 0.0,
+        }
+        .with_row_alignment(16)
+        .unwrap();
+        let image = Image::from_color(header(), &frame).unwrap();
+
+        assert_eq!((image.encoding, image.step), ("rgb8", 3));
+        assert_eq!(image.data, [3, 2, 1, 6, 5, 4]);
+    }
+
+    #[test]
+    fn camera_info_cdr() {
+        let info = CameraInfo {
+            header: Header::default(),
+            height: 1,
+            width: 2,
+            distortion_model: "plumb_bob",
+            d: vec![0.5],
+            k: array::from_fn(|index| index as f64),
+            r: array::from_fn(|index| index as f64 + 10.0),
+            p: array::from_fn(|index| index as f64 + 20.0),
+            binning_x: 3,
+            binning_y: 4,
+            roi: RegionOfInterest {
+                x_offset: 5,
+                y_offset: 6,
+                height: 7,
+                width: 8,
+                do_rectify: true,
+            },
+        };
+        let mut expected = vec![0x00, 0x01, 0x00, 0x00];
+
+        expected.extend([0; 8]); // sec, nanosec
+        expected.extend([1, 0, 0, 0, 0]); // empty frame_id
+        expected.extend([0; 3]); // padding
+        expected.extend([1, 0, 0, 0, 2, 0, 0, 0]); // height, width
+        expected.extend([10, 0, 0, 0]);
+        expected.extend(b"plumb_bob\0");
+        expected.extend([0; 2]); // padding
+        expected.extend([1, 0, 0, 0]); // d length
+        expected.extend([0; 4]); // padding
+        expected.extend(0.5f64.to_le_bytes());
+
+        for value in (0..9).chain(10..19).chain(20..32) {
+            expected.extend((value as f64).to_le_bytes());
+        }
+        for value in [3u32, 4, 5, 6, 7, 8] {
+            expected.extend(value.to_le_bytes());
+        }
+
+        expected.push(1); // do_rectify
+
+        assert_eq!(info.to_cdr(), expected);
+    }
+}
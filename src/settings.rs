@@ -84,6 +84,92 @@ pub enum ColorSettingCommandType {
     GetFrameRate = 83,
 }
 
+impl ColorSettingCommandType {
+    /// Valid value range of a float setting, `None` if the range is unknown or the value isn't a float.
+    pub const fn range(&self) -> Option<(f32, f32)> {
+        match self {
+            Self::SetExposureCompensation | Self::GetExposureCompensation => Some((-2.0, 2.0)),
+            Self::SetIntegrationTime
+            | Self::GetIntegrationTime
+            | Self::SetExposureTimeMs
+            | Self::GetExposureTimeMs => Some((0.0, 66.0)),
+            Self::SetAnalogGain | Self::GetAnalogGain => Some((1.0, 4.0)),
+            Self::SetReChannelGain
+            | Self::SetGreenChannelGain
+            | Self::SetBlueChannelGain
+            | Self::GetRedChannelGain
+            | Self::GetGreenChannelGain
+            | Self::GetBlueChannelGain => Some((1.0, 4.0)),
+            _ => None,
+        }
+    }
+
+    /// Whether the value of the setting is an `f32` sent as its bits, see [`f32::to_bits`].
+    ///
+    /// Times (ms), gains, gain caps and exposure compensation are floats, modes, ACS,
+    /// metering zones and weights, flicker free frequency and frame rate are integers.
+    pub const fn is_float(&self) -> bool {
+        matches!(
+            self,
+            Self::SetIntegrationTime
+                | Self::GetIntegrationTime
+                | Self::SetReChannelGain
+                | Self::SetGreenChannelGain
+                | Self::SetBlueChannelGain
+                | Self::GetRedChannelGain
+                | Self::GetGreenChannelGain
+                | Self::GetBlueChannelGain
+                | Self::SetExposureTimeMs
+                | Self::GetExposureTimeMs
+                | Self::SetDigitalGain
+                | Self::GetDigitalGain
+                | Self::SetAnalogGain
+                | Self::GetAnalogGain
+                | Self::SetExposureCompensation
+                | Self::GetExposureCompensation
+                | Self::SetMaxAnalogGainCap
+                | Self::SetMaxDigitalGainCap
+        )
+    }
+
+    /// Clamp a value to the valid range of the setting, unchanged if the range is unknown.
+    pub fn clamp(&self, value: f32) -> f32 {
+        match self.range() {
+            Some((min, max)) => value.clamp(min, max),
+            None => value,
+        }
+    }
+}
+
+/// White balance mode of the color camera, the values follow the exposure modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhiteBalanceMode {
+    Auto,
+    Manual,
+    /// Mode value not known to this crate.
+    Other(u32),
+}
+
+impl From<u32> for WhiteBalanceMode {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::Auto,
+            4 => Self::Manual,
+            value => Self::Other(value),
+        }
+    }
+}
+
+impl From<WhiteBalanceMode> for u32 {
+    fn from(value: WhiteBalanceMode) -> Self {
+        match value {
+            WhiteBalanceMode::Auto => 0,
+            WhiteBalanceMode::Manual => 4,
+            WhiteBalanceMode::Other(value) => value,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u16)]
 pub enum LedId {
@@ -115,13 +201,25 @@ pub struct LedSettings {
 }
 
 impl LedSettings {
+    // LED states of the start and stop sequences, libfreenect2 sends them as the undocumented
+    // "set mode" command, `(true, 0x00640064)` being the secondary LED at 100 / 1000.
+    pub(crate) const SECONDARY_DIM: Self = Self {
+        id: LedId::Secondary,
+        mode: LedMode::Constant,
+        start_level: 100,
+        stop_level: 100,
+        interval: Duration::from_secs(0),
+    };
+    pub(crate) const PRIMARY_OFF: Self = Self::off(LedId::Primary);
+    pub(crate) const SECONDARY_OFF: Self = Self::off(LedId::Secondary);
+
     /// Constant mode
     ///
     /// # Arguments
     ///
     /// * `id` - LED id
     /// * `level` - LED intensity [0, 1000]
-    pub fn constant(id: LedId, level: u16) -> Self {
+    pub const fn constant(id: LedId, level: u16) -> Self {
         Self {
             id,
             mode: LedMode::Constant,
@@ -149,6 +247,24 @@ impl LedSettings {
         }
     }
 
+    /// Pulse between full and dim intensity.
+    ///
+    /// The firmware switches between the levels without fading,
+    /// each level is held for half the period.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - LED id
+    /// * `period` - Duration of a full on and off cycle
+    pub fn pulse(id: LedId, period: Duration) -> Self {
+        Self::blink(id, 1000, 100, period / 2)
+    }
+
+    /// Turn the LED off.
+    pub const fn off(id: LedId) -> Self {
+        Self::constant(id, 0)
+    }
+
     pub fn id(&self) -> LedId {
         self.id
     }
@@ -172,6 +288,11 @@ impl LedSettings {
 
 #[derive(Debug, Clone, Copy)]
 /// Parameters of depth processing.
+///
+/// The defaults are the libfreenect2 constants and are used for every unit, the calibration
+/// read from the device has no per-unit phase or frequency data: the depth parameters only
+/// hold the intrinsics and distortion, and the per-pixel phase offsets of each frequency
+/// are the [`P0Tables`](crate::data::P0Tables), which the processors already apply.
 pub struct DepthProcessorParams {
     pub ab_multiplier: f32,
     pub ab_multiplier_per_frq: [f32; 3],
@@ -267,6 +388,8 @@ impl Default for DepthProcessorParams {
     }
 }
 
+/// USB transfer sizes and counts of the color and depth streams,
+/// the default depends on the OS.
 #[derive(Debug, Clone, Copy)]
 pub struct PacketParams {
     pub max_iso_packet_size: u16,